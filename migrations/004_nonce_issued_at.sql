@@ -0,0 +1,3 @@
+-- Track when a sign-in nonce was issued so stale nonces can be rejected
+
+ALTER TABLE users ADD COLUMN nonce_issued_at DATETIME;
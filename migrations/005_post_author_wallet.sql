@@ -0,0 +1,6 @@
+-- Track which wallet owns a post so writers can only edit their own.
+-- Posts loaded from markdown files have no owner and are admin-only.
+
+ALTER TABLE posts ADD COLUMN author_wallet TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_posts_author_wallet ON posts(author_wallet);
@@ -0,0 +1,15 @@
+-- Accounts group the wallets (rows in users) that belong to one person, so a
+-- reader using both MetaMask and Phantom is counted once.
+
+CREATE TABLE IF NOT EXISTS accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+ALTER TABLE users ADD COLUMN account_id INTEGER REFERENCES accounts(id);
+
+-- Every existing wallet starts out as its own account
+INSERT INTO accounts (id, created_at) SELECT id, created_at FROM users;
+UPDATE users SET account_id = id;
+
+CREATE INDEX IF NOT EXISTS idx_users_account ON users(account_id);
@@ -0,0 +1,5 @@
+-- Scheduled publishing: a published post stays hidden until publish_at
+
+ALTER TABLE posts ADD COLUMN publish_at DATETIME;
+
+CREATE INDEX IF NOT EXISTS idx_posts_publish_at ON posts(published, publish_at);
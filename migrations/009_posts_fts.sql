@@ -0,0 +1,30 @@
+-- Full-text search index over posts, kept in sync by triggers
+
+CREATE VIRTUAL TABLE IF NOT EXISTS posts_fts USING fts5(
+    title,
+    description,
+    content,
+    tags,
+    content = 'posts',
+    content_rowid = 'id',
+    tokenize = 'porter unicode61'
+);
+
+CREATE TRIGGER IF NOT EXISTS posts_fts_insert AFTER INSERT ON posts BEGIN
+    INSERT INTO posts_fts (rowid, title, description, content, tags)
+    VALUES (new.id, new.title, new.description, new.content, new.tags);
+END;
+
+CREATE TRIGGER IF NOT EXISTS posts_fts_delete AFTER DELETE ON posts BEGIN
+    INSERT INTO posts_fts (posts_fts, rowid, title, description, content, tags)
+    VALUES ('delete', old.id, old.title, old.description, old.content, old.tags);
+END;
+
+CREATE TRIGGER IF NOT EXISTS posts_fts_update AFTER UPDATE OF title, description, content, tags ON posts BEGIN
+    INSERT INTO posts_fts (posts_fts, rowid, title, description, content, tags)
+    VALUES ('delete', old.id, old.title, old.description, old.content, old.tags);
+    INSERT INTO posts_fts (rowid, title, description, content, tags)
+    VALUES (new.id, new.title, new.description, new.content, new.tags);
+END;
+
+INSERT INTO posts_fts (posts_fts) VALUES ('rebuild');
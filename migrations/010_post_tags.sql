@@ -0,0 +1,37 @@
+-- Tags as rows instead of the comma-joined posts.tags string, which is kept
+-- as the authored list for display and the search index.
+CREATE TABLE IF NOT EXISTS tags (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE IF NOT EXISTS post_tags (
+    post_id INTEGER NOT NULL REFERENCES posts(id) ON DELETE CASCADE,
+    tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+    PRIMARY KEY (post_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_post_tags_tag ON post_tags(tag_id);
+
+-- Backfill from the existing tag strings
+WITH RECURSIVE split(post_id, tag, rest) AS (
+    SELECT id, '', tags || ',' FROM posts
+    UNION ALL
+    SELECT post_id,
+           lower(trim(substr(rest, 1, instr(rest, ',') - 1))),
+           substr(rest, instr(rest, ',') + 1)
+    FROM split WHERE rest <> ''
+)
+INSERT OR IGNORE INTO tags (name)
+SELECT DISTINCT tag FROM split WHERE tag <> '';
+
+WITH RECURSIVE split(post_id, tag, rest) AS (
+    SELECT id, '', tags || ',' FROM posts
+    UNION ALL
+    SELECT post_id,
+           lower(trim(substr(rest, 1, instr(rest, ',') - 1))),
+           substr(rest, instr(rest, ',') + 1)
+    FROM split WHERE rest <> ''
+)
+INSERT OR IGNORE INTO post_tags (post_id, tag_id)
+SELECT split.post_id, tags.id FROM split JOIN tags ON tags.name = split.tag;
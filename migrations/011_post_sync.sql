@@ -0,0 +1,5 @@
+-- What posts/<slug>.md and the post looked like when they were last in sync,
+-- so a sync can tell which side changed since.
+ALTER TABLE posts ADD COLUMN file_hash TEXT;
+ALTER TABLE posts ADD COLUMN synced_hash TEXT;
+ALTER TABLE posts ADD COLUMN synced_at DATETIME;
@@ -0,0 +1,3 @@
+-- Headings of the rendered post (JSON), for the table of contents.
+-- Filled in when posts are re-rendered on startup.
+ALTER TABLE posts ADD COLUMN toc TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,156 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::json;
+use sha2::Sha256;
+use std::sync::Arc;
+
+use crate::{db, models::Session, AppState};
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// Pull the session token from the `session` cookie, falling back to an
+/// `Authorization: Bearer` header for non-browser clients.
+pub fn session_token(headers: &HeaderMap) -> Option<String> {
+    let from_cookie = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, value)| value.to_string());
+
+    from_cookie
+        .or_else(|| {
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.strip_prefix("Bearer "))
+                .map(|t| t.trim().to_string())
+        })
+        .filter(|t| !t.is_empty())
+}
+
+pub fn session_cookie(token: &str, max_age_secs: i64, secure: bool) -> String {
+    let mut cookie = format!(
+        "{}={}; Path=/; HttpOnly; SameSite=Lax; Max-Age={}",
+        SESSION_COOKIE, token, max_age_secs
+    );
+    if secure {
+        cookie.push_str("; Secure");
+    }
+    cookie
+}
+
+pub fn clear_session_cookie(secure: bool) -> String {
+    session_cookie("", 0, secure)
+}
+
+fn preview_mac(secret: &[u8], slug: &str, expires: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(format!("preview\n{}\n{}", slug, expires).as_bytes());
+    mac
+}
+
+/// Signature for a draft preview link to `slug` that is valid until `expires`.
+pub fn sign_preview(secret: &[u8], slug: &str, expires: DateTime<Utc>) -> String {
+    hex::encode(
+        preview_mac(secret, slug, expires.timestamp())
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Check a preview link signature (in constant time) and its expiry.
+pub fn verify_preview(secret: &[u8], slug: &str, expires: i64, signature: &str) -> bool {
+    if expires < Utc::now().timestamp() {
+        return false;
+    }
+    match hex::decode(signature) {
+        Ok(bytes) => preview_mac(secret, slug, expires).verify_slice(&bytes).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Why a request was refused by one of the session extractors.
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthenticated,
+    Forbidden,
+    Internal,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            AuthError::Unauthenticated => (StatusCode::UNAUTHORIZED, "Sign in required"),
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, "Unauthorized"),
+            AuthError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error"),
+        };
+        (status, Json(json!({ "success": false, "error": error }))).into_response()
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Session {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = session_token(&parts.headers).ok_or(AuthError::Unauthenticated)?;
+
+        db::get_session_by_token(&state.db, &token)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to load session: {}", e);
+                AuthError::Internal
+            })?
+            .ok_or(AuthError::Unauthenticated)
+    }
+}
+
+/// A session whose user has the `admin` role.
+pub struct AdminSession(pub Session);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AdminSession {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state).await?;
+        if !session.is_admin() {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(AdminSession(session))
+    }
+}
+
+/// A session whose user may write posts (`admin` or `writer`).
+pub struct WriterSession(pub Session);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for WriterSession {
+    type Rejection = AuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state).await?;
+        if !session.can_write() {
+            return Err(AuthError::Forbidden);
+        }
+        Ok(WriterSession(session))
+    }
+}
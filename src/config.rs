@@ -1,16 +1,53 @@
 use std::env;
 
+use crate::{
+    markdown::{highlight, HtmlPolicy},
+    sync::SyncMode,
+};
+
 #[derive(Clone)]
 pub struct Config {
     pub database_url: String,
     pub blog_title: String,
     pub blog_description: String,
+    pub base_url: String,
     pub author_eth_address: String,
     pub author_avax_address: String,
     pub author_sol_address: String,
     pub author_btc_address: String,
     pub eth_rpc_url: String,
     pub sol_rpc_url: String,
+    pub eth_chain_id: u64,
+    pub sol_cluster: String,
+    pub admin_wallets: Vec<String>,
+    pub session_ttl_hours: i64,
+    pub session_cookie_secure: bool,
+    pub preview_secret: Vec<u8>,
+    pub preview_ttl_hours: i64,
+    /// When false, robots.txt asks crawlers to stay away entirely (staging)
+    pub allow_indexing: bool,
+    pub robots_disallow: Vec<String>,
+    /// Reload posts when files in `posts/` change
+    pub watch_posts: bool,
+    /// How long a file in `posts/` must be quiet before its changes are synced
+    pub watch_debounce_ms: u64,
+    /// Who wins when a post changed both in `posts/` and in the database
+    pub sync_mode: SyncMode,
+    /// What raw HTML in posts may contain (iframe hosts, URL schemes)
+    pub html_policy: HtmlPolicy,
+    /// Show a `#` link next to headings in posts
+    pub heading_anchors: bool,
+    /// Syntect theme names (or `.tmTheme` paths) for code in light/dark mode
+    pub code_theme_light: String,
+    pub code_theme_dark: String,
+    /// Number the lines of code blocks unless a fence says `{nolinenos}`
+    pub code_line_numbers: bool,
+    /// Extra `.sublime-syntax` grammars for code blocks
+    pub syntax_dir: String,
+    /// Fence tokens mapped to a syntax name or extension, e.g. `sol` -> `Solidity`
+    pub syntax_aliases: Vec<(String, String)>,
+    /// How long rendered pages are cached; 0 turns the cache off
+    pub page_cache_ttl_secs: u64,
 }
 
 impl Config {
@@ -21,6 +58,9 @@ impl Config {
             blog_title: env::var("BLOG_TITLE").unwrap_or_else(|_| "My Web3 Blog".to_string()),
             blog_description: env::var("BLOG_DESCRIPTION")
                 .unwrap_or_else(|_| "A decentralized blog with crypto tipping".to_string()),
+            base_url: env::var("BASE_URL")
+                .map(|v| v.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
             author_eth_address: env::var("AUTHOR_ETH_ADDRESS").unwrap_or_default(),
             author_avax_address: env::var("AUTHOR_AVAX_ADDRESS").unwrap_or_default(),
             author_sol_address: env::var("AUTHOR_SOL_ADDRESS").unwrap_or_default(),
@@ -29,6 +69,125 @@ impl Config {
                 .unwrap_or_else(|_| "https://eth.llamarpc.com".to_string()),
             sol_rpc_url: env::var("SOL_RPC_URL")
                 .unwrap_or_else(|_| "https://api.mainnet-beta.solana.com".to_string()),
+            eth_chain_id: env::var("ETH_CHAIN_ID")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(1),
+            sol_cluster: env::var("SOL_CLUSTER").unwrap_or_else(|_| "mainnet".to_string()),
+            admin_wallets: env::var("ADMIN_WALLETS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|w| w.trim().to_string())
+                .filter(|w| !w.is_empty())
+                .collect(),
+            session_ttl_hours: env::var("SESSION_TTL_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(24 * 7),
+            session_cookie_secure: env::var("SESSION_COOKIE_SECURE")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            preview_secret: env::var("PREVIEW_SECRET")
+                .ok()
+                .filter(|v| !v.is_empty())
+                .map(String::into_bytes)
+                .unwrap_or_else(|| {
+                    tracing::warn!("PREVIEW_SECRET not set; preview links will stop working on restart");
+                    rand::random::<[u8; 32]>().to_vec()
+                }),
+            preview_ttl_hours: env::var("PREVIEW_TTL_HOURS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(72),
+            allow_indexing: env::var("ALLOW_INDEXING")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            robots_disallow: env::var("ROBOTS_DISALLOW")
+                .unwrap_or_else(|_| "/admin/,/api/,/preview/".to_string())
+                .split(',')
+                .map(|p| p.trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+            watch_posts: env::var("WATCH_POSTS")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            watch_debounce_ms: env::var("WATCH_DEBOUNCE_MS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(500),
+            sync_mode: env::var("SYNC_MODE")
+                .ok()
+                .and_then(|v| {
+                    v.parse()
+                        .map_err(|e| tracing::warn!("Ignoring SYNC_MODE: {}", e))
+                        .ok()
+                })
+                .unwrap_or(SyncMode::Files),
+            html_policy: {
+                let defaults = HtmlPolicy::default();
+                HtmlPolicy {
+                    iframe_hosts: env::var("IFRAME_HOSTS")
+                        .map(|v| split_list(&v))
+                        .unwrap_or(defaults.iframe_hosts),
+                    url_schemes: env::var("URL_SCHEMES")
+                        .map(|v| split_list(&v))
+                        .unwrap_or(defaults.url_schemes),
+                }
+            },
+            heading_anchors: env::var("HEADING_ANCHORS")
+                .map(|v| v != "false" && v != "0")
+                .unwrap_or(true),
+            code_theme_light: env::var("CODE_THEME_LIGHT")
+                .unwrap_or_else(|_| highlight::DEFAULT_LIGHT_THEME.to_string()),
+            code_theme_dark: env::var("CODE_THEME_DARK")
+                .unwrap_or_else(|_| highlight::DEFAULT_DARK_THEME.to_string()),
+            code_line_numbers: env::var("CODE_LINE_NUMBERS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            syntax_dir: env::var("SYNTAX_DIR").unwrap_or_else(|_| "syntaxes".to_string()),
+            syntax_aliases: env::var("SYNTAX_ALIASES")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|pair| {
+                    let (token, syntax) = pair.split_once('=')?;
+                    Some((token.trim().to_lowercase(), syntax.trim().to_string()))
+                })
+                .filter(|(token, syntax)| !token.is_empty() && !syntax.is_empty())
+                .collect(),
+            page_cache_ttl_secs: env::var("PAGE_CACHE_TTL_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(300),
         }
     }
+
+    /// Wallets that are promoted to admin on their first sign-in, or when no
+    /// admin is left, so the first admin can be bootstrapped without touching
+    /// the database.
+    pub fn is_bootstrap_admin(&self, wallet_address: &str) -> bool {
+        self.admin_wallets
+            .iter()
+            .any(|w| w.eq_ignore_ascii_case(wallet_address))
+    }
+
+    /// Host (and port, if any) of `base_url`, used as the sign-in message domain.
+    pub fn domain(&self) -> String {
+        url::Url::parse(&self.base_url)
+            .ok()
+            .and_then(|u| {
+                u.host_str().map(|h| match u.port() {
+                    Some(port) => format!("{}:{}", h, port),
+                    None => h.to_string(),
+                })
+            })
+            .unwrap_or_else(|| "localhost:3000".to_string())
+    }
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect()
 }
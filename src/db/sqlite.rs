@@ -1,17 +1,84 @@
 use anyhow::Result;
-use chrono::Utc;
-use sqlx::SqlitePool;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use sqlx::{types::Json, SqliteConnection, SqlitePool};
 
-use crate::models::{Post, Tip, User, UserActivity};
+use crate::markdown::Rendered;
+use crate::models::{
+    normalize_tag, parse_tags, CreateActivityRequest, Post, PostCursor, PostRevision, SearchQuery, Session,
+    TagCount, Tip, User, UserActivity, UserType,
+};
 
-pub async fn get_all_posts(pool: &SqlitePool) -> Result<Vec<Post>> {
+/// One page of published posts, newest first.
+pub async fn get_posts_page(pool: &SqlitePool, limit: i64, offset: i64) -> Result<Vec<Post>> {
     let posts = sqlx::query_as::<_, Post>(
         r#"
         SELECT * FROM posts
-        WHERE published = true
-        ORDER BY created_at DESC
+        WHERE published = true AND (publish_at IS NULL OR publish_at <= ?)
+        ORDER BY created_at DESC, id DESC
+        LIMIT ? OFFSET ?
         "#,
     )
+    .bind(Utc::now())
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(posts)
+}
+
+/// Every published post, newest first, for the sitemap.
+pub async fn get_all_published_posts(pool: &SqlitePool) -> Result<Vec<Post>> {
+    let posts = sqlx::query_as::<_, Post>(
+        r#"
+        SELECT * FROM posts
+        WHERE published = true AND (publish_at IS NULL OR publish_at <= ?)
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(Utc::now())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(posts)
+}
+
+pub async fn count_published_posts(pool: &SqlitePool) -> Result<i64> {
+    let count: (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM posts WHERE published = true AND (publish_at IS NULL OR publish_at <= ?)",
+    )
+    .bind(Utc::now())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count.0)
+}
+
+/// Published posts older than `cursor` (or the newest, without one), newest
+/// first. Keyed on `(created_at, id)` so pages stay stable while posts are
+/// added.
+pub async fn get_posts_before(
+    pool: &SqlitePool,
+    cursor: Option<&PostCursor>,
+    limit: i64,
+) -> Result<Vec<Post>> {
+    let posts = sqlx::query_as::<_, Post>(
+        r#"
+        SELECT * FROM posts
+        WHERE published = true AND (publish_at IS NULL OR publish_at <= ?)
+          AND (? IS NULL OR created_at < ? OR (created_at = ? AND id < ?))
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(Utc::now())
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(limit)
     .fetch_all(pool)
     .await?;
 
@@ -21,16 +88,142 @@ pub async fn get_all_posts(pool: &SqlitePool) -> Result<Vec<Post>> {
 pub async fn get_post_by_slug(pool: &SqlitePool, slug: &str) -> Result<Option<Post>> {
     let post = sqlx::query_as::<_, Post>(
         r#"
-        SELECT * FROM posts WHERE slug = ? AND published = true
+        SELECT * FROM posts
+        WHERE slug = ? AND published = true AND (publish_at IS NULL OR publish_at <= ?)
         "#,
     )
     .bind(slug)
+    .bind(Utc::now())
     .fetch_optional(pool)
     .await?;
 
     Ok(post)
 }
 
+/// Fetch a post regardless of its published state, for the editor.
+pub async fn get_any_post_by_slug(pool: &SqlitePool, slug: &str) -> Result<Option<Post>> {
+    let post = sqlx::query_as::<_, Post>("SELECT * FROM posts WHERE slug = ?")
+        .bind(slug)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(post)
+}
+
+/// Posts a user may manage: every post for admins, only their own for writers.
+pub async fn get_posts_for_editor(
+    pool: &SqlitePool,
+    author_wallet: Option<&str>,
+) -> Result<Vec<Post>> {
+    let posts = match author_wallet {
+        Some(wallet) => {
+            sqlx::query_as::<_, Post>(
+                r#"
+                SELECT * FROM posts
+                WHERE author_wallet = ?
+                   OR (author_wallet LIKE '0x%' AND lower(author_wallet) = lower(?))
+                ORDER BY created_at DESC
+                "#,
+            )
+            .bind(wallet)
+            .bind(wallet)
+            .fetch_all(pool)
+            .await?
+        }
+        None => {
+            sqlx::query_as::<_, Post>("SELECT * FROM posts ORDER BY created_at DESC")
+                .fetch_all(pool)
+                .await?
+        }
+    };
+
+    Ok(posts)
+}
+
+/// Marks placed around matched terms by `search_posts`. They are private-use
+/// code points so the text can be HTML-escaped before they become `<mark>`.
+pub const SEARCH_MATCH_START: char = '\u{E000}';
+pub const SEARCH_MATCH_END: char = '\u{E001}';
+
+/// A published post matching a search, best match first.
+#[derive(Debug, sqlx::FromRow)]
+pub struct SearchHit {
+    pub slug: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub tags: String,
+    pub created_at: DateTime<Utc>,
+    /// Title with matches wrapped in `SEARCH_MATCH_START`/`SEARCH_MATCH_END`
+    pub title_marked: String,
+    /// Best-matching excerpt, marked the same way
+    pub snippet_marked: String,
+    /// bm25 score; lower is better, 0 when only filters were given
+    pub score: f64,
+}
+
+/// Full-text search over published posts. Title matches weigh most, then
+/// tags, description and body. With no search terms, the tag and author
+/// filters alone select posts, newest first.
+pub async fn search_posts(
+    pool: &SqlitePool,
+    query: &SearchQuery,
+    limit: i64,
+) -> Result<Vec<SearchHit>> {
+    let mut builder = sqlx::QueryBuilder::<sqlx::Sqlite>::new("");
+    let fts_query = query.fts_query();
+
+    if let Some(fts_query) = &fts_query {
+        builder.push(
+            r#"
+            SELECT p.slug, p.title, p.description, p.author, p.tags, p.created_at,
+                highlight(posts_fts, 0, char(57344), char(57345)) AS title_marked,
+                snippet(posts_fts, -1, char(57344), char(57345), '…', 24) AS snippet_marked,
+                bm25(posts_fts, 10.0, 4.0, 1.0, 6.0) AS score
+            FROM posts_fts
+            JOIN posts p ON p.id = posts_fts.rowid
+            WHERE posts_fts MATCH "#,
+        );
+        builder.push_bind(fts_query.clone());
+    } else {
+        builder.push(
+            r#"
+            SELECT p.slug, p.title, p.description, p.author, p.tags, p.created_at,
+                p.title AS title_marked, p.description AS snippet_marked, 0.0 AS score
+            FROM posts p
+            WHERE 1 = 1"#,
+        );
+    }
+
+    builder.push(" AND p.published = true AND (p.publish_at IS NULL OR p.publish_at <= ");
+    builder.push_bind(Utc::now());
+    builder.push(")");
+
+    for tag in &query.tags {
+        builder.push(
+            " AND p.id IN (SELECT pt.post_id FROM post_tags pt JOIN tags t ON t.id = pt.tag_id WHERE t.name = ",
+        );
+        builder.push_bind(tag.clone());
+        builder.push(")");
+    }
+    if let Some(author) = &query.author {
+        builder.push(" AND lower(p.author) = lower(");
+        builder.push_bind(author.clone());
+        builder.push(")");
+    }
+
+    builder.push(if fts_query.is_some() {
+        " ORDER BY score LIMIT "
+    } else {
+        " ORDER BY p.created_at DESC LIMIT "
+    });
+    builder.push_bind(limit);
+
+    let hits = builder.build_query_as::<SearchHit>().fetch_all(pool).await?;
+
+    Ok(hits)
+}
+
 pub async fn increment_post_views(pool: &SqlitePool, slug: &str) -> Result<()> {
     sqlx::query("UPDATE posts SET views = views + 1 WHERE slug = ?")
         .bind(slug)
@@ -40,11 +233,51 @@ pub async fn increment_post_views(pool: &SqlitePool, slug: &str) -> Result<()> {
     Ok(())
 }
 
+/// View counts of the given live posts, by slug. Kept out of cached pages,
+/// which fetch them separately.
+pub async fn get_post_views(pool: &SqlitePool, slugs: &[&str]) -> Result<HashMap<String, i64>> {
+    if slugs.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut builder = sqlx::QueryBuilder::<sqlx::Sqlite>::new("SELECT slug, views FROM posts WHERE slug IN (");
+    let mut separated = builder.separated(", ");
+    for slug in slugs {
+        separated.push_bind(*slug);
+    }
+    builder.push(") AND published = true AND (publish_at IS NULL OR publish_at <= ");
+    builder.push_bind(Utc::now());
+    builder.push(")");
+
+    let views: Vec<(String, i64)> = builder.build_query_as().fetch_all(pool).await?;
+    Ok(views.into_iter().collect())
+}
+
+/// When a live post last changed (see [`Post::modified_at`]), without loading
+/// the post itself.
+pub async fn get_post_modified_at(pool: &SqlitePool, slug: &str) -> Result<Option<DateTime<Utc>>> {
+    type Dates = (DateTime<Utc>, DateTime<Utc>, Option<DateTime<Utc>>);
+    let dates: Option<Dates> = sqlx::query_as(
+        r#"
+        SELECT created_at, updated_at, publish_at FROM posts
+        WHERE slug = ? AND published = true AND (publish_at IS NULL OR publish_at <= ?)
+        "#,
+    )
+    .bind(slug)
+    .bind(Utc::now())
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(dates.map(|(created_at, updated_at, publish_at)| {
+        updated_at.max(created_at).max(publish_at.unwrap_or(created_at))
+    }))
+}
+
 pub async fn create_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
     let result = sqlx::query(
         r#"
-        INSERT INTO posts (slug, title, description, content, html_content, author, tags, published, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT INTO posts (slug, title, description, content, html_content, toc, author, tags, published, created_at, updated_at, author_wallet, publish_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(&post.slug)
@@ -52,15 +285,21 @@ pub async fn create_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
     .bind(&post.description)
     .bind(&post.content)
     .bind(&post.html_content)
+    .bind(&post.toc)
     .bind(&post.author)
     .bind(&post.tags)
     .bind(post.published)
     .bind(post.created_at)
     .bind(post.updated_at)
+    .bind(&post.author_wallet)
+    .bind(post.publish_at)
     .execute(pool)
     .await?;
 
-    Ok(result.last_insert_rowid())
+    let post_id = result.last_insert_rowid();
+    set_post_tags(pool, post_id, &post.tags).await?;
+
+    Ok(post_id)
 }
 
 pub async fn get_or_create_user(
@@ -83,18 +322,28 @@ pub async fn get_or_create_user(
     let nonce = uuid::Uuid::new_v4().to_string();
     let now = Utc::now();
 
+    // Every new wallet starts out as its own account
+    let mut tx = pool.begin().await?;
+    let account_id = sqlx::query("INSERT INTO accounts (created_at) VALUES (?)")
+        .bind(now)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+
     sqlx::query(
         r#"
-        INSERT INTO users (wallet_address, wallet_type, nonce, created_at)
-        VALUES (?, ?, ?, ?)
+        INSERT INTO users (wallet_address, wallet_type, nonce, created_at, account_id)
+        VALUES (?, ?, ?, ?, ?)
         "#,
     )
     .bind(wallet_address)
     .bind(wallet_type)
     .bind(&nonce)
     .bind(now)
-    .execute(pool)
+    .bind(account_id)
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
 
     let user = sqlx::query_as::<_, User>(
         "SELECT * FROM users WHERE wallet_address = ? AND wallet_type = ?",
@@ -108,8 +357,9 @@ pub async fn get_or_create_user(
 }
 
 pub async fn update_user_nonce(pool: &SqlitePool, user_id: i64, nonce: &str) -> Result<()> {
-    sqlx::query("UPDATE users SET nonce = ? WHERE id = ?")
+    sqlx::query("UPDATE users SET nonce = ?, nonce_issued_at = ? WHERE id = ?")
         .bind(nonce)
+        .bind(Utc::now())
         .bind(user_id)
         .execute(pool)
         .await?;
@@ -117,6 +367,21 @@ pub async fn update_user_nonce(pool: &SqlitePool, user_id: i64, nonce: &str) ->
     Ok(())
 }
 
+/// Invalidate a nonce after a successful sign-in. Returns false if the nonce
+/// was already consumed or replaced, so a signature can only be used once.
+pub async fn consume_user_nonce(pool: &SqlitePool, user_id: i64, nonce: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET nonce = ?, nonce_issued_at = NULL WHERE id = ? AND nonce = ?",
+    )
+    .bind(uuid::Uuid::new_v4().to_string())
+    .bind(user_id)
+    .bind(nonce)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
 pub async fn update_user_login(pool: &SqlitePool, user_id: i64) -> Result<()> {
     sqlx::query("UPDATE users SET last_login = ? WHERE id = ?")
         .bind(Utc::now())
@@ -127,6 +392,171 @@ pub async fn update_user_login(pool: &SqlitePool, user_id: i64) -> Result<()> {
     Ok(())
 }
 
+pub async fn create_session(
+    pool: &SqlitePool,
+    user_id: i64,
+    token: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<i64> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO sessions (user_id, token, expires_at, created_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(user_id)
+    .bind(token)
+    .bind(expires_at)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Look up a session by token. Expired sessions are treated as missing. The
+/// role is the account's: the strongest held by any of its wallets.
+pub async fn get_session_by_token(pool: &SqlitePool, token: &str) -> Result<Option<Session>> {
+    let session = sqlx::query_as::<_, Session>(
+        r#"
+        SELECT s.token, s.user_id, u.account_id, u.wallet_address, u.wallet_type,
+               (
+                   SELECT l.user_type FROM users l
+                   WHERE l.account_id = u.account_id
+                   ORDER BY CASE l.user_type WHEN 'admin' THEN 0 WHEN 'writer' THEN 1 ELSE 2 END
+                   LIMIT 1
+               ) as user_type,
+               s.expires_at
+        FROM sessions s
+        JOIN users u ON u.id = s.user_id
+        WHERE s.token = ?
+        "#,
+    )
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(session.filter(|s| s.expires_at > Utc::now()))
+}
+
+pub async fn delete_session(pool: &SqlitePool, token: &str) -> Result<()> {
+    sqlx::query("DELETE FROM sessions WHERE token = ?")
+        .bind(token)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_expired_sessions(pool: &SqlitePool) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(Utc::now())
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Set the role of one wallet, leaving the others on its account alone.
+/// Returns whether the wallet exists.
+pub async fn set_wallet_user_type(pool: &SqlitePool, user_id: i64, user_type: UserType) -> Result<bool> {
+    let result = sqlx::query("UPDATE users SET user_type = ? WHERE id = ?")
+        .bind(user_type)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn has_admin(pool: &SqlitePool) -> Result<bool> {
+    let exists: (bool,) = sqlx::query_as("SELECT EXISTS (SELECT 1 FROM users WHERE user_type = ?)")
+        .bind(UserType::Admin)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(exists.0)
+}
+
+/// A wallet linked to an account, without the sign-in nonce.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct LinkedWallet {
+    pub id: i64,
+    pub wallet_address: String,
+    pub wallet_type: String,
+    pub created_at: DateTime<Utc>,
+    pub last_login: Option<DateTime<Utc>>,
+}
+
+pub async fn get_account_wallets(pool: &SqlitePool, account_id: i64) -> Result<Vec<LinkedWallet>> {
+    let wallets = sqlx::query_as::<_, LinkedWallet>(
+        r#"
+        SELECT id, wallet_address, wallet_type, created_at, last_login
+        FROM users
+        WHERE account_id = ?
+        ORDER BY id
+        "#,
+    )
+    .bind(account_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(wallets)
+}
+
+/// Merge the account that `user_id` belongs to into `account_id`. Any other
+/// wallets already linked to the user come along. Each wallet keeps its own
+/// role, so unlinking one later takes away only what it brought.
+pub async fn link_user_to_account(pool: &SqlitePool, user_id: i64, account_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let (old_account_id,): (i64,) = sqlx::query_as("SELECT account_id FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if old_account_id == account_id {
+        return Ok(());
+    }
+
+    sqlx::query("UPDATE users SET account_id = ? WHERE account_id = ?")
+        .bind(account_id)
+        .bind(old_account_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM accounts WHERE id = ?")
+        .bind(old_account_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Detach a wallet from its account into a fresh account of its own. The
+/// wallet keeps its own role; its sessions are revoked, since they were
+/// carrying the old account's role.
+pub async fn unlink_user(pool: &SqlitePool, user_id: i64) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let account_id = sqlx::query("INSERT INTO accounts (created_at) VALUES (?)")
+        .bind(Utc::now())
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    sqlx::query("UPDATE users SET account_id = ? WHERE id = ?")
+        .bind(account_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
 pub async fn create_tip(pool: &SqlitePool, tip: &Tip) -> Result<i64> {
     let result = sqlx::query(
         r#"
@@ -149,17 +579,6 @@ pub async fn create_tip(pool: &SqlitePool, tip: &Tip) -> Result<i64> {
     Ok(result.last_insert_rowid())
 }
 
-pub async fn get_tips_for_post(pool: &SqlitePool, post_id: i64) -> Result<Vec<Tip>> {
-    let tips = sqlx::query_as::<_, Tip>(
-        "SELECT * FROM tips WHERE post_id = ? AND verified = true ORDER BY created_at DESC",
-    )
-    .bind(post_id)
-    .fetch_all(pool)
-    .await?;
-
-    Ok(tips)
-}
-
 pub async fn get_all_tips(pool: &SqlitePool) -> Result<Vec<Tip>> {
     let tips = sqlx::query_as::<_, Tip>(
         "SELECT * FROM tips WHERE verified = true ORDER BY created_at DESC LIMIT 50",
@@ -193,10 +612,105 @@ pub async fn delete_post(pool: &SqlitePool, slug: &str) -> Result<()> {
         .bind(slug)
         .execute(pool)
         .await?;
+    delete_unused_tags(&mut *pool.acquire().await?).await?;
 
     Ok(())
 }
 
+/// Hashes recorded when a post was last synced with its file; both are `None`
+/// until the first sync.
+#[derive(Debug, Default, sqlx::FromRow)]
+pub struct PostSyncState {
+    pub file_hash: Option<String>,
+    pub synced_hash: Option<String>,
+}
+
+pub async fn get_post_sync_state(pool: &SqlitePool, slug: &str) -> Result<Option<PostSyncState>> {
+    let state = sqlx::query_as::<_, PostSyncState>(
+        "SELECT file_hash, synced_hash FROM posts WHERE slug = ?",
+    )
+    .bind(slug)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(state)
+}
+
+pub async fn mark_post_synced(
+    pool: &SqlitePool,
+    slug: &str,
+    file_hash: &str,
+    synced_hash: &str,
+) -> Result<()> {
+    sqlx::query("UPDATE posts SET file_hash = ?, synced_hash = ?, synced_at = ? WHERE slug = ?")
+        .bind(file_hash)
+        .bind(synced_hash)
+        .bind(Utc::now())
+        .bind(slug)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Replace a post's rendered HTML and TOC without touching its content or dates.
+pub async fn update_post_rendered(pool: &SqlitePool, slug: &str, rendered: &Rendered) -> Result<()> {
+    sqlx::query("UPDATE posts SET html_content = ?, toc = ? WHERE slug = ?")
+        .bind(&rendered.html)
+        .bind(Json(&rendered.toc))
+        .bind(slug)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_all_post_slugs(pool: &SqlitePool) -> Result<Vec<String>> {
+    let slugs: Vec<(String,)> = sqlx::query_as("SELECT slug FROM posts ORDER BY slug")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(slugs.into_iter().map(|(slug,)| slug).collect())
+}
+
+/// Move a post to a new slug, along with the activity recorded against it.
+/// Returns false if there is no post at `old` or `new` is already taken.
+pub async fn rename_post_slug(pool: &SqlitePool, old: &str, new: &str) -> Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        "UPDATE posts SET slug = ? WHERE slug = ? AND NOT EXISTS (SELECT 1 FROM posts WHERE slug = ?)",
+    )
+    .bind(new)
+    .bind(old)
+    .bind(new)
+    .execute(&mut *tx)
+    .await?;
+    if result.rows_affected() != 1 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE user_activities SET target_id = ? WHERE target_type = 'post' AND target_id = ?")
+        .bind(new)
+        .bind(old)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+/// Publish or unpublish a post without touching its content (or `updated_at`).
+pub async fn set_post_published(pool: &SqlitePool, slug: &str, published: bool) -> Result<bool> {
+    let result = sqlx::query("UPDATE posts SET published = ? WHERE slug = ?")
+        .bind(published)
+        .bind(slug)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
 pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
     let exists = post_exists(pool, &post.slug).await?;
 
@@ -204,8 +718,10 @@ pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
         sqlx::query(
             r#"
             UPDATE posts SET
-                title = ?, description = ?, content = ?, html_content = ?,
-                author = ?, tags = ?, published = ?, updated_at = ?
+                title = ?, description = ?, content = ?, html_content = ?, toc = ?,
+                author = ?, tags = ?, published = ?, publish_at = ?,
+                created_at = ?, updated_at = ?,
+                author_wallet = COALESCE(?, author_wallet)
             WHERE slug = ?
             "#,
         )
@@ -213,10 +729,14 @@ pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
         .bind(&post.description)
         .bind(&post.content)
         .bind(&post.html_content)
+        .bind(&post.toc)
         .bind(&post.author)
         .bind(&post.tags)
         .bind(post.published)
+        .bind(post.publish_at)
+        .bind(post.created_at)
         .bind(post.updated_at)
+        .bind(&post.author_wallet)
         .bind(&post.slug)
         .execute(pool)
         .await?;
@@ -225,6 +745,7 @@ pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
             .bind(&post.slug)
             .fetch_one(pool)
             .await?;
+        set_post_tags(pool, existing.0, &post.tags).await?;
 
         Ok(existing.0)
     } else {
@@ -232,6 +753,232 @@ pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
     }
 }
 
+async fn replace_post_tags(conn: &mut SqliteConnection, post_id: i64, tags: &[String]) -> Result<()> {
+    sqlx::query("DELETE FROM post_tags WHERE post_id = ?")
+        .bind(post_id)
+        .execute(&mut *conn)
+        .await?;
+
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO tags (name) VALUES (?)")
+            .bind(tag)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query(
+            "INSERT OR IGNORE INTO post_tags (post_id, tag_id) SELECT ?, id FROM tags WHERE name = ?",
+        )
+        .bind(post_id)
+        .bind(tag)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn delete_unused_tags(conn: &mut SqliteConnection) -> Result<()> {
+    sqlx::query("DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM post_tags)")
+        .execute(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Point `post_tags` at the tags in a comma-joined list.
+pub async fn set_post_tags(pool: &SqlitePool, post_id: i64, tags: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    replace_post_tags(&mut tx, post_id, &parse_tags(tags)).await?;
+    delete_unused_tags(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Tags with their number of posts, most used first. With `live_only`, drafts
+/// and scheduled posts aren't counted and tags only they use are left out.
+pub async fn get_tag_counts(pool: &SqlitePool, live_only: bool) -> Result<Vec<TagCount>> {
+    let tags = sqlx::query_as::<_, TagCount>(
+        r#"
+        SELECT t.name, COUNT(p.id) AS count
+        FROM tags t
+        JOIN post_tags pt ON pt.tag_id = t.id
+        JOIN posts p ON p.id = pt.post_id
+        WHERE ? = false OR (p.published = true AND (p.publish_at IS NULL OR p.publish_at <= ?))
+        GROUP BY t.id
+        ORDER BY count DESC, t.name
+        "#,
+    )
+    .bind(live_only)
+    .bind(Utc::now())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tags)
+}
+
+/// The stored name of a tag, matched case-insensitively.
+pub async fn get_tag_name(pool: &SqlitePool, name: &str) -> Result<Option<String>> {
+    let tag: Option<(String,)> = sqlx::query_as("SELECT name FROM tags WHERE name = ?")
+        .bind(normalize_tag(name))
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(tag.map(|(name,)| name))
+}
+
+/// One page of the published posts with a tag, newest first.
+pub async fn get_posts_by_tag(
+    pool: &SqlitePool,
+    name: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Post>> {
+    let posts = sqlx::query_as::<_, Post>(
+        r#"
+        SELECT p.* FROM posts p
+        JOIN post_tags pt ON pt.post_id = p.id
+        JOIN tags t ON t.id = pt.tag_id
+        WHERE t.name = ? AND p.published = true AND (p.publish_at IS NULL OR p.publish_at <= ?)
+        ORDER BY p.created_at DESC, p.id DESC
+        LIMIT ? OFFSET ?
+        "#,
+    )
+    .bind(normalize_tag(name))
+    .bind(Utc::now())
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(posts)
+}
+
+pub async fn count_posts_by_tag(pool: &SqlitePool, name: &str) -> Result<i64> {
+    let count: (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM posts p
+        JOIN post_tags pt ON pt.post_id = p.id
+        JOIN tags t ON t.id = pt.tag_id
+        WHERE t.name = ? AND p.published = true AND (p.publish_at IS NULL OR p.publish_at <= ?)
+        "#,
+    )
+    .bind(normalize_tag(name))
+    .bind(Utc::now())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count.0)
+}
+
+/// Rename tag `from` to `to` on every post that has it. If `to` already
+/// exists the two tags are merged. Each post's tag string is rewritten in
+/// place, keeping its order. Returns the slugs of the changed posts, or `None`
+/// if `from` doesn't exist.
+pub async fn rename_tag(pool: &SqlitePool, from: &str, to: &str) -> Result<Option<Vec<String>>> {
+    let from = normalize_tag(from);
+    let to = normalize_tag(to);
+    let mut tx = pool.begin().await?;
+
+    let tag: Option<(i64,)> = sqlx::query_as("SELECT id FROM tags WHERE name = ?")
+        .bind(&from)
+        .fetch_optional(&mut *tx)
+        .await?;
+    let Some((tag_id,)) = tag else {
+        return Ok(None);
+    };
+
+    let posts: Vec<(i64, String, String)> = sqlx::query_as(
+        r#"
+        SELECT p.id, p.slug, p.tags FROM posts p
+        JOIN post_tags pt ON pt.post_id = p.id
+        WHERE pt.tag_id = ?
+        "#,
+    )
+    .bind(tag_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut slugs = Vec::with_capacity(posts.len());
+    for (post_id, slug, tags) in posts {
+        let mut renamed: Vec<String> = Vec::new();
+        for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let tag = if normalize_tag(tag) == from { to.as_str() } else { tag };
+            if !renamed.iter().any(|t| normalize_tag(t) == normalize_tag(tag)) {
+                renamed.push(tag.to_string());
+            }
+        }
+        let renamed = renamed.join(", ");
+
+        sqlx::query("UPDATE posts SET tags = ? WHERE id = ?")
+            .bind(&renamed)
+            .bind(post_id)
+            .execute(&mut *tx)
+            .await?;
+        replace_post_tags(&mut tx, post_id, &parse_tags(&renamed)).await?;
+        slugs.push(slug);
+    }
+
+    delete_unused_tags(&mut tx).await?;
+    tx.commit().await?;
+
+    Ok(Some(slugs))
+}
+
+/// Record the current state of the post with `slug` as a new revision.
+pub async fn create_revision(
+    pool: &SqlitePool,
+    slug: &str,
+    editor_wallet: Option<&str>,
+    note: Option<&str>,
+) -> Result<i64> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO post_revisions (post_id, title, description, author, tags, content, editor_wallet, note, created_at)
+        SELECT id, title, description, author, tags, content, ?, ?, ?
+        FROM posts WHERE slug = ?
+        "#,
+    )
+    .bind(editor_wallet)
+    .bind(note)
+    .bind(Utc::now())
+    .bind(slug)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Revisions of a post, newest first.
+pub async fn get_revisions(pool: &SqlitePool, post_id: i64) -> Result<Vec<PostRevision>> {
+    let revisions = sqlx::query_as::<_, PostRevision>(
+        "SELECT * FROM post_revisions WHERE post_id = ? ORDER BY id DESC",
+    )
+    .bind(post_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(revisions)
+}
+
+pub async fn get_revision(pool: &SqlitePool, revision_id: i64) -> Result<Option<PostRevision>> {
+    let revision = sqlx::query_as::<_, PostRevision>("SELECT * FROM post_revisions WHERE id = ?")
+        .bind(revision_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(revision)
+}
+
+pub async fn has_revisions(pool: &SqlitePool, post_id: i64) -> Result<bool> {
+    let (count,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM post_revisions WHERE post_id = ?")
+            .bind(post_id)
+            .fetch_one(pool)
+            .await?;
+
+    Ok(count > 0)
+}
+
 // =============================================
 // USER ACTIVITY TRACKING FUNCTIONS
 // =============================================
@@ -239,12 +986,7 @@ pub async fn upsert_post(pool: &SqlitePool, post: &Post) -> Result<i64> {
 /// Create a new user activity record
 pub async fn create_activity(
     pool: &SqlitePool,
-    wallet_address: &str,
-    activity_type: &str,
-    target_type: Option<&str>,
-    target_id: Option<&str>,
-    metadata: Option<&str>,
-    chain: Option<&str>,
+    activity: &CreateActivityRequest,
     ip_hash: Option<&str>,
     user_agent: Option<&str>,
 ) -> Result<i64> {
@@ -252,7 +994,7 @@ pub async fn create_activity(
     let user: Option<(i64,)> = sqlx::query_as(
         "SELECT id FROM users WHERE wallet_address = ? LIMIT 1"
     )
-    .bind(wallet_address)
+    .bind(&activity.wallet_address)
     .fetch_optional(pool)
     .await?;
 
@@ -265,12 +1007,12 @@ pub async fn create_activity(
         "#,
     )
     .bind(user_id)
-    .bind(wallet_address)
-    .bind(activity_type)
-    .bind(target_type)
-    .bind(target_id)
-    .bind(metadata)
-    .bind(chain)
+    .bind(&activity.wallet_address)
+    .bind(&activity.activity_type)
+    .bind(&activity.target_type)
+    .bind(&activity.target_id)
+    .bind(&activity.metadata)
+    .bind(&activity.chain)
     .bind(ip_hash)
     .bind(user_agent)
     .bind(Utc::now())
@@ -280,7 +1022,8 @@ pub async fn create_activity(
     Ok(result.last_insert_rowid())
 }
 
-/// Get activities for a specific user
+/// Get activities for a specific user, including every wallet linked to the
+/// same account
 pub async fn get_user_activities(
     pool: &SqlitePool,
     wallet_address: &str,
@@ -290,11 +1033,18 @@ pub async fn get_user_activities(
         r#"
         SELECT * FROM user_activities
         WHERE wallet_address = ?
+        OR wallet_address IN (
+            SELECT linked.wallet_address
+            FROM users u
+            JOIN users linked ON linked.account_id = u.account_id
+            WHERE u.wallet_address = ?
+        )
         ORDER BY created_at DESC
         LIMIT ?
         "#,
     )
     .bind(wallet_address)
+    .bind(wallet_address)
     .bind(limit)
     .fetch_all(pool)
     .await?;
@@ -339,12 +1089,16 @@ pub async fn get_post_view_count(pool: &SqlitePool, post_slug: &str) -> Result<i
     Ok(result.0)
 }
 
-/// Get unique viewers for a post
+/// Get unique viewers for a post. Linked wallets count as one viewer.
 pub async fn get_post_unique_viewers(pool: &SqlitePool, post_slug: &str) -> Result<i64> {
     let result: (i64,) = sqlx::query_as(
         r#"
-        SELECT COUNT(DISTINCT wallet_address) FROM user_activities
-        WHERE activity_type = 'post_view' AND target_id = ?
+        SELECT COUNT(DISTINCT COALESCE(
+            (SELECT 'a' || u.account_id FROM users u WHERE u.wallet_address = ua.wallet_address LIMIT 1),
+            'w' || ua.wallet_address
+        ))
+        FROM user_activities ua
+        WHERE ua.activity_type = 'post_view' AND ua.target_id = ?
         "#,
     )
     .bind(post_slug)
@@ -376,6 +1130,15 @@ pub async fn update_user_preferred_chain(pool: &SqlitePool, user_id: i64, chain:
     Ok(())
 }
 
+pub async fn get_user_by_id(pool: &SqlitePool, user_id: i64) -> Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
 /// Get user by wallet address (any chain)
 pub async fn get_user_by_wallet(pool: &SqlitePool, wallet_address: &str) -> Result<Option<User>> {
     let user = sqlx::query_as::<_, User>(
@@ -388,13 +1151,19 @@ pub async fn get_user_by_wallet(pool: &SqlitePool, wallet_address: &str) -> Resu
     Ok(user)
 }
 
-/// Reader info with last read post
+/// Reader info with last read post. One row per account; the address shown
+/// is the account's first wallet and the stats cover all linked wallets.
 #[derive(Debug, sqlx::FromRow)]
 pub struct ReaderInfo {
+    pub id: i64,
+    pub account_id: i64,
     pub wallet_address: String,
     pub wallet_type: Option<String>,
+    pub user_type: UserType,
+    pub linked_wallets: i64,
     pub last_login: Option<chrono::DateTime<chrono::Utc>>,
     pub login_count: i64,
+    pub tip_count: i64,
     pub last_post_slug: Option<String>,
     pub last_post_title: Option<String>,
     pub last_read_at: Option<chrono::DateTime<chrono::Utc>>,
@@ -404,38 +1173,63 @@ pub struct ReaderInfo {
 pub async fn get_readers_with_last_post(pool: &SqlitePool) -> Result<Vec<ReaderInfo>> {
     let readers = sqlx::query_as::<_, ReaderInfo>(
         r#"
+        WITH account_wallets AS (
+            SELECT account_id, wallet_address FROM users
+        ),
+        account_views AS (
+            SELECT aw.account_id, ua.target_id, ua.created_at
+            FROM user_activities ua
+            JOIN account_wallets aw ON aw.wallet_address = ua.wallet_address
+            WHERE ua.activity_type = 'post_view'
+        )
         SELECT
+            u.id,
+            u.account_id,
             u.wallet_address,
             u.wallet_type,
-            u.last_login,
-            COALESCE(u.login_count, 0) as login_count,
             (
-                SELECT ua.target_id
-                FROM user_activities ua
-                WHERE ua.wallet_address = u.wallet_address
-                AND ua.activity_type = 'post_view'
-                ORDER BY ua.created_at DESC
+                SELECT l.user_type FROM users l
+                WHERE l.account_id = u.account_id
+                ORDER BY CASE l.user_type WHEN 'admin' THEN 0 WHEN 'writer' THEN 1 ELSE 2 END
+                LIMIT 1
+            ) as user_type,
+            (SELECT COUNT(*) FROM users l WHERE l.account_id = u.account_id) as linked_wallets,
+            (SELECT MAX(l.last_login) FROM users l WHERE l.account_id = u.account_id) as last_login,
+            (
+                SELECT COALESCE(SUM(l.login_count), 0)
+                FROM users l WHERE l.account_id = u.account_id
+            ) as login_count,
+            (
+                SELECT COUNT(*)
+                FROM tips t
+                JOIN account_wallets aw ON aw.wallet_address = t.from_address
+                WHERE aw.account_id = u.account_id
+            ) as tip_count,
+            (
+                SELECT av.target_id
+                FROM account_views av
+                WHERE av.account_id = u.account_id
+                ORDER BY av.created_at DESC
                 LIMIT 1
             ) as last_post_slug,
             (
                 SELECT p.title
-                FROM user_activities ua
-                JOIN posts p ON p.slug = ua.target_id
-                WHERE ua.wallet_address = u.wallet_address
-                AND ua.activity_type = 'post_view'
-                ORDER BY ua.created_at DESC
+                FROM account_views av
+                JOIN posts p ON p.slug = av.target_id
+                WHERE av.account_id = u.account_id
+                ORDER BY av.created_at DESC
                 LIMIT 1
             ) as last_post_title,
             (
-                SELECT ua.created_at
-                FROM user_activities ua
-                WHERE ua.wallet_address = u.wallet_address
-                AND ua.activity_type = 'post_view'
-                ORDER BY ua.created_at DESC
+                SELECT av.created_at
+                FROM account_views av
+                WHERE av.account_id = u.account_id
+                ORDER BY av.created_at DESC
                 LIMIT 1
             ) as last_read_at
         FROM users u
-        ORDER BY u.last_login DESC NULLS LAST
+        WHERE u.id = (SELECT MIN(f.id) FROM users f WHERE f.account_id = u.account_id)
+        ORDER BY last_login DESC NULLS LAST
         LIMIT 100
         "#,
     )
@@ -444,3 +1238,139 @@ pub async fn get_readers_with_last_post(pool: &SqlitePool) -> Result<Vec<ReaderI
 
     Ok(readers)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn pool() -> SqlitePool {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn role(pool: &SqlitePool, token: &str) -> UserType {
+        get_session_by_token(pool, token).await.unwrap().unwrap().user_type
+    }
+
+    #[tokio::test]
+    async fn unlinking_a_wallet_takes_its_role_with_it() {
+        let pool = pool().await;
+        let expires = Utc::now() + chrono::Duration::hours(1);
+        let admin = get_or_create_user(&pool, "0xaa", "ethereum").await.unwrap();
+        let reader = get_or_create_user(&pool, "0xbb", "ethereum").await.unwrap();
+        set_wallet_user_type(&pool, admin.id, UserType::Admin).await.unwrap();
+        assert!(has_admin(&pool).await.unwrap());
+        create_session(&pool, admin.id, "admin", expires).await.unwrap();
+        create_session(&pool, reader.id, "reader", expires).await.unwrap();
+
+        // Linking the admin wallet to the reader's account lends it the role
+        link_user_to_account(&pool, admin.id, reader.account_id).await.unwrap();
+        assert_eq!(role(&pool, "reader").await, UserType::Admin);
+
+        // ...until the admin wallet is unlinked again
+        unlink_user(&pool, admin.id).await.unwrap();
+        assert_eq!(role(&pool, "reader").await, UserType::Reader);
+        assert!(get_session_by_token(&pool, "admin").await.unwrap().is_none());
+
+        // The admin wallet keeps its own role when it signs in alone
+        let admin = get_or_create_user(&pool, "0xaa", "ethereum").await.unwrap();
+        assert_ne!(admin.account_id, reader.account_id);
+        create_session(&pool, admin.id, "admin-again", expires).await.unwrap();
+        assert_eq!(role(&pool, "admin-again").await, UserType::Admin);
+    }
+
+    #[tokio::test]
+    async fn setting_a_role_touches_only_that_wallet() {
+        let pool = pool().await;
+        let expires = Utc::now() + chrono::Duration::hours(1);
+        let writer = get_or_create_user(&pool, "0xaa", "ethereum").await.unwrap();
+        let reader = get_or_create_user(&pool, "0xbb", "ethereum").await.unwrap();
+        link_user_to_account(&pool, reader.id, writer.account_id).await.unwrap();
+
+        assert!(set_wallet_user_type(&pool, writer.id, UserType::Writer).await.unwrap());
+        assert!(!set_wallet_user_type(&pool, 9999, UserType::Admin).await.unwrap());
+        assert!(!has_admin(&pool).await.unwrap());
+
+        // The linked reader wallet only borrows the role while linked
+        unlink_user(&pool, reader.id).await.unwrap();
+        create_session(&pool, reader.id, "reader", expires).await.unwrap();
+        assert_eq!(role(&pool, "reader").await, UserType::Reader);
+    }
+
+    #[tokio::test]
+    async fn a_consumed_nonce_cannot_be_reused() {
+        let pool = pool().await;
+        let user = get_or_create_user(&pool, "0xaa", "ethereum").await.unwrap();
+        update_user_nonce(&pool, user.id, "n0nce").await.unwrap();
+
+        assert!(consume_user_nonce(&pool, user.id, "n0nce").await.unwrap());
+        assert!(!consume_user_nonce(&pool, user.id, "n0nce").await.unwrap());
+
+        // The nonce was replaced, not just marked used
+        let user = get_user_by_id(&pool, user.id).await.unwrap().unwrap();
+        assert_ne!(user.nonce, "n0nce");
+    }
+
+    fn post(slug: &str, title: &str) -> Post {
+        let now = Utc::now() - chrono::Duration::minutes(1);
+        Post {
+            id: 0,
+            slug: slug.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            content: "Body text".to_string(),
+            html_content: String::new(),
+            toc: Json(Vec::new()),
+            author: "Alice".to_string(),
+            tags: "rust".to_string(),
+            published: true,
+            created_at: now,
+            updated_at: now,
+            views: 0,
+            author_wallet: None,
+            publish_at: None,
+        }
+    }
+
+    async fn search(pool: &SqlitePool, input: &str) -> Vec<String> {
+        search_posts(pool, &SearchQuery::parse(input), 10)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.slug)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn search_follows_post_writes() {
+        let pool = pool().await;
+        upsert_post(&pool, &post("wombats", "Wombats dig burrows")).await.unwrap();
+        assert_eq!(search(&pool, "burrow").await, vec!["wombats"]);
+
+        upsert_post(&pool, &post("wombats", "Wombats eat grass")).await.unwrap();
+        assert!(search(&pool, "burrows").await.is_empty());
+        assert_eq!(search(&pool, "grass").await, vec!["wombats"]);
+
+        delete_post(&pool, "wombats").await.unwrap();
+        assert!(search(&pool, "grass").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hostile_search_input_is_a_valid_match() {
+        let pool = pool().await;
+        upsert_post(&pool, &post("or", "Either OR neither")).await.unwrap();
+
+        for input in [r#"""#, "*", "a OR", "NEAR(a b", "-x", "title:either", "^", r#"say"hi"#, "AND ( )"] {
+            search_posts(&pool, &SearchQuery::parse(input), 10).await.unwrap();
+        }
+        // Operators are searched for as words
+        assert_eq!(search(&pool, "OR").await, vec!["or"]);
+        assert_eq!(search(&pool, "title:either").await, Vec::<String>::new());
+    }
+}
@@ -0,0 +1,362 @@
+//! RSS 2.0, Atom and JSON Feed documents for a list of posts.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::fmt::Write;
+
+use crate::models::Post;
+
+/// What a feed is about and where it lives. URLs are absolute.
+pub struct FeedInfo {
+    pub title: String,
+    pub description: String,
+    /// The HTML page the feed mirrors
+    pub home_url: String,
+    /// The feed's own URL
+    pub feed_url: String,
+    /// Prefix for post links, without a trailing slash
+    pub base_url: String,
+}
+
+impl FeedInfo {
+    fn post_url(&self, post: &Post) -> String {
+        format!("{}/post/{}", self.base_url, post.slug)
+    }
+}
+
+/// When the newest change to one of `posts` happened (see [`Post::modified_at`]).
+pub fn last_updated(posts: &[Post]) -> Option<DateTime<Utc>> {
+    posts.iter().map(Post::modified_at).max()
+}
+
+/// Escape text for XML element content and attribute values, dropping
+/// control characters XML 1.0 doesn't allow.
+pub(crate) fn xml_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn rfc3339(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+pub fn rss(info: &FeedInfo, posts: &[Post]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\" \
+         xmlns:content=\"http://purl.org/rss/1.0/modules/content/\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n<channel>\n",
+    );
+    let _ = writeln!(xml, "<title>{}</title>", xml_escape(&info.title));
+    let _ = writeln!(xml, "<link>{}</link>", xml_escape(&info.home_url));
+    let _ = writeln!(
+        xml,
+        "<description>{}</description>",
+        xml_escape(&info.description)
+    );
+    let _ = writeln!(
+        xml,
+        "<atom:link href=\"{}\" rel=\"self\" type=\"application/rss+xml\"/>",
+        xml_escape(&info.feed_url)
+    );
+    if let Some(updated) = last_updated(posts) {
+        let _ = writeln!(
+            xml,
+            "<lastBuildDate>{}</lastBuildDate>",
+            updated.to_rfc2822()
+        );
+    }
+
+    for post in posts {
+        let url = xml_escape(&info.post_url(post));
+        xml.push_str("<item>\n");
+        let _ = writeln!(xml, "<title>{}</title>", xml_escape(&post.title));
+        let _ = writeln!(xml, "<link>{}</link>", url);
+        let _ = writeln!(xml, "<guid isPermaLink=\"true\">{}</guid>", url);
+        let _ = writeln!(xml, "<pubDate>{}</pubDate>", post.created_at.to_rfc2822());
+        let _ = writeln!(xml, "<dc:creator>{}</dc:creator>", xml_escape(&post.author));
+        for tag in post.tags_list() {
+            let _ = writeln!(xml, "<category>{}</category>", xml_escape(tag));
+        }
+        let _ = writeln!(
+            xml,
+            "<description>{}</description>",
+            xml_escape(&post.description)
+        );
+        let _ = writeln!(
+            xml,
+            "<content:encoded>{}</content:encoded>",
+            xml_escape(&post.html_content)
+        );
+        xml.push_str("</item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+pub fn atom(info: &FeedInfo, posts: &[Post]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(xml, "<title>{}</title>", xml_escape(&info.title));
+    let _ = writeln!(
+        xml,
+        "<subtitle>{}</subtitle>",
+        xml_escape(&info.description)
+    );
+    let _ = writeln!(xml, "<id>{}</id>", xml_escape(&info.home_url));
+    let _ = writeln!(
+        xml,
+        "<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>",
+        xml_escape(&info.home_url)
+    );
+    let _ = writeln!(
+        xml,
+        "<link rel=\"self\" type=\"application/atom+xml\" href=\"{}\"/>",
+        xml_escape(&info.feed_url)
+    );
+    // Atom requires <updated>. An empty feed uses a fixed date so its body,
+    // and so its ETag, stays the same between requests.
+    let updated = last_updated(posts).unwrap_or(DateTime::UNIX_EPOCH);
+    let _ = writeln!(xml, "<updated>{}</updated>", rfc3339(updated));
+
+    for post in posts {
+        let url = xml_escape(&info.post_url(post));
+        xml.push_str("<entry>\n");
+        let _ = writeln!(xml, "<title>{}</title>", xml_escape(&post.title));
+        let _ = writeln!(
+            xml,
+            "<link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>",
+            url
+        );
+        let _ = writeln!(xml, "<id>{}</id>", url);
+        let _ = writeln!(xml, "<published>{}</published>", rfc3339(post.created_at));
+        let _ = writeln!(xml, "<updated>{}</updated>", rfc3339(post.modified_at()));
+        let _ = writeln!(
+            xml,
+            "<author><name>{}</name></author>",
+            xml_escape(&post.author)
+        );
+        for tag in post.tags_list() {
+            let _ = writeln!(xml, "<category term=\"{}\"/>", xml_escape(tag));
+        }
+        let _ = writeln!(
+            xml,
+            "<summary type=\"text\">{}</summary>",
+            xml_escape(&post.description)
+        );
+        let _ = writeln!(
+            xml,
+            "<content type=\"html\">{}</content>",
+            xml_escape(&post.html_content)
+        );
+        xml.push_str("</entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    home_page_url: &'a str,
+    feed_url: &'a str,
+    description: &'a str,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    content_html: &'a str,
+    summary: &'a str,
+    date_published: String,
+    date_modified: String,
+    authors: [JsonFeedAuthor<'a>; 1],
+    tags: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedAuthor<'a> {
+    name: &'a str,
+}
+
+/// A JSON Feed 1.1 document.
+pub fn json_feed(info: &FeedInfo, posts: &[Post]) -> String {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &info.title,
+        home_page_url: &info.home_url,
+        feed_url: &info.feed_url,
+        description: &info.description,
+        items: posts
+            .iter()
+            .map(|post| {
+                let url = info.post_url(post);
+                JsonFeedItem {
+                    id: url.clone(),
+                    url,
+                    title: &post.title,
+                    content_html: &post.html_content,
+                    summary: &post.description,
+                    date_published: rfc3339(post.created_at),
+                    date_modified: rfc3339(post.modified_at()),
+                    authors: [JsonFeedAuthor { name: &post.author }],
+                    tags: post.tags_list(),
+                }
+            })
+            .collect(),
+    };
+
+    serde_json::to_string_pretty(&feed).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event;
+    use sqlx::types::Json;
+
+    const TITLE: &str = "Tom & Jerry <b>\"quoted\" 'single'</b> ]]> bell\u{7}\u{1b}end";
+    const DESCRIPTION: &str = "a < b && c > d ]]> \"q\"\u{0}";
+    const HTML: &str = "<p>a &amp; b ]]&gt; <![CDATA[x]]> <em>\"hi\"</em>\u{8}</p>";
+
+    fn info() -> FeedInfo {
+        FeedInfo {
+            title: "Thoughnuts".to_string(),
+            description: "A blog".to_string(),
+            home_url: "https://example.com/".to_string(),
+            feed_url: "https://example.com/atom.xml".to_string(),
+            base_url: "https://example.com".to_string(),
+        }
+    }
+
+    fn post() -> Post {
+        let date = DateTime::parse_from_rfc3339("2024-01-15T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        Post {
+            id: 1,
+            slug: "tom-and-jerry".to_string(),
+            title: TITLE.to_string(),
+            description: DESCRIPTION.to_string(),
+            content: String::new(),
+            html_content: HTML.to_string(),
+            toc: Json(Vec::new()),
+            author: "A & B".to_string(),
+            tags: "r&d, <tags>".to_string(),
+            published: true,
+            created_at: date,
+            updated_at: date,
+            views: 0,
+            author_wallet: None,
+            publish_at: None,
+        }
+    }
+
+    /// What XML 1.0 can carry of `text`: everything but control characters.
+    fn xml_text(text: &str) -> String {
+        text.chars()
+            .filter(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'))
+            .collect()
+    }
+
+    /// Parse `xml`, failing on anything malformed, and return the unescaped
+    /// text of every element named `name`.
+    fn texts_of(xml: &str, name: &str) -> Vec<String> {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        let mut text: Option<String> = None;
+        let mut texts = Vec::new();
+        loop {
+            match reader.read_event().unwrap() {
+                Event::Start(e) => text = (e.name().0 == name).then(String::new),
+                Event::Text(e) => text.iter_mut().for_each(|t| t.push_str(&e)),
+                Event::GeneralRef(e) => {
+                    let c = match e.resolve_char_ref().unwrap() {
+                        Some(c) => c.to_string(),
+                        None => quick_xml::escape::resolve_predefined_entity(&e)
+                            .unwrap()
+                            .to_string(),
+                    };
+                    text.iter_mut().for_each(|t| t.push_str(&c));
+                }
+                Event::End(_) => texts.extend(text.take()),
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        texts
+    }
+
+    #[test]
+    fn empty_atom_feed_is_stable() {
+        let feed = atom(&info(), &[]);
+        assert!(feed.contains("<updated>1970-01-01T00:00:00Z</updated>"));
+        assert_eq!(feed, atom(&info(), &[]));
+    }
+
+    #[test]
+    fn rss_escapes_post_fields() {
+        let xml = rss(&info(), &[post()]);
+
+        assert_eq!(texts_of(&xml, "title"), ["Thoughnuts", &xml_text(TITLE)]);
+        assert_eq!(texts_of(&xml, "description")[1], xml_text(DESCRIPTION));
+        assert_eq!(texts_of(&xml, "content:encoded"), [xml_text(HTML)]);
+        assert_eq!(texts_of(&xml, "dc:creator"), ["A & B"]);
+        assert_eq!(texts_of(&xml, "category"), ["r&d", "<tags>"]);
+        assert!(!xml.contains("]]>"));
+    }
+
+    #[test]
+    fn atom_escapes_post_fields() {
+        let xml = atom(&info(), &[post()]);
+
+        assert_eq!(texts_of(&xml, "title"), ["Thoughnuts", &xml_text(TITLE)]);
+        assert_eq!(texts_of(&xml, "summary"), [xml_text(DESCRIPTION)]);
+        assert_eq!(texts_of(&xml, "content"), [xml_text(HTML)]);
+        assert!(xml.contains(r#"<category term="r&amp;d"/>"#));
+        assert!(xml.contains(r#"<category term="&lt;tags&gt;"/>"#));
+        assert!(!xml.contains("]]>"));
+    }
+
+    #[test]
+    fn json_feed_keeps_post_fields_exactly() {
+        let feed: serde_json::Value = serde_json::from_str(&json_feed(&info(), &[post()])).unwrap();
+        let item = &feed["items"][0];
+
+        assert_eq!(item["title"], TITLE);
+        assert_eq!(item["summary"], DESCRIPTION);
+        assert_eq!(item["content_html"], HTML);
+        assert_eq!(item["authors"][0]["name"], "A & B");
+        assert_eq!(item["tags"], serde_json::json!(["r&d", "<tags>"]));
+    }
+
+    #[test]
+    fn a_scheduled_post_going_live_moves_the_feed_date() {
+        let mut post = post();
+        let live_at = post.updated_at + chrono::Duration::days(1);
+        post.publish_at = Some(live_at);
+        assert_eq!(last_updated(std::slice::from_ref(&post)), Some(live_at));
+
+        post.publish_at = Some(Utc::now() + chrono::Duration::days(1));
+        assert_eq!(last_updated(&[post.clone()]), Some(post.updated_at));
+    }
+}
@@ -6,17 +6,11 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::{db, AppState};
-
-#[derive(Debug, Deserialize)]
-pub struct CreateActivityRequest {
-    pub wallet_address: String,
-    pub activity_type: String,
-    pub target_type: Option<String>,
-    pub target_id: Option<String>,
-    pub metadata: Option<String>,
-    pub chain: Option<String>,
-}
+use crate::{
+    db,
+    models::{ActivityType, CreateActivityRequest},
+    AppState,
+};
 
 #[derive(Debug, Serialize)]
 pub struct ActivityResponse {
@@ -86,25 +80,20 @@ pub async fn create_activity(
 ) -> Result<Json<ActivityResponse>, StatusCode> {
     let (ip_hash, user_agent) = extract_client_info(&headers);
 
-    let activity_id = db::create_activity(
-        &state.db,
-        &req.wallet_address,
-        &req.activity_type,
-        req.target_type.as_deref(),
-        req.target_id.as_deref(),
-        req.metadata.as_deref(),
-        req.chain.as_deref(),
-        ip_hash.as_deref(),
-        user_agent.as_deref(),
-    )
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to create activity: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let activity_type: ActivityType = req
+        .activity_type
+        .parse()
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let activity_id = db::create_activity(&state.db, &req, ip_hash.as_deref(), user_agent.as_deref())
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create activity: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
     // If this is a wallet_connect event, increment login count
-    if req.activity_type == "wallet_connect" {
+    if activity_type == ActivityType::WalletConnect {
         if let Ok(Some(user)) = db::get_user_by_wallet(&state.db, &req.wallet_address).await {
             let _ = db::increment_user_login_count(&state.db, user.id).await;
 
@@ -116,7 +105,7 @@ pub async fn create_activity(
     }
 
     // If this is a network_switch event, update preferred chain
-    if req.activity_type == "network_switch" {
+    if activity_type == ActivityType::NetworkSwitch {
         if let Some(chain) = &req.chain {
             if let Ok(Some(user)) = db::get_user_by_wallet(&state.db, &req.wallet_address).await {
                 let _ = db::update_user_preferred_chain(&state.db, user.id, chain).await;
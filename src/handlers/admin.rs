@@ -1,21 +1,23 @@
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    response::Html,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use chrono::Utc;
-
-use crate::{db, db::sqlite::ReaderInfo, markdown::MarkdownParser, models::Post, AppState};
-
-// Admin wallet addresses (lowercase)
-const ADMIN_ADDRESSES: &[&str] = &["0x360091e9e692b7775543da956b7ca6cc39bae86c"];
-
-fn is_admin(address: &str) -> bool {
-    ADMIN_ADDRESSES.contains(&address.to_lowercase().as_str())
-}
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    auth::{self, AdminSession, WriterSession},
+    db,
+    db::sqlite::ReaderInfo,
+    models::{normalize_tag, Post, TagCount, UserType},
+    posts_dir,
+    sync::{self, SyncReport},
+    AppState,
+};
 
 #[derive(Clone)]
 pub struct PostData {
@@ -25,6 +27,9 @@ pub struct PostData {
     pub author: String,
     pub tags: String,
     pub content: String,
+    pub published: bool,
+    /// RFC 3339, or empty when the post is not scheduled
+    pub publish_at: String,
 }
 
 impl Default for PostData {
@@ -36,6 +41,8 @@ impl Default for PostData {
             author: String::new(),
             tags: String::new(),
             content: String::new(),
+            published: true,
+            publish_at: String::new(),
         }
     }
 }
@@ -49,13 +56,18 @@ pub struct EditorTemplate {
 
 // Display struct for readers template
 pub struct ReaderDisplay {
+    pub id: i64,
+    pub account_id: i64,
     pub short_address: String,
     pub chain_display: String,
     pub last_post_slug: Option<String>,
     pub last_post_title: Option<String>,
     pub last_read_display: Option<String>,
     pub login_count: i64,
+    pub tip_count: i64,
+    pub other_wallets: i64,
     pub last_login_display: Option<String>,
+    pub user_type: String,
 }
 
 impl From<ReaderInfo> for ReaderDisplay {
@@ -79,13 +91,18 @@ impl From<ReaderInfo> for ReaderDisplay {
         let last_login_display = r.last_login.map(|dt| dt.format("%Y-%m-%d %H:%M").to_string());
 
         ReaderDisplay {
+            id: r.id,
+            account_id: r.account_id,
             short_address,
             chain_display,
             last_post_slug: r.last_post_slug,
             last_post_title: r.last_post_title,
             last_read_display,
             login_count: r.login_count,
+            tip_count: r.tip_count,
+            other_wallets: r.linked_wallets - 1,
             last_login_display,
+            user_type: r.user_type.to_string(),
         }
     }
 }
@@ -94,6 +111,20 @@ impl From<ReaderInfo> for ReaderDisplay {
 #[template(path = "admin/readers.html")]
 pub struct ReadersTemplate {
     pub readers: Vec<ReaderDisplay>,
+    pub current_account_id: i64,
+}
+
+#[derive(Template)]
+#[template(path = "admin/posts.html")]
+pub struct AdminPostsTemplate {
+    pub posts: Vec<Post>,
+    pub is_admin: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin/tags.html")]
+pub struct AdminTagsTemplate {
+    pub tags: Vec<TagCount>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -104,12 +135,24 @@ pub struct PostRequest {
     pub author: String,
     pub tags: String,
     pub content: String,
-    pub wallet_address: Option<String>,
+    #[serde(default = "default_published")]
+    pub published: bool,
+    #[serde(default)]
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+fn default_published() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize)]
-pub struct DeleteRequest {
-    pub wallet_address: Option<String>,
+pub struct RoleRequest {
+    pub user_type: UserType,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RenameTagRequest {
+    pub name: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -121,8 +164,37 @@ pub struct ApiResponse {
     pub slug: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SyncQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub mode: sync::SyncMode,
+    pub dry_run: bool,
+    pub unresolved: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub report: Option<SyncReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewLinkResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
 // Editor page for new post
-pub async fn new_post() -> Html<String> {
+pub async fn new_post(_writer: WriterSession) -> Html<String> {
     let template = EditorTemplate {
         is_edit: false,
         post: PostData::default(),
@@ -132,10 +204,14 @@ pub async fn new_post() -> Html<String> {
 
 // Editor page for editing existing post
 pub async fn edit_post(
+    WriterSession(session): WriterSession,
     State(state): State<Arc<AppState>>,
     Path(slug): Path<String>,
 ) -> Html<String> {
-    match db::get_post_by_slug(&state.db, &slug).await {
+    match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if !session.can_edit(&post) => {
+            Html("<h1>You can only edit your own posts</h1>".to_string())
+        }
         Ok(Some(post)) => {
             let template = EditorTemplate {
                 is_edit: true,
@@ -146,6 +222,11 @@ pub async fn edit_post(
                     author: post.author,
                     tags: post.tags,
                     content: post.content,
+                    published: post.published,
+                    publish_at: post
+                        .publish_at
+                        .map(|at| at.to_rfc3339())
+                        .unwrap_or_default(),
                 },
             };
             Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
@@ -154,42 +235,161 @@ pub async fn edit_post(
     }
 }
 
+// Posts the signed-in writer can manage (all posts for admins)
+pub async fn posts(
+    WriterSession(session): WriterSession,
+    State(state): State<Arc<AppState>>,
+) -> Html<String> {
+    let owner = (!session.is_admin()).then_some(session.wallet_address.as_str());
+    let posts = db::get_posts_for_editor(&state.db, owner)
+        .await
+        .unwrap_or_default();
+    let template = AdminPostsTemplate {
+        posts,
+        is_admin: session.is_admin(),
+    };
+    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+}
+
 // Admin readers page
-pub async fn readers(State(state): State<Arc<AppState>>) -> Html<String> {
+pub async fn readers(
+    AdminSession(session): AdminSession,
+    State(state): State<Arc<AppState>>,
+) -> Html<String> {
     let readers = db::get_readers_with_last_post(&state.db)
         .await
         .unwrap_or_default()
         .into_iter()
         .map(ReaderDisplay::from)
         .collect();
-    let template = ReadersTemplate { readers };
+    let template = ReadersTemplate {
+        readers,
+        current_account_id: session.account_id,
+    };
+    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+}
+
+// Admin tags page
+pub async fn tags(_admin: AdminSession, State(state): State<Arc<AppState>>) -> Html<String> {
+    let tags = db::get_tag_counts(&state.db, false)
+        .await
+        .unwrap_or_default();
+    let template = AdminTagsTemplate { tags };
     Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
 }
 
+// API: Rename a tag on every post, merging it into the new name if that tag exists
+pub async fn rename_tag(
+    AdminSession(session): AdminSession,
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(req): Json<RenameTagRequest>,
+) -> Json<ApiResponse> {
+    let error = |message: String| {
+        Json(ApiResponse {
+            success: false,
+            error: Some(message),
+            slug: None,
+        })
+    };
+
+    let new_name = normalize_tag(&req.name);
+    if new_name.is_empty() || new_name.contains(',') {
+        return error("Tag names can't be empty or contain commas".to_string());
+    }
+
+    let slugs = match db::rename_tag(&state.db, &name, &new_name).await {
+        Ok(Some(slugs)) => slugs,
+        Ok(None) => return error("Tag not found".to_string()),
+        Err(e) => return error(format!("Failed to rename tag: {}", e)),
+    };
+    state.pages.clear();
+
+    // Keep the markdown files in step so the rename survives a reload
+    let note = format!("Renamed tag {} to {}", normalize_tag(&name), new_name);
+    for slug in &slugs {
+        if let Ok(Some(post)) = db::get_any_post_by_slug(&state.db, slug).await {
+            if let Err(e) = sync::export_post(&state.db, &post).await {
+                tracing::error!("Failed to save {} after tag rename: {}", slug, e);
+            }
+        }
+        if let Err(e) =
+            db::create_revision(&state.db, slug, Some(&session.wallet_address), Some(&note)).await
+        {
+            tracing::error!("Failed to record revision for {}: {}", slug, e);
+        }
+    }
+
+    Json(ApiResponse {
+        success: true,
+        error: None,
+        slug: None,
+    })
+}
+
+// API: Sync posts/ with the database and report what changed and any conflicts
+pub async fn sync_posts(
+    _admin: AdminSession,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SyncQuery>,
+) -> Json<SyncResponse> {
+    let mode = state.config.sync_mode;
+    match sync::sync_all(&state.db, &state.parser, mode, !query.dry_run).await {
+        Ok(report) => {
+            if !query.dry_run {
+                report.log();
+                state.pages.clear();
+            }
+            Json(SyncResponse {
+                success: true,
+                error: None,
+                mode,
+                dry_run: query.dry_run,
+                unresolved: report.unresolved(),
+                report: Some(report),
+            })
+        }
+        Err(e) => Json(SyncResponse {
+            success: false,
+            error: Some(format!("Failed to sync: {}", e)),
+            mode,
+            dry_run: query.dry_run,
+            unresolved: 0,
+            report: None,
+        }),
+    }
+}
+
 // API: Create new post
 pub async fn create_post(
+    WriterSession(session): WriterSession,
     State(state): State<Arc<AppState>>,
     Json(req): Json<PostRequest>,
-) -> Json<ApiResponse> {
-    // Check admin authorization
-    let wallet = req.wallet_address.as_deref().unwrap_or("");
-    if !is_admin(wallet) {
+) -> Response {
+    if !posts_dir::is_valid_slug(&req.slug) {
         return Json(ApiResponse {
             success: false,
-            error: Some("Unauthorized".to_string()),
+            error: Some("Slugs may only contain lowercase letters, digits and dashes".to_string()),
             slug: None,
-        });
+        })
+        .into_response();
+    }
+
+    // Creating never overwrites: edits go through `update_post`, which keeps
+    // the author and creation date and records a revision
+    if let Ok(true) = db::post_exists(&state.db, &req.slug).await {
+        return slug_taken();
     }
 
-    let parser = MarkdownParser::new();
-    let html_content = match parser.parse(&req.content) {
-        Ok(html) => html,
+    let rendered = match state.parser.render(&req.content) {
+        Ok(rendered) => rendered,
         Err(e) => {
             return Json(ApiResponse {
                 success: false,
                 error: Some(format!("Failed to parse markdown: {}", e)),
                 slug: None,
-            });
+            })
+            .into_response();
         }
     };
 
@@ -199,57 +399,99 @@ pub async fn create_post(
         title: req.title,
         description: req.description,
         content: req.content.clone(),
-        html_content,
+        html_content: rendered.html,
+        toc: sqlx::types::Json(rendered.toc),
         author: req.author,
         tags: req.tags,
-        published: true,
+        published: req.published,
         created_at: Utc::now(),
         updated_at: Utc::now(),
         views: 0,
+        author_wallet: Some(session.wallet_address.clone()),
+        publish_at: req.publish_at,
     };
 
-    match db::upsert_post(&state.db, &post).await {
+    match db::create_post(&state.db, &post).await {
         Ok(_) => {
             // Also save to markdown file
-            let _ = save_post_to_file(&post, &req.content);
+            if let Err(e) = sync::export_post(&state.db, &post).await {
+                tracing::error!("Failed to save {} to file: {}", post.slug, e);
+            }
+            record_revision(&state, &post.slug, &session.wallet_address).await;
+            state.pages.clear();
 
             Json(ApiResponse {
                 success: true,
                 error: None,
                 slug: Some(req.slug),
             })
+            .into_response()
         }
+        // Another request took the slug since the check above
+        Err(e) if is_unique_violation(&e) => slug_taken(),
         Err(e) => Json(ApiResponse {
             success: false,
             error: Some(format!("Failed to save: {}", e)),
             slug: None,
-        }),
+        })
+        .into_response(),
     }
 }
 
+fn slug_taken() -> Response {
+    (
+        StatusCode::CONFLICT,
+        Json(ApiResponse {
+            success: false,
+            error: Some("A post with this slug already exists".to_string()),
+            slug: None,
+        }),
+    )
+        .into_response()
+}
+
+fn is_unique_violation(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .is_some_and(|e| e.is_unique_violation())
+}
+
 // API: Update existing post
 pub async fn update_post(
+    WriterSession(session): WriterSession,
     State(state): State<Arc<AppState>>,
     Path(slug): Path<String>,
     Json(req): Json<PostRequest>,
 ) -> Json<ApiResponse> {
-    // Check admin authorization
-    let wallet = req.wallet_address.as_deref().unwrap_or("");
-    if !is_admin(wallet) {
+    // Get existing post to preserve views and ownership
+    let existing = match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) => post,
+        Ok(None) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("Post not found".to_string()),
+                slug: None,
+            });
+        }
+        Err(e) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some(format!("Failed to load post: {}", e)),
+                slug: None,
+            });
+        }
+    };
+
+    if !session.can_edit(&existing) {
         return Json(ApiResponse {
             success: false,
-            error: Some("Unauthorized".to_string()),
+            error: Some("You can only edit your own posts".to_string()),
             slug: None,
         });
     }
 
-    // Get existing post to preserve views
-    let existing = db::get_post_by_slug(&state.db, &slug).await.ok().flatten();
-    let views = existing.map(|p| p.views).unwrap_or(0);
-
-    let parser = MarkdownParser::new();
-    let html_content = match parser.parse(&req.content) {
-        Ok(html) => html,
+    let rendered = match state.parser.render(&req.content) {
+        Ok(rendered) => rendered,
         Err(e) => {
             return Json(ApiResponse {
                 success: false,
@@ -265,19 +507,38 @@ pub async fn update_post(
         title: req.title,
         description: req.description,
         content: req.content.clone(),
-        html_content,
+        html_content: rendered.html,
+        toc: sqlx::types::Json(rendered.toc),
         author: req.author,
         tags: req.tags,
-        published: true,
-        created_at: Utc::now(),
+        published: req.published,
+        created_at: existing.created_at,
         updated_at: Utc::now(),
-        views,
+        views: existing.views,
+        author_wallet: existing.author_wallet.clone(),
+        publish_at: req.publish_at,
     };
 
+    // Posts that predate revision history get their current state recorded
+    // first, so the first edit has something to diff against
+    if let Ok(false) = db::has_revisions(&state.db, existing.id).await {
+        let _ = db::create_revision(
+            &state.db,
+            &slug,
+            existing.author_wallet.as_deref(),
+            Some("Version before history was kept"),
+        )
+        .await;
+    }
+
     match db::upsert_post(&state.db, &post).await {
         Ok(_) => {
             // Also update markdown file
-            let _ = save_post_to_file(&post, &req.content);
+            if let Err(e) = sync::export_post(&state.db, &post).await {
+                tracing::error!("Failed to save {} to file: {}", post.slug, e);
+            }
+            record_revision(&state, &slug, &session.wallet_address).await;
+            state.pages.clear();
 
             Json(ApiResponse {
                 success: true,
@@ -295,24 +556,42 @@ pub async fn update_post(
 
 // API: Delete post
 pub async fn delete_post(
+    WriterSession(session): WriterSession,
     State(state): State<Arc<AppState>>,
     Path(slug): Path<String>,
-    Json(req): Json<DeleteRequest>,
 ) -> Json<ApiResponse> {
-    // Check admin authorization
-    let wallet = req.wallet_address.as_deref().unwrap_or("");
-    if !is_admin(wallet) {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            slug: None,
-        });
+    match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if session.can_edit(&post) => {}
+        Ok(Some(_)) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("You can only delete your own posts".to_string()),
+                slug: None,
+            });
+        }
+        Ok(None) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("Post not found".to_string()),
+                slug: None,
+            });
+        }
+        Err(e) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some(format!("Failed to load post: {}", e)),
+                slug: None,
+            });
+        }
     }
 
     match db::delete_post(&state.db, &slug).await {
         Ok(_) => {
             // Also delete markdown file
-            let _ = std::fs::remove_file(format!("posts/{}.md", slug));
+            if let Ok(path) = posts_dir::post_path(&slug) {
+                let _ = std::fs::remove_file(path);
+            }
+            state.pages.clear();
 
             Json(ApiResponse {
                 success: true,
@@ -328,28 +607,148 @@ pub async fn delete_post(
     }
 }
 
-fn save_post_to_file(post: &Post, content: &str) -> std::io::Result<()> {
-    let posts_dir = std::path::Path::new("posts");
-    if !posts_dir.exists() {
-        std::fs::create_dir_all(posts_dir)?;
+// API: Take a post back to draft
+pub async fn unpublish_post(
+    WriterSession(session): WriterSession,
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Json<ApiResponse> {
+    let mut post = match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if session.can_edit(&post) => post,
+        Ok(Some(_)) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("You can only unpublish your own posts".to_string()),
+                slug: None,
+            });
+        }
+        Ok(None) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("Post not found".to_string()),
+                slug: None,
+            });
+        }
+        Err(e) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some(format!("Failed to load post: {}", e)),
+                slug: None,
+            });
+        }
+    };
+
+    match db::set_post_published(&state.db, &slug, false).await {
+        Ok(_) => {
+            // Keep the markdown file in step, or the next restart republishes it
+            post.published = false;
+            if let Err(e) = sync::export_post(&state.db, &post).await {
+                tracing::error!("Failed to save {} to file: {}", post.slug, e);
+            }
+            state.pages.clear();
+
+            Json(ApiResponse {
+                success: true,
+                error: None,
+                slug: Some(slug),
+            })
+        }
+        Err(e) => Json(ApiResponse {
+            success: false,
+            error: Some(format!("Failed to unpublish: {}", e)),
+            slug: None,
+        }),
+    }
+}
+
+// API: Signed, expiring link for previewing a draft or scheduled post
+pub async fn preview_link(
+    WriterSession(session): WriterSession,
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+) -> Json<PreviewLinkResponse> {
+    let error = |message: String| {
+        Json(PreviewLinkResponse {
+            success: false,
+            error: Some(message),
+            url: None,
+            expires_at: None,
+        })
+    };
+
+    match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if session.can_edit(&post) => {}
+        Ok(Some(_)) => return error("You can only preview your own posts".to_string()),
+        Ok(None) => return error("Post not found".to_string()),
+        Err(e) => return error(format!("Failed to load post: {}", e)),
     }
 
-    let frontmatter = format!(
-        r#"---
-title: "{}"
-description: "{}"
-author: "{}"
-tags: [{}]
-published: true
----
-
-{}"#,
-        post.title,
-        post.description,
-        post.author,
-        post.tags.split(',').map(|t| format!("\"{}\"", t.trim())).collect::<Vec<_>>().join(", "),
-        content
+    let expires_at = Utc::now() + Duration::hours(state.config.preview_ttl_hours);
+    let signature = auth::sign_preview(&state.config.preview_secret, &slug, expires_at);
+    let url = format!(
+        "{}/preview/{}?expires={}&sig={}",
+        state.config.base_url,
+        slug,
+        expires_at.timestamp(),
+        signature
     );
 
-    std::fs::write(format!("posts/{}.md", post.slug), frontmatter)
+    Json(PreviewLinkResponse {
+        success: true,
+        error: None,
+        url: Some(url),
+        expires_at: Some(expires_at),
+    })
+}
+
+// API: Promote or demote a user
+pub async fn set_user_role(
+    AdminSession(session): AdminSession,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<i64>,
+    Json(req): Json<RoleRequest>,
+) -> Json<ApiResponse> {
+    // An account has the strongest role of its wallets, so changing one linked
+    // to your own account would change yours
+    match db::get_user_by_id(&state.db, user_id).await {
+        Ok(Some(user)) if user.account_id == session.account_id => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some("You cannot change your own role".to_string()),
+                slug: None,
+            });
+        }
+        Ok(_) => {}
+        Err(e) => {
+            return Json(ApiResponse {
+                success: false,
+                error: Some(format!("Failed to update role: {}", e)),
+                slug: None,
+            });
+        }
+    }
+
+    match db::set_wallet_user_type(&state.db, user_id, req.user_type).await {
+        Ok(true) => Json(ApiResponse {
+            success: true,
+            error: None,
+            slug: None,
+        }),
+        Ok(false) => Json(ApiResponse {
+            success: false,
+            error: Some("User not found".to_string()),
+            slug: None,
+        }),
+        Err(e) => Json(ApiResponse {
+            success: false,
+            error: Some(format!("Failed to update role: {}", e)),
+            slug: None,
+        }),
+    }
+}
+
+async fn record_revision(state: &AppState, slug: &str, editor_wallet: &str) {
+    if let Err(e) = db::create_revision(&state.db, slug, Some(editor_wallet), None).await {
+        tracing::error!("Failed to record revision for {}: {}", slug, e);
+    }
 }
@@ -0,0 +1,13 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+use std::sync::Arc;
+
+use crate::AppState;
+
+/// Stylesheet for highlighted code, generated at startup from the configured
+/// light and dark themes.
+pub async fn syntax_css(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/css; charset=utf-8")],
+        state.syntax_css.clone(),
+    )
+}
@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use std::sync::Arc;
+
+use crate::{
+    db,
+    feed::{self, FeedInfo},
+    handlers::posts::tag_path,
+    page_cache::Page,
+    AppState,
+};
+
+/// Posts included in each feed.
+const FEED_ITEMS: i64 = 20;
+
+#[derive(Clone, Copy)]
+enum FeedFormat {
+    Rss,
+    Atom,
+    Json,
+}
+
+impl FeedFormat {
+    fn file_name(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "feed.xml",
+            FeedFormat::Atom => "atom.xml",
+            FeedFormat::Json => "feed.json",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "application/rss+xml; charset=utf-8",
+            FeedFormat::Atom => "application/atom+xml; charset=utf-8",
+            FeedFormat::Json => "application/feed+json; charset=utf-8",
+        }
+    }
+}
+
+async fn render(
+    state: &AppState,
+    headers: &HeaderMap,
+    format: FeedFormat,
+    tag: Option<String>,
+) -> Response {
+    let base_url = &state.config.base_url;
+
+    let (info, posts) = match tag {
+        None => (
+            FeedInfo {
+                title: state.config.blog_title.clone(),
+                description: state.config.blog_description.clone(),
+                home_url: format!("{}/", base_url),
+                feed_url: format!("{}/{}", base_url, format.file_name()),
+                base_url: base_url.clone(),
+            },
+            db::get_posts_page(&state.db, FEED_ITEMS, 0).await,
+        ),
+        Some(name) => {
+            let tag = match db::get_tag_name(&state.db, &name).await {
+                Ok(Some(tag)) => tag,
+                _ => {
+                    return (
+                        StatusCode::NOT_FOUND,
+                        Html("<h1>Tag not found</h1>".to_string()),
+                    )
+                        .into_response()
+                }
+            };
+            let tag_url = format!("{}{}", base_url, tag_path(&tag));
+            (
+                FeedInfo {
+                    title: format!("{}: {}", state.config.blog_title, tag),
+                    description: format!("Posts tagged {} on {}", tag, state.config.blog_title),
+                    feed_url: format!("{}/{}", tag_url, format.file_name()),
+                    home_url: tag_url,
+                    base_url: base_url.clone(),
+                },
+                db::get_posts_by_tag(&state.db, &tag, FEED_ITEMS, 0).await,
+            )
+        }
+    };
+
+    let posts = match posts {
+        Ok(posts) => posts,
+        Err(e) => {
+            tracing::error!("Failed to load posts for feed: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let body = match format {
+        FeedFormat::Rss => feed::rss(&info, &posts),
+        FeedFormat::Atom => feed::atom(&info, &posts),
+        FeedFormat::Json => feed::json_feed(&info, &posts),
+    };
+    // Validated by ETag only: removing or unpublishing a post moves no date,
+    // so a Last-Modified would let readers keep a stale copy
+    Page::new(body, None).respond(headers, format.content_type())
+}
+
+pub async fn rss(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    render(&state, &headers, FeedFormat::Rss, None).await
+}
+
+pub async fn atom(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    render(&state, &headers, FeedFormat::Atom, None).await
+}
+
+pub async fn json(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    render(&state, &headers, FeedFormat::Json, None).await
+}
+
+pub async fn tag_rss(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    render(&state, &headers, FeedFormat::Rss, Some(tag)).await
+}
+
+pub async fn tag_atom(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    render(&state, &headers, FeedFormat::Atom, Some(tag)).await
+}
+
+pub async fn tag_json(
+    State(state): State<Arc<AppState>>,
+    Path(tag): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    render(&state, &headers, FeedFormat::Json, Some(tag)).await
+}
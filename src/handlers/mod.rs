@@ -1,5 +1,10 @@
 pub mod activity;
 pub mod admin;
+pub mod assets;
+pub mod feeds;
 pub mod posts;
+pub mod revisions;
+pub mod search;
+pub mod seo;
 pub mod tips;
 pub mod wallet;
@@ -1,11 +1,29 @@
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    response::Html,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    Json,
 };
-use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
 
-use crate::{db, models::Post, AppState};
+use crate::{
+    auth, db, feed,
+    markdown::TocEntry,
+    models::{Post, PostCursor, TagCount},
+    page_cache::{self, Page},
+    seo,
+    AppState,
+};
+
+/// Posts per page on `/posts` and tag pages.
+const POSTS_PER_PAGE: i64 = 10;
+/// Latest posts shown on the home page.
+const INDEX_POSTS: i64 = 9;
+const API_DEFAULT_LIMIT: i64 = 10;
+const API_MAX_LIMIT: i64 = 50;
 
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -13,6 +31,22 @@ pub struct IndexTemplate {
     pub title: String,
     pub description: String,
     pub posts: Vec<Post>,
+    pub tags: Vec<TagCount>,
+    pub has_more: bool,
+}
+
+impl IndexTemplate {
+    /// Text size for a tag in the tag cloud, scaled by its share of the most
+    /// used tag.
+    pub fn tag_size(&self, tag: &TagCount) -> &'static str {
+        let max = self.tags.iter().map(|t| t.count).max().unwrap_or(1).max(1);
+        match tag.count * 4 / max {
+            4 => "text-lg",
+            3 => "text-base",
+            2 => "text-sm",
+            _ => "text-xs",
+        }
+    }
 }
 
 #[derive(Template)]
@@ -24,6 +58,55 @@ pub struct PostTemplate {
     pub author_avax_address: String,
     pub author_sol_address: String,
     pub author_btc_address: String,
+    pub is_preview: bool,
+    pub site_name: String,
+    pub canonical_url: String,
+    /// `BlogPosting` structured data, already escaped for a `<script>` tag
+    pub json_ld: String,
+}
+
+impl PostTemplate {
+    fn new(state: &AppState, post: Post, is_preview: bool) -> Self {
+        let canonical_url = format!("{}/post/{}", state.config.base_url, post.slug);
+        let title = if is_preview {
+            format!("Preview: {} | {}", post.title, state.config.blog_title)
+        } else {
+            format!("{} | {}", post.title, state.config.blog_title)
+        };
+
+        PostTemplate {
+            title,
+            json_ld: seo::blog_posting_json_ld(&post, &canonical_url, &state.config),
+            canonical_url,
+            site_name: state.config.blog_title.clone(),
+            post,
+            author_eth_address: state.config.author_eth_address.clone(),
+            author_avax_address: state.config.author_avax_address.clone(),
+            author_sol_address: state.config.author_sol_address.clone(),
+            author_btc_address: state.config.author_btc_address.clone(),
+            is_preview,
+        }
+    }
+
+    /// Headings for the table of contents; posts with fewer than two get none.
+    fn toc(&self) -> &[TocEntry] {
+        match self.post.toc.as_slice() {
+            toc if toc.len() >= 2 => toc,
+            _ => &[],
+        }
+    }
+
+    /// Indentation of a TOC entry, relative to the post's top heading level.
+    fn toc_indent(&self, entry: &TocEntry) -> u8 {
+        let top = self.toc().iter().map(|e| e.level).min().unwrap_or(entry.level);
+        entry.level.saturating_sub(top)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub expires: i64,
+    pub sig: String,
 }
 
 #[derive(Template)]
@@ -31,47 +114,340 @@ pub struct PostTemplate {
 pub struct PostsTemplate {
     pub title: String,
     pub posts: Vec<Post>,
+    /// Set when listing the posts of one tag
+    pub tag: Option<String>,
+    /// Path the page links are built on, e.g. `/posts` or `/tag/rust`
+    pub base_path: String,
+    pub page: i64,
+    pub total_pages: i64,
+}
+
+impl PostsTemplate {
+    fn page_url(&self, page: i64) -> String {
+        if page <= 1 {
+            self.base_path.clone()
+        } else {
+            format!("{}?page={}", self.base_path, page)
+        }
+    }
+
+    pub fn prev_url(&self) -> Option<String> {
+        (self.page > 1).then(|| self.page_url(self.page - 1))
+    }
+
+    pub fn next_url(&self) -> Option<String> {
+        (self.page < self.total_pages).then(|| self.page_url(self.page + 1))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    pub page: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ViewsQuery {
+    pub slugs: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CursorQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// A post in `/api/posts`, without its body.
+#[derive(Debug, Serialize)]
+pub struct PostSummary {
+    pub slug: String,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub reading_time: u32,
+    pub views: i64,
+}
+
+impl From<Post> for PostSummary {
+    fn from(post: Post) -> Self {
+        PostSummary {
+            url: format!("/post/{}", post.slug),
+            tags: post.tags_list().into_iter().map(String::from).collect(),
+            reading_time: post.reading_time(),
+            slug: post.slug,
+            title: post.title,
+            description: post.description,
+            author: post.author,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+            views: post.views,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PostsResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub posts: Vec<PostSummary>,
+    /// Pass as `cursor` to get the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Total pages for `total` posts; an empty listing still has one page.
+fn page_count(total: i64) -> i64 {
+    ((total + POSTS_PER_PAGE - 1) / POSTS_PER_PAGE).max(1)
 }
 
-pub async fn index(State(state): State<Arc<AppState>>) -> Html<String> {
-    let posts = db::get_all_posts(&state.db).await.unwrap_or_default();
+/// `/tag/<name>` with the name percent-encoded as a path segment.
+pub(crate) fn tag_path(tag: &str) -> String {
+    let mut url = url::Url::parse("http://localhost/tag/").expect("valid base URL");
+    url.path_segments_mut()
+        .expect("base URL has a path")
+        .pop_if_empty()
+        .push(tag);
+    url.path().to_string()
+}
+
+fn page_not_found() -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Html("<h1>Page not found</h1>".to_string()),
+    )
+        .into_response()
+}
 
+pub async fn index(State(state): State<Arc<AppState>>, headers: HeaderMap) -> Response {
+    if let Some(page) = state.pages.get("/") {
+        return page.respond(&headers, page_cache::HTML);
+    }
+    let generation = state.pages.generation();
+
+    // One extra to know whether to link to the full list
+    let mut posts = db::get_posts_page(&state.db, INDEX_POSTS + 1, 0)
+        .await
+        .unwrap_or_default();
+    let has_more = posts.len() as i64 > INDEX_POSTS;
+    posts.truncate(INDEX_POSTS as usize);
+    let tags = db::get_tag_counts(&state.db, true)
+        .await
+        .unwrap_or_default();
+    let last_modified = feed::last_updated(&posts);
     let template = IndexTemplate {
         title: state.config.blog_title.clone(),
         description: state.config.blog_description.clone(),
         posts,
+        tags,
+        has_more,
     };
 
-    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+    let body = template.render().unwrap_or_else(|e| format!("Error: {}", e));
+    state
+        .pages
+        .insert("/", generation, Page::new(body, last_modified))
+        .respond(&headers, page_cache::HTML)
 }
 
-pub async fn show(State(state): State<Arc<AppState>>, Path(slug): Path<String>) -> Html<String> {
+/// A published post. Pages are cached by slug and modification date, so a
+/// visit only loads and renders the post after it changed. View counts
+/// change on every visit, so they aren't part of the page; it fetches them
+/// from [`api_views`].
+pub async fn show(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let modified = match db::get_post_modified_at(&state.db, &slug).await {
+        Ok(Some(modified)) => modified,
+        _ => return Html("<h1>Post not found</h1>".to_string()).into_response(),
+    };
+    let _ = db::increment_post_views(&state.db, &slug).await;
+
+    let key = format!("/post/{}@{}", slug, modified.timestamp_micros());
+    if let Some(page) = state.pages.get(&key) {
+        return page.respond(&headers, page_cache::HTML);
+    }
+    let generation = state.pages.generation();
+
     match db::get_post_by_slug(&state.db, &slug).await {
         Ok(Some(post)) => {
-            // Increment view count
-            let _ = db::increment_post_views(&state.db, &slug).await;
-
-            let template = PostTemplate {
-                title: format!("{} | {}", post.title, state.config.blog_title),
-                post,
-                author_eth_address: state.config.author_eth_address.clone(),
-                author_avax_address: state.config.author_avax_address.clone(),
-                author_sol_address: state.config.author_sol_address.clone(),
-                author_btc_address: state.config.author_btc_address.clone(),
+            let last_modified = Some(post.modified_at());
+            let template = PostTemplate::new(&state, post, false);
+            let body = template.render().unwrap_or_else(|e| format!("Error: {}", e));
+            state
+                .pages
+                .insert(&key, generation, Page::new(body, last_modified))
+                .respond(&headers, page_cache::HTML)
+        }
+        _ => Html("<h1>Post not found</h1>".to_string()).into_response(),
+    }
+}
+
+/// Render a draft or scheduled post from a signed preview link. Views are
+/// not counted and search engines are told not to index the page.
+pub async fn preview(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    Query(query): Query<PreviewQuery>,
+) -> Response {
+    if !auth::verify_preview(&state.config.preview_secret, &slug, query.expires, &query.sig) {
+        return (
+            StatusCode::FORBIDDEN,
+            Html("<h1>Preview link is invalid or has expired</h1>".to_string()),
+        )
+            .into_response();
+    }
+
+    match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) => {
+            let template = PostTemplate::new(&state, post, true);
+            (
+                [("x-robots-tag", "noindex, nofollow")],
+                Html(template.render().unwrap_or_else(|e| format!("Error: {}", e))),
+            )
+                .into_response()
+        }
+        _ => (
+            StatusCode::NOT_FOUND,
+            Html("<h1>Post not found</h1>".to_string()),
+        )
+            .into_response(),
+    }
+}
+
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    let page = query.page.unwrap_or(1).max(1);
+    let total_pages = page_count(db::count_published_posts(&state.db).await.unwrap_or(0));
+    if page > total_pages {
+        return page_not_found();
+    }
+
+    let posts = db::get_posts_page(&state.db, POSTS_PER_PAGE, (page - 1) * POSTS_PER_PAGE)
+        .await
+        .unwrap_or_default();
+
+    let template = PostsTemplate {
+        title: if page > 1 {
+            format!("All Posts - Page {} | {}", page, state.config.blog_title)
+        } else {
+            format!("All Posts | {}", state.config.blog_title)
+        },
+        posts,
+        tag: None,
+        base_path: "/posts".to_string(),
+        page,
+        total_pages,
+    };
+
+    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e))).into_response()
+}
+
+// API: Published posts, newest first, paged with an opaque cursor
+pub async fn api_list(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CursorQuery>,
+) -> Json<PostsResponse> {
+    let cursor = match query.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(value) => match PostCursor::decode(value) {
+            Some(cursor) => Some(cursor),
+            None => {
+                return Json(PostsResponse {
+                    success: false,
+                    error: Some("Invalid cursor".to_string()),
+                    posts: Vec::new(),
+                    next_cursor: None,
+                })
+            }
+        },
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(API_DEFAULT_LIMIT)
+        .clamp(1, API_MAX_LIMIT);
+
+    // One extra to know whether there is a next page
+    match db::get_posts_before(&state.db, cursor.as_ref(), limit + 1).await {
+        Ok(mut posts) => {
+            let next_cursor = if posts.len() as i64 > limit {
+                posts.truncate(limit as usize);
+                posts.last().map(|p| PostCursor::after(p).encode())
+            } else {
+                None
             };
-            Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+            Json(PostsResponse {
+                success: true,
+                error: None,
+                posts: posts.into_iter().map(PostSummary::from).collect(),
+                next_cursor,
+            })
         }
-        _ => Html("<h1>Post not found</h1>".to_string()),
+        Err(e) => Json(PostsResponse {
+            success: false,
+            error: Some(format!("Failed to load posts: {}", e)),
+            posts: Vec::new(),
+            next_cursor: None,
+        }),
     }
 }
 
-pub async fn list(State(state): State<Arc<AppState>>) -> Html<String> {
-    let posts = db::get_all_posts(&state.db).await.unwrap_or_default();
+/// API: view counts of the comma-separated `slugs`, by slug. Cached pages
+/// show them by calling this once loaded.
+pub async fn api_views(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ViewsQuery>,
+) -> Json<HashMap<String, i64>> {
+    let slugs: Vec<&str> = query
+        .slugs
+        .split(',')
+        .map(str::trim)
+        .filter(|slug| !slug.is_empty())
+        .take(API_MAX_LIMIT as usize)
+        .collect();
+
+    Json(db::get_post_views(&state.db, &slugs).await.unwrap_or_default())
+}
+
+pub async fn tag(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Response {
+    let tag = match db::get_tag_name(&state.db, &name).await {
+        Ok(Some(tag)) => tag,
+        _ => {
+            return (
+                StatusCode::NOT_FOUND,
+                Html("<h1>Tag not found</h1>".to_string()),
+            )
+                .into_response()
+        }
+    };
+    let page = query.page.unwrap_or(1).max(1);
+    let total_pages = page_count(db::count_posts_by_tag(&state.db, &tag).await.unwrap_or(0));
+    if page > total_pages {
+        return page_not_found();
+    }
+
+    let posts = db::get_posts_by_tag(&state.db, &tag, POSTS_PER_PAGE, (page - 1) * POSTS_PER_PAGE)
+        .await
+        .unwrap_or_default();
 
     let template = PostsTemplate {
-        title: format!("All Posts | {}", state.config.blog_title),
+        title: format!("Posts tagged {} | {}", tag, state.config.blog_title),
         posts,
+        base_path: tag_path(&tag),
+        tag: Some(tag),
+        page,
+        total_pages,
     };
 
-    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e))).into_response()
 }
@@ -0,0 +1,220 @@
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    response::Html,
+    Json,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use std::sync::Arc;
+
+use crate::{
+    auth::WriterSession,
+    db,
+    handlers::admin::ApiResponse,
+    models::{Post, PostRevision},
+    sync, AppState,
+};
+
+/// Lines of unchanged context shown around each change.
+const DIFF_CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct CompareQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// One row of a rendered line diff.
+pub struct DiffLine {
+    /// "add", "del", "ctx", or "hunk" for the separator between hunks
+    pub kind: &'static str,
+    pub old_no: Option<usize>,
+    pub new_no: Option<usize>,
+    pub text: String,
+}
+
+/// A frontmatter field that differs between two revisions.
+pub struct FieldChange {
+    pub name: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/revisions.html")]
+pub struct RevisionsTemplate {
+    pub post: Post,
+    pub revisions: Vec<PostRevision>,
+    pub from_id: i64,
+    pub to_id: i64,
+    pub field_changes: Vec<FieldChange>,
+    pub diff: Vec<DiffLine>,
+}
+
+impl RevisionsTemplate {
+    pub fn short_wallet(&self, revision: &PostRevision) -> String {
+        match revision.editor_wallet.as_deref() {
+            Some(w) if w.len() > 10 => format!("{}...{}", &w[..6], &w[w.len() - 4..]),
+            Some(w) => w.to_string(),
+            None => "file".to_string(),
+        }
+    }
+}
+
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut lines = Vec::new();
+
+    for (i, group) in diff.grouped_ops(DIFF_CONTEXT_LINES).iter().enumerate() {
+        if i > 0 {
+            lines.push(DiffLine {
+                kind: "hunk",
+                old_no: None,
+                new_no: None,
+                text: String::new(),
+            });
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                lines.push(DiffLine {
+                    kind: match change.tag() {
+                        ChangeTag::Delete => "del",
+                        ChangeTag::Insert => "add",
+                        ChangeTag::Equal => "ctx",
+                    },
+                    old_no: change.old_index().map(|n| n + 1),
+                    new_no: change.new_index().map(|n| n + 1),
+                    text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+                });
+            }
+        }
+    }
+
+    lines
+}
+
+fn field_changes(old: &PostRevision, new: &PostRevision) -> Vec<FieldChange> {
+    [
+        ("Title", &old.title, &new.title),
+        ("Description", &old.description, &new.description),
+        ("Author", &old.author, &new.author),
+        ("Tags", &old.tags, &new.tags),
+    ]
+    .into_iter()
+    .filter(|(_, old, new)| old != new)
+    .map(|(name, old, new)| FieldChange {
+        name,
+        old: old.clone(),
+        new: new.clone(),
+    })
+    .collect()
+}
+
+// Revision history of a post, with a diff between two revisions
+pub async fn list(
+    WriterSession(session): WriterSession,
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    Query(query): Query<CompareQuery>,
+) -> Html<String> {
+    let post = match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if session.can_edit(&post) => post,
+        Ok(Some(_)) => return Html("<h1>You can only view your own posts</h1>".to_string()),
+        _ => return Html("<h1>Post not found</h1>".to_string()),
+    };
+
+    let revisions = db::get_revisions(&state.db, post.id)
+        .await
+        .unwrap_or_default();
+
+    // Default to the latest revision against the one before it
+    let find = |id: Option<i64>| id.and_then(|id| revisions.iter().position(|r| r.id == id));
+    let to = find(query.to).unwrap_or(0);
+    let from = find(query.from).unwrap_or((to + 1).min(revisions.len().saturating_sub(1)));
+
+    let (field_changes, diff) = match (revisions.get(from), revisions.get(to)) {
+        (Some(old), Some(new)) => (
+            field_changes(old, new),
+            diff_lines(&old.content, &new.content),
+        ),
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    let template = RevisionsTemplate {
+        from_id: revisions.get(from).map(|r| r.id).unwrap_or_default(),
+        to_id: revisions.get(to).map(|r| r.id).unwrap_or_default(),
+        post,
+        revisions,
+        field_changes,
+        diff,
+    };
+    Html(template.render().unwrap_or_else(|e| format!("Error: {}", e)))
+}
+
+// API: Restore a post to an earlier revision (recorded as a new revision)
+pub async fn restore(
+    WriterSession(session): WriterSession,
+    State(state): State<Arc<AppState>>,
+    Path((slug, revision_id)): Path<(String, i64)>,
+) -> Json<ApiResponse> {
+    let error = |message: String| {
+        Json(ApiResponse {
+            success: false,
+            error: Some(message),
+            slug: None,
+        })
+    };
+
+    let existing = match db::get_any_post_by_slug(&state.db, &slug).await {
+        Ok(Some(post)) if session.can_edit(&post) => post,
+        Ok(Some(_)) => return error("You can only restore your own posts".to_string()),
+        Ok(None) => return error("Post not found".to_string()),
+        Err(e) => return error(format!("Failed to load post: {}", e)),
+    };
+
+    let revision = match db::get_revision(&state.db, revision_id).await {
+        Ok(Some(revision)) if revision.post_id == existing.id => revision,
+        Ok(_) => return error("Revision not found".to_string()),
+        Err(e) => return error(format!("Failed to load revision: {}", e)),
+    };
+
+    let rendered = match state.parser.render(&revision.content) {
+        Ok(rendered) => rendered,
+        Err(e) => return error(format!("Failed to parse markdown: {}", e)),
+    };
+
+    let post = Post {
+        title: revision.title,
+        description: revision.description,
+        author: revision.author,
+        tags: revision.tags,
+        content: revision.content,
+        html_content: rendered.html,
+        toc: sqlx::types::Json(rendered.toc),
+        updated_at: Utc::now(),
+        ..existing
+    };
+
+    if let Err(e) = db::upsert_post(&state.db, &post).await {
+        return error(format!("Failed to save: {}", e));
+    }
+    state.pages.clear();
+    if let Err(e) = sync::export_post(&state.db, &post).await {
+        tracing::error!("Failed to save {} to file: {}", slug, e);
+    }
+
+    let note = format!("Restored revision #{}", revision_id);
+    if let Err(e) =
+        db::create_revision(&state.db, &slug, Some(&session.wallet_address), Some(&note)).await
+    {
+        tracing::error!("Failed to record revision for {}: {}", slug, e);
+    }
+
+    Json(ApiResponse {
+        success: true,
+        error: None,
+        slug: Some(slug),
+    })
+}
@@ -0,0 +1,154 @@
+use askama::Template;
+use axum::{
+    extract::{Query, State},
+    response::Html,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    db::{self, SearchHit, SEARCH_MATCH_END, SEARCH_MATCH_START},
+    models::SearchQuery,
+    AppState,
+};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    #[serde(default)]
+    pub q: String,
+    pub limit: Option<i64>,
+}
+
+/// A search hit ready for display. `title_html` and `snippet_html` are
+/// escaped, with matched terms wrapped in `<mark>`.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub slug: String,
+    pub url: String,
+    pub title: String,
+    pub description: String,
+    pub title_html: String,
+    pub snippet_html: String,
+    pub author: String,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub score: f64,
+}
+
+impl From<SearchHit> for SearchResult {
+    fn from(hit: SearchHit) -> Self {
+        SearchResult {
+            url: format!("/post/{}", hit.slug),
+            title_html: marked_to_html(&hit.title_marked),
+            snippet_html: marked_to_html(&hit.snippet_marked),
+            tags: hit
+                .tags
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect(),
+            slug: hit.slug,
+            title: hit.title,
+            description: hit.description,
+            author: hit.author,
+            created_at: hit.created_at,
+            // bm25 is negative with lower being better; flip it for readers
+            score: -hit.score,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub query: String,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Template)]
+#[template(path = "search.html")]
+pub struct SearchTemplate {
+    pub title: String,
+    pub q: String,
+    pub query: SearchQuery,
+    pub results: Vec<SearchResult>,
+    pub error: Option<String>,
+}
+
+fn marked_to_html(marked: &str) -> String {
+    html_escape::encode_text(marked)
+        .replace(SEARCH_MATCH_START, "<mark>")
+        .replace(SEARCH_MATCH_END, "</mark>")
+}
+
+async fn run_search(
+    state: &AppState,
+    query: &SearchQuery,
+    limit: i64,
+) -> Result<Vec<SearchResult>, String> {
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    db::search_posts(&state.db, query, limit.clamp(1, MAX_LIMIT))
+        .await
+        .map(|hits| hits.into_iter().map(SearchResult::from).collect())
+        .map_err(|e| {
+            tracing::error!("Search for {:?} failed: {}", query, e);
+            "Search failed".to_string()
+        })
+}
+
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+) -> Html<String> {
+    let query = SearchQuery::parse(&params.q);
+    let (results, error) =
+        match run_search(&state, &query, params.limit.unwrap_or(DEFAULT_LIMIT)).await {
+            Ok(results) => (results, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+
+    let template = SearchTemplate {
+        title: format!("Search - {}", state.config.blog_title),
+        q: params.q,
+        query,
+        results,
+        error,
+    };
+    Html(
+        template
+            .render()
+            .unwrap_or_else(|e| format!("Error: {}", e)),
+    )
+}
+
+// API: Search published posts
+pub async fn api(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+) -> Json<SearchResponse> {
+    let query = SearchQuery::parse(&params.q);
+    match run_search(&state, &query, params.limit.unwrap_or(DEFAULT_LIMIT)).await {
+        Ok(results) => Json(SearchResponse {
+            success: true,
+            error: None,
+            query: params.q,
+            results,
+        }),
+        Err(e) => Json(SearchResponse {
+            success: false,
+            error: Some(e),
+            query: params.q,
+            results: Vec::new(),
+        }),
+    }
+}
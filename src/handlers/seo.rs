@@ -0,0 +1,75 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    db,
+    handlers::posts::tag_path,
+    models::normalize_tag,
+    seo::{self, SitemapUrl},
+    AppState,
+};
+
+pub async fn sitemap(State(state): State<Arc<AppState>>) -> Response {
+    let posts = match db::get_all_published_posts(&state.db).await {
+        Ok(posts) => posts,
+        Err(e) => {
+            tracing::error!("Failed to load posts for sitemap: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let base_url = &state.config.base_url;
+    let last_modified = crate::feed::last_updated(&posts);
+
+    let mut urls = vec![
+        SitemapUrl {
+            loc: format!("{}/", base_url),
+            lastmod: last_modified,
+        },
+        SitemapUrl {
+            loc: format!("{}/posts", base_url),
+            lastmod: last_modified,
+        },
+    ];
+
+    // A tag page changes whenever one of its posts does
+    let mut tag_modified = HashMap::new();
+    for post in &posts {
+        let modified = post.modified_at();
+        for tag in post.tags_list() {
+            let entry = tag_modified.entry(normalize_tag(tag)).or_insert(modified);
+            *entry = (*entry).max(modified);
+        }
+        urls.push(SitemapUrl {
+            loc: format!("{}/post/{}", base_url, post.slug),
+            lastmod: Some(modified),
+        });
+    }
+
+    let tags = db::get_tag_counts(&state.db, true)
+        .await
+        .unwrap_or_default();
+    for tag in tags {
+        urls.push(SitemapUrl {
+            loc: format!("{}{}", base_url, tag_path(&tag.name)),
+            lastmod: tag_modified.get(&tag.name).copied(),
+        });
+    }
+
+    (
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        seo::sitemap(&urls),
+    )
+        .into_response()
+}
+
+pub async fn robots(State(state): State<Arc<AppState>>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        seo::robots_txt(&state.config),
+    )
+        .into_response()
+}
@@ -1,12 +1,24 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json},
 };
+use chrono::{Duration, Utc};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::{db, web3, AppState};
+use crate::{
+    auth, db,
+    models::{Session, User, UserType},
+    web3::{
+        self,
+        sign_in::{Expected, MessageParams, SignInError},
+    },
+    AppState,
+};
+
+/// How long a nonce (and the message built around it) stays valid.
+const NONCE_TTL_MINUTES: i64 = 10;
 
 #[derive(Debug, Deserialize)]
 pub struct NonceRequest {
@@ -32,7 +44,6 @@ pub struct VerifyRequest {
 pub struct VerifyResponse {
     pub success: bool,
     pub message: String,
-    pub session_token: Option<String>,
 }
 
 pub async fn get_nonce(
@@ -43,14 +54,28 @@ pub async fn get_nonce(
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let new_nonce = uuid::Uuid::new_v4().to_string();
+    let new_nonce = siwe::generate_nonce();
     db::update_user_nonce(&state.db, user.id, &new_nonce)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    let domain = state.config.domain();
+    let chain_id = chain_id(&state, &req.wallet_type).ok_or(StatusCode::BAD_REQUEST)?;
+    let issued_at = Utc::now();
+    let params = MessageParams {
+        domain: &domain,
+        uri: &state.config.base_url,
+        chain_id: &chain_id,
+        statement: &format!("Sign in to {}", state.config.blog_title),
+        nonce: &new_nonce,
+        issued_at,
+        expiration_time: issued_at + Duration::minutes(NONCE_TTL_MINUTES),
+    };
+
     let message = match req.wallet_type.as_str() {
-        "ethereum" => web3::ethereum::create_siwe_message(&req.wallet_address, &new_nonce),
-        "solana" => web3::solana::create_siws_message(&req.wallet_address, &new_nonce),
+        "ethereum" => web3::ethereum::create_siwe_message(&req.wallet_address, &params)
+            .map_err(|_| StatusCode::BAD_REQUEST)?,
+        "solana" => web3::solana::create_siws_message(&req.wallet_address, &params),
         _ => return Err(StatusCode::BAD_REQUEST),
     };
 
@@ -60,18 +85,35 @@ pub async fn get_nonce(
     }))
 }
 
-pub async fn verify_signature(
-    State(state): State<Arc<AppState>>,
-    Json(req): Json<VerifyRequest>,
-) -> Result<Json<VerifyResponse>, StatusCode> {
+/// Result of checking a signed sign-in message against the wallet's nonce.
+enum SignInOutcome {
+    Verified(User),
+    Rejected(String),
+}
+
+/// Verify the signed message and signature for `req`, consuming the nonce.
+/// Shared by sign-in and wallet linking.
+async fn authenticate(state: &AppState, req: &VerifyRequest) -> Result<SignInOutcome, StatusCode> {
     let user = db::get_or_create_user(&state.db, &req.wallet_address, &req.wallet_type)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    if let Err(e) = check_message(state, req, &user) {
+        return Ok(SignInOutcome::Rejected(format!(
+            "Invalid sign-in message: {}",
+            e
+        )));
+    }
+
     let is_valid = match req.wallet_type.as_str() {
         "ethereum" => {
-            web3::ethereum::verify_signature(&req.wallet_address, &req.message, &req.signature)
-                .await
+            web3::ethereum::verify_signature(
+                &state.config.eth_rpc_url,
+                &req.wallet_address,
+                &req.message,
+                &req.signature,
+            )
+            .await
         }
         "solana" => {
             web3::solana::verify_signature(&req.wallet_address, &req.message, &req.signature)
@@ -80,22 +122,241 @@ pub async fn verify_signature(
     };
 
     if !is_valid {
-        return Ok(Json(VerifyResponse {
-            success: false,
-            message: "Invalid signature".to_string(),
-            session_token: None,
-        }));
+        return Ok(SignInOutcome::Rejected("Invalid signature".to_string()));
+    }
+
+    // Consume the nonce so a captured signature cannot be replayed
+    let consumed = db::consume_user_nonce(&state.db, user.id, &user.nonce)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !consumed {
+        return Ok(SignInOutcome::Rejected("Nonce already used".to_string()));
     }
 
+    Ok(SignInOutcome::Verified(user))
+}
+
+pub async fn verify_signature(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<VerifyRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let user = match authenticate(&state, &req).await? {
+        SignInOutcome::Verified(user) => user,
+        SignInOutcome::Rejected(message) => {
+            return Ok(Json(VerifyResponse {
+                success: false,
+                message,
+            })
+            .into_response());
+        }
+    };
+
     db::update_user_login(&state.db, user.id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
+    if user.user_type != UserType::Admin
+        && state.config.is_bootstrap_admin(&req.wallet_address)
+        && should_bootstrap(&state, &user).await?
+    {
+        db::set_wallet_user_type(&state.db, user.id, UserType::Admin)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        tracing::info!("Promoted bootstrap admin {}", req.wallet_address);
+    }
+
     let session_token = uuid::Uuid::new_v4().to_string();
+    let ttl = Duration::hours(state.config.session_ttl_hours);
+
+    db::create_session(&state.db, user.id, &session_token, Utc::now() + ttl)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to create session: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let cookie = auth::session_cookie(
+        &session_token,
+        ttl.num_seconds(),
+        state.config.session_cookie_secure,
+    );
+
+    Ok((
+        [(header::SET_COOKIE, cookie)],
+        Json(VerifyResponse {
+            success: true,
+            message: "Authentication successful".to_string(),
+        }),
+    )
+        .into_response())
+}
+
+/// A bootstrap wallet is promoted on its first sign-in, or while there is
+/// no admin at all. Later sign-ins leave it alone, so an admin can demote it.
+async fn should_bootstrap(state: &AppState, user: &User) -> Result<bool, StatusCode> {
+    if user.last_login.is_none() {
+        return Ok(true);
+    }
+    db::has_admin(&state.db)
+        .await
+        .map(|has_admin| !has_admin)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn chain_id(state: &AppState, wallet_type: &str) -> Option<String> {
+    match wallet_type {
+        "ethereum" => Some(state.config.eth_chain_id.to_string()),
+        "solana" => Some(state.config.sol_cluster.clone()),
+        _ => None,
+    }
+}
+
+/// Check that the signed message is the one issued by `get_nonce` for this
+/// user: right domain, URI and chain, the user's current nonce, and recent.
+fn check_message(state: &AppState, req: &VerifyRequest, user: &User) -> Result<(), SignInError> {
+    let fields = match req.wallet_type.as_str() {
+        "ethereum" => web3::ethereum::parse_siwe_message(&req.message)?,
+        "solana" => web3::solana::parse_siws_message(&req.message)?,
+        _ => return Err(SignInError::ChainId),
+    };
+
+    let now = Utc::now();
+    let max_age = Duration::minutes(NONCE_TTL_MINUTES);
+    match user.nonce_issued_at {
+        Some(issued) if now - issued <= max_age => {}
+        Some(_) => return Err(SignInError::Expired),
+        None => return Err(SignInError::Nonce),
+    }
 
-    Ok(Json(VerifyResponse {
+    let domain = state.config.domain();
+    let chain_id = chain_id(state, &req.wallet_type).ok_or(SignInError::ChainId)?;
+    fields.validate(
+        &Expected {
+            domain: &domain,
+            uri: &state.config.base_url,
+            chain_id: &chain_id,
+            address: &req.wallet_address,
+            nonce: &user.nonce,
+            max_age,
+        },
+        now,
+    )
+}
+
+#[derive(Debug, Serialize)]
+pub struct WalletsResponse {
+    pub success: bool,
+    pub message: Option<String>,
+    pub wallets: Vec<db::LinkedWallet>,
+}
+
+impl WalletsResponse {
+    fn error(message: impl Into<String>) -> Json<Self> {
+        Json(WalletsResponse {
+            success: false,
+            message: Some(message.into()),
+            wallets: Vec::new(),
+        })
+    }
+}
+
+/// POST /api/auth/link - Prove ownership of another wallet and attach it to
+/// the signed-in account. The body is the same signed nonce message as
+/// `/verify`, produced by the wallet being linked.
+pub async fn link_wallet(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    Json(req): Json<VerifyRequest>,
+) -> Result<Json<WalletsResponse>, StatusCode> {
+    let user = match authenticate(&state, &req).await? {
+        SignInOutcome::Verified(user) => user,
+        SignInOutcome::Rejected(message) => return Ok(WalletsResponse::error(message)),
+    };
+
+    if user.account_id != session.account_id {
+        db::link_user_to_account(&state.db, user.id, session.account_id)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to link wallet: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        tracing::info!(
+            "Linked {} wallet {} to account {}",
+            req.wallet_type,
+            req.wallet_address,
+            session.account_id
+        );
+    }
+
+    list_wallets(State(state), session).await
+}
+
+/// GET /api/auth/wallets - List the wallets linked to the signed-in account
+pub async fn list_wallets(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+) -> Result<Json<WalletsResponse>, StatusCode> {
+    let wallets = db::get_account_wallets(&state.db, session.account_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(WalletsResponse {
         success: true,
-        message: "Authentication successful".to_string(),
-        session_token: Some(session_token),
+        message: None,
+        wallets,
     }))
 }
+
+/// DELETE /api/auth/wallets/:id - Detach a linked wallet from the account.
+/// The wallet used by the current session cannot be unlinked.
+pub async fn unlink_wallet(
+    State(state): State<Arc<AppState>>,
+    session: Session,
+    Path(user_id): Path<i64>,
+) -> Result<Json<WalletsResponse>, StatusCode> {
+    if user_id == session.user_id {
+        return Ok(WalletsResponse::error(
+            "Cannot unlink the wallet you are signed in with",
+        ));
+    }
+
+    let wallets = db::get_account_wallets(&state.db, session.account_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !wallets.iter().any(|w| w.id == user_id) {
+        return Ok(WalletsResponse::error(
+            "Wallet is not linked to this account",
+        ));
+    }
+
+    db::unlink_user(&state.db, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    list_wallets(State(state), session).await
+}
+
+/// GET /api/auth/session - Return the currently signed-in wallet
+pub async fn current_session(session: Option<Session>) -> Json<Option<Session>> {
+    Json(session)
+}
+
+/// POST /api/auth/logout - Revoke the current session and clear the cookie
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    session: Option<Session>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if let Some(session) = session {
+        db::delete_session(&state.db, &session.token)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok((
+        [(
+            header::SET_COOKIE,
+            auth::clear_session_cookie(state.config.session_cookie_secure),
+        )],
+        StatusCode::NO_CONTENT,
+    ))
+}
@@ -1,82 +1,98 @@
+mod auth;
 mod config;
 mod db;
+mod feed;
 mod handlers;
 mod markdown;
 mod models;
+mod page_cache;
+mod posts_dir;
 mod routes;
+mod seo;
+mod sync;
 mod web3;
 
 use anyhow::Result;
 use axum::{routing::{get, post, put, delete}, Router};
-use chrono::Utc;
 use sqlx::sqlite::SqlitePoolOptions;
 use std::sync::Arc;
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::config::Config;
-use crate::markdown::MarkdownParser;
-use crate::models::Post;
 
 pub struct AppState {
     pub db: sqlx::SqlitePool,
     pub config: Config,
+    /// `/static/css/syntax.css`, generated from the code themes at startup
+    pub syntax_css: String,
+    /// Shared so syntaxes and themes are only loaded once
+    pub parser: markdown::MarkdownParser,
+    /// Rendered post and index pages; clear it whenever posts change
+    pub pages: page_cache::PageCache,
 }
 
-async fn load_posts_from_directory(pool: &sqlx::SqlitePool) -> Result<()> {
-    let posts_dir = std::path::Path::new("posts");
+async fn run_sync_command(db: &sqlx::SqlitePool, config: &Config, args: &[String]) -> Result<()> {
+    let mut mode = config.sync_mode;
+    let mut apply = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dry-run" | "-n" => apply = false,
+            "--prefer" => {
+                let value = args.next().ok_or_else(|| anyhow::anyhow!("--prefer needs a value"))?;
+                mode = value.parse().map_err(anyhow::Error::msg)?;
+            }
+            other => anyhow::bail!("unknown sync option {:?}", other),
+        }
+    }
 
-    if !posts_dir.exists() {
-        tracing::info!("No posts directory found, skipping post loading");
-        return Ok(());
+    let parser = markdown::MarkdownParser::new(config);
+    let report = sync::sync_all(db, &parser, mode, apply).await?;
+    for entry in report.entries.iter().filter(|e| e.action != sync::SyncAction::Unchanged) {
+        println!(
+            "{:<10} {}{}",
+            format!("{:?}", entry.action).to_lowercase(),
+            entry.slug,
+            entry.message.as_ref().map(|m| format!(" ({})", m)).unwrap_or_default()
+        );
+    }
+    let unchanged = report.entries.iter().filter(|e| e.action == sync::SyncAction::Unchanged).count();
+    println!(
+        "{} post(s) checked, {} unchanged, {} unresolved conflict(s){}",
+        report.entries.len(),
+        unchanged,
+        report.unresolved(),
+        if apply { "" } else { " (dry run, nothing changed)" }
+    );
+
+    if report.unresolved() > 0 {
+        std::process::exit(1);
     }
+    Ok(())
+}
 
-    let parser = MarkdownParser::new();
-
-    for entry in std::fs::read_dir(posts_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-
-        if path.extension().map(|e| e == "md").unwrap_or(false) {
-            let content = std::fs::read_to_string(&path)?;
-            let slug = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("untitled")
-                .to_string();
-
-            match markdown::parse_frontmatter(&content) {
-                Ok((frontmatter, markdown_content)) => {
-                    let html_content = parser.parse(&markdown_content)?;
-
-                    let post = Post {
-                        id: 0,
-                        slug,
-                        title: frontmatter.title,
-                        description: frontmatter.description.unwrap_or_default(),
-                        content: markdown_content,
-                        html_content,
-                        author: frontmatter.author.unwrap_or_else(|| "Anonymous".to_string()),
-                        tags: frontmatter.tags.unwrap_or_default().join(","),
-                        published: frontmatter.published.unwrap_or(true),
-                        created_at: Utc::now(),
-                        updated_at: Utc::now(),
-                        views: 0,
-                    };
-
-                    match db::upsert_post(pool, &post).await {
-                        Ok(_) => tracing::info!("Loaded post: {}", post.title),
-                        Err(e) => tracing::error!("Failed to load post {}: {}", post.title, e),
-                    }
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to parse frontmatter for {:?}: {}", path, e);
-                }
+/// Re-render every post whose stored HTML or TOC is out of date, returning how many changed.
+async fn render_posts(db: &sqlx::SqlitePool, parser: &markdown::MarkdownParser) -> Result<usize> {
+    let mut count = 0;
+    for slug in db::get_all_post_slugs(db).await? {
+        let Some(post) = db::get_any_post_by_slug(db, &slug).await? else {
+            continue;
+        };
+        let rendered = match parser.render(&post.content) {
+            Ok(rendered) => rendered,
+            Err(e) => {
+                tracing::warn!("Not re-rendering post {}: {}", slug, e);
+                continue;
             }
+        };
+        if rendered.html != post.html_content || rendered.toc != post.toc.0 {
+            db::update_post_rendered(db, &slug, &rendered).await?;
+            count += 1;
         }
     }
 
-    Ok(())
+    Ok(count)
 }
 
 #[tokio::main]
@@ -100,34 +116,90 @@ async fn main() -> Result<()> {
 
     sqlx::migrate!("./migrations").run(&db).await?;
 
-    // Load posts from markdown files
-    if let Err(e) = load_posts_from_directory(&db).await {
-        tracing::error!("Failed to load posts: {}", e);
+    match db::delete_expired_sessions(&db).await {
+        Ok(n) if n > 0 => tracing::info!("Removed {} expired sessions", n),
+        Ok(_) => {}
+        Err(e) => tracing::error!("Failed to clean up sessions: {}", e),
+    }
+
+    // `sync [--dry-run] [--prefer files|database|manual]` syncs posts/ with
+    // the database, prints what happened and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("sync") {
+        return run_sync_command(&db, &config, &args[1..]).await;
+    }
+
+    // Sync posts with the markdown files
+    let parser = markdown::MarkdownParser::new(&config);
+    match sync::sync_all(&db, &parser, config.sync_mode, true).await {
+        Ok(report) => report.log(),
+        Err(e) => tracing::error!("Failed to sync posts: {}", e),
+    }
+
+    // Re-render stored posts so renderer and HTML policy changes apply to all of them
+    match render_posts(&db, &parser).await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Re-rendered {} post(s)", count),
+        Err(e) => tracing::error!("Failed to re-render posts: {}", e),
     }
 
-    let state = Arc::new(AppState { db, config });
+    let base_url = config.base_url.clone();
+    let syntax_css = markdown::highlight::theme_css(&config);
+    let pages = page_cache::PageCache::new(std::time::Duration::from_secs(config.page_cache_ttl_secs));
+    let state = Arc::new(AppState { db, config, syntax_css, parser, pages });
+
+    if state.config.watch_posts {
+        tokio::spawn(posts_dir::watch(state.clone()));
+    }
 
     let app = Router::new()
         .route("/", get(handlers::posts::index))
         .route("/post/:slug", get(handlers::posts::show))
         .route("/posts", get(handlers::posts::list))
+        .route("/api/posts", get(handlers::posts::api_list))
+        .route("/api/posts/views", get(handlers::posts::api_views))
+        .route("/tag/:name", get(handlers::posts::tag))
+        .route("/sitemap.xml", get(handlers::seo::sitemap))
+        .route("/robots.txt", get(handlers::seo::robots))
+        .route("/feed.xml", get(handlers::feeds::rss))
+        .route("/atom.xml", get(handlers::feeds::atom))
+        .route("/feed.json", get(handlers::feeds::json))
+        .route("/tag/:name/feed.xml", get(handlers::feeds::tag_rss))
+        .route("/tag/:name/atom.xml", get(handlers::feeds::tag_atom))
+        .route("/tag/:name/feed.json", get(handlers::feeds::tag_json))
+        .route("/preview/:slug", get(handlers::posts::preview))
+        .route("/search", get(handlers::search::page))
+        .route("/api/search", get(handlers::search::api))
         // Admin routes
         .route("/admin/new", get(handlers::admin::new_post))
         .route("/admin/edit/:slug", get(handlers::admin::edit_post))
+        .route("/admin/posts", get(handlers::admin::posts))
+        .route("/admin/posts/:slug/revisions", get(handlers::revisions::list))
         .route("/admin/readers", get(handlers::admin::readers))
+        .route("/admin/tags", get(handlers::admin::tags))
         .route("/api/admin/posts", post(handlers::admin::create_post))
         .route("/api/admin/posts/:slug", put(handlers::admin::update_post))
         .route("/api/admin/posts/:slug", delete(handlers::admin::delete_post))
+        .route("/api/admin/posts/:slug/unpublish", post(handlers::admin::unpublish_post))
+        .route("/api/admin/posts/:slug/preview-link", get(handlers::admin::preview_link))
+        .route(
+            "/api/admin/posts/:slug/revisions/:id/restore",
+            post(handlers::revisions::restore),
+        )
+        .route("/api/admin/users/:id/role", put(handlers::admin::set_user_role))
+        .route("/api/admin/tags/:name", put(handlers::admin::rename_tag))
+        .route("/api/admin/sync", post(handlers::admin::sync_posts))
         .nest("/api/auth", routes::auth::router())
         .nest("/api/tips", routes::tipping::router())
         .nest("/api/activity", routes::activity::router())
+        .route("/static/css/syntax.css", get(handlers::assets::syntax_css))
         .nest_service("/static", ServeDir::new("static"))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive())
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    tracing::info!("Server running on http://localhost:3000");
+    tracing::info!("Server listening on 0.0.0.0:3000, public URL {}", base_url);
 
     axum::serve(listener, app).await?;
 
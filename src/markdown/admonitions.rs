@@ -0,0 +1,354 @@
+//! Callouts for notes and warnings, written GitHub-style as a blockquote
+//! starting with `[!NOTE]`, or as a `:::note` ... `:::` container. Both may
+//! be given a title and hold any markdown.
+
+use html_escape::encode_text;
+use pulldown_cmark::{Event, Tag, TagEnd};
+use std::collections::HashSet;
+
+use super::sanitize::TrustedHtml;
+use super::scan::outside_code_blocks;
+
+/// Kinds of callout, with the names they can also be written as.
+const KINDS: &[(&str, &str, &[&str])] = &[
+    ("note", "Note", &["info"]),
+    ("tip", "Tip", &["hint"]),
+    ("important", "Important", &[]),
+    ("warning", "Warning", &[]),
+    ("caution", "Caution", &["danger"]),
+];
+
+const CLOSE: &str = "</aside>";
+
+/// A callout's kind and its default title.
+type Kind = (&'static str, &'static str);
+
+fn kind(name: &str) -> Option<Kind> {
+    KINDS
+        .iter()
+        .find(|(kind, _, aliases)| {
+            kind.eq_ignore_ascii_case(name) || aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+        })
+        .map(|(kind, label, _)| (*kind, *label))
+}
+
+/// The opening tags of a callout, up to its title.
+fn open_title((kind, _): Kind) -> String {
+    format!(
+        r#"<aside class="admonition admonition-{}"><p class="admonition-title">"#,
+        kind
+    )
+}
+
+fn open(kind: Kind, title: &str) -> String {
+    let title = TrustedHtml::remove_placeholders(title.trim());
+    format!(
+        "{}{}</p>",
+        open_title(kind),
+        encode_text(if title.is_empty() { kind.1 } else { &title })
+    )
+}
+
+/// Replace the fence lines of `:::` containers outside code with
+/// placeholders for their opening and closing tags, each in a paragraph of
+/// its own at the fence's indentation. A closing fence only closes a
+/// container opened at the same indentation, so the tags stay in the same
+/// list item. Returns the markdown and the placeholders, which
+/// [`render_admonitions`] unwraps.
+pub(crate) fn replace_containers(
+    markdown: &str,
+    trusted: &mut TrustedHtml,
+) -> (String, HashSet<String>) {
+    let mut blocks = HashSet::new();
+    // Indentation of each open container
+    let mut open_at = Vec::new();
+
+    let mut out = outside_code_blocks(markdown, |text, _| {
+        let mut out = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let indent = &line[..line.len() - line.trim_start_matches(' ').len()];
+            let html = match container_fence(line) {
+                Some(Fence::Open(kind, title)) => {
+                    open_at.push(indent.len());
+                    open(kind, title)
+                }
+                Some(Fence::Close) if open_at.last() == Some(&indent.len()) => {
+                    open_at.pop();
+                    CLOSE.to_string()
+                }
+                _ => {
+                    out.push_str(line);
+                    continue;
+                }
+            };
+            let placeholder = trusted.insert(html);
+            out.push_str(&format!("\n{}{}\n\n", indent, placeholder));
+            blocks.insert(placeholder);
+        }
+        out
+    });
+
+    // Containers left open close at the end of the post
+    for _ in 0..open_at.len() {
+        let placeholder = trusted.insert(CLOSE.to_string());
+        out.push_str(&format!("\n\n{}\n", placeholder));
+        blocks.insert(placeholder);
+    }
+    (out, blocks)
+}
+
+enum Fence<'a> {
+    Open(Kind, &'a str),
+    Close,
+}
+
+fn container_fence(line: &str) -> Option<Fence<'_>> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() >= 4 || !trimmed.starts_with(":::") {
+        return None;
+    }
+    let rest = trimmed.trim_start_matches(':').trim();
+    if rest.is_empty() {
+        return Some(Fence::Close);
+    }
+    let (name, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    Some(Fence::Open(kind(name)?, title))
+}
+
+/// Turn `[!KIND]` blockquotes into callouts, and put the tags of `:::`
+/// containers in place of the paragraphs holding their placeholders.
+pub(crate) fn render_admonitions<'a>(
+    events: Vec<Event<'a>>,
+    blocks: &HashSet<String>,
+    trusted: &mut TrustedHtml,
+) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    // Whether each open blockquote became a callout
+    let mut quotes = Vec::new();
+    let mut i = 0;
+
+    while i < events.len() {
+        match &events[i] {
+            Event::Start(Tag::BlockQuote) => match alert(&events[i + 1..]) {
+                Some(alert) => {
+                    out.push(Event::Html(trusted.insert(open_title(alert.kind)).into()));
+                    if alert.title.is_empty() {
+                        out.push(Event::Text(alert.kind.1.into()));
+                    }
+                    out.extend(alert.title);
+                    out.push(Event::Html(trusted.insert("</p>".to_string()).into()));
+                    if alert.continues {
+                        out.push(Event::Start(Tag::Paragraph));
+                    }
+                    quotes.push(true);
+                    i += alert.end + 2;
+                    continue;
+                }
+                None => quotes.push(false),
+            },
+            Event::End(TagEnd::BlockQuote) if quotes.pop() == Some(true) => {
+                out.push(Event::Html(trusted.insert(CLOSE.to_string()).into()));
+                i += 1;
+                continue;
+            }
+            Event::Start(Tag::Paragraph) => {
+                if let (Some(Event::Text(text)), Some(Event::End(TagEnd::Paragraph))) =
+                    (events.get(i + 1), events.get(i + 2))
+                {
+                    if blocks.contains(text.as_ref()) {
+                        out.push(Event::Html(text.clone()));
+                        i += 3;
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.push(events[i].clone());
+        i += 1;
+    }
+    out
+}
+
+/// The `[!KIND] title` line starting a blockquote.
+struct Alert<'a> {
+    kind: Kind,
+    /// The inline events after the marker, which may hold markup
+    title: Vec<Event<'a>>,
+    /// Index of the event ending the line
+    end: usize,
+    /// Whether the first paragraph goes on after the line
+    continues: bool,
+}
+
+fn alert<'a>(events: &[Event<'a>]) -> Option<Alert<'a>> {
+    let Some(Event::Start(Tag::Paragraph)) = events.first() else {
+        return None;
+    };
+
+    // The marker may be split over several text events
+    let mut marker = String::new();
+    let mut end = 1;
+    while !marker.contains(']') {
+        let Some(Event::Text(text)) = events.get(end) else {
+            return None;
+        };
+        marker.push_str(text);
+        end += 1;
+    }
+    let (name, rest) = marker.trim_start().strip_prefix("[!")?.split_once(']')?;
+    let kind = kind(name)?;
+
+    let mut title = vec![Event::Text(rest.to_string().into())];
+    let mut depth = 0usize;
+    let continues = loop {
+        match events.get(end)? {
+            Event::SoftBreak | Event::HardBreak if depth == 0 => break true,
+            Event::End(TagEnd::Paragraph) if depth == 0 => break false,
+            // Markup running past the first line can't be split from the
+            // body, so the blockquote is left as it is
+            Event::SoftBreak | Event::HardBreak => return None,
+            event @ Event::Start(_) => {
+                depth += 1;
+                title.push(event.clone());
+            }
+            event @ Event::End(_) => {
+                depth = depth.checked_sub(1)?;
+                title.push(event.clone());
+            }
+            event => title.push(event.clone()),
+        }
+        end += 1;
+    };
+
+    let is_empty = |event: &Event| matches!(event, Event::Text(text) if text.is_empty());
+    title.retain(|event| !is_empty(event));
+    if let Some(Event::Text(text)) = title.first_mut() {
+        *text = text.trim_start().to_string().into();
+    }
+    if let Some(Event::Text(text)) = title.last_mut() {
+        *text = text.trim_end().to_string().into();
+    }
+    title.retain(|event| !is_empty(event));
+
+    Some(Alert {
+        kind,
+        title,
+        end,
+        continues,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{config::Config, markdown::MarkdownParser};
+    use std::sync::OnceLock;
+
+    fn render(markdown: &str) -> String {
+        static PARSER: OnceLock<MarkdownParser> = OnceLock::new();
+        PARSER
+            .get_or_init(|| MarkdownParser::new(&Config::from_env()))
+            .render(markdown)
+            .unwrap()
+            .html
+            .replace('\n', "")
+    }
+
+    const NOTE: &str = r#"<aside class="admonition admonition-note"><p class="admonition-title">"#;
+
+    #[test]
+    fn alerts_take_a_default_or_given_title() {
+        assert_eq!(
+            render("> [!NOTE]\n> Body"),
+            format!("{}Note</p><p>Body</p></aside>", NOTE)
+        );
+        assert_eq!(
+            render("> [!note] Heads up\n> Body"),
+            format!("{}Heads up</p><p>Body</p></aside>", NOTE)
+        );
+        assert_eq!(
+            render("> [!NOTE] Heads up"),
+            format!("{}Heads up</p></aside>", NOTE)
+        );
+    }
+
+    #[test]
+    fn alert_titles_keep_inline_markup() {
+        assert_eq!(
+            render("> [!NOTE] **Heads** up `now` <b>x</b>\n> Body"),
+            format!(
+                "{}<strong>Heads</strong> up <code>now</code> <b>x</b></p><p>Body</p></aside>",
+                NOTE
+            )
+        );
+        assert_eq!(
+            render("> [!TIP] See [the docs](https://example.com)"),
+            r#"<aside class="admonition admonition-tip"><p class="admonition-title">See <a href="https://example.com">the docs</a></p></aside>"#
+        );
+        // Markup spanning the title and body can't be split, so it stays a quote
+        let html = render("> [!NOTE] **Heads\n> up**");
+        assert!(html.starts_with("<blockquote>"), "{}", html);
+        assert!(!html.contains("aside"), "{}", html);
+    }
+
+    #[test]
+    fn alert_bodies_hold_any_markdown() {
+        assert_eq!(
+            render("> [!WARNING]\n> First *line*\n> goes on\n>\n> - a\n> - b\n>\n> > nested"),
+            concat!(
+                r#"<aside class="admonition admonition-warning"><p class="admonition-title">Warning</p>"#,
+                "<p>First <em>line</em>goes on</p><ul><li>a</li><li>b</li></ul>",
+                "<blockquote><p>nested</p></blockquote></aside>"
+            )
+        );
+    }
+
+    #[test]
+    fn aliases_and_unknown_kinds() {
+        assert!(render("> [!info]\n> x").starts_with(NOTE));
+        assert!(render(":::hint\nx\n:::").contains("admonition-tip"));
+        assert!(render(":::danger\nx\n:::").contains("admonition-caution"));
+        assert!(render("> [!NOPE]\n> x").starts_with("<blockquote>"));
+        assert_eq!(render(":::nope\nx\n:::"), "<p>:::nopex:::</p>");
+    }
+
+    #[test]
+    fn containers_nest_and_take_titles() {
+        assert_eq!(
+            render(":::note Read *this*\nOuter\n\n:::tip\nInner\n:::\n:::"),
+            format!(
+                r#"{}Read *this*</p><p>Outer</p><aside class="admonition admonition-tip"><p class="admonition-title">Tip</p><p>Inner</p></aside></aside>"#,
+                NOTE
+            )
+        );
+    }
+
+    #[test]
+    fn unclosed_containers_close_at_the_end() {
+        assert_eq!(
+            render(":::note\n:::tip\nText"),
+            format!(
+                r#"{}Note</p><aside class="admonition admonition-tip"><p class="admonition-title">Tip</p><p>Text</p></aside></aside>"#,
+                NOTE
+            )
+        );
+        // A stray close is just text
+        assert_eq!(render("Text\n\n:::"), "<p>Text</p><p>:::</p>");
+    }
+
+    #[test]
+    fn containers_close_at_the_indentation_they_opened() {
+        // The indented close belongs to the list item, not the callout
+        let html = render(":::note\n- item\n  :::\n:::");
+        assert_eq!(
+            html,
+            format!("{}Note</p><ul><li>item:::</li></ul></aside>", NOTE)
+        );
+
+        // A callout opened in a list item closes inside it
+        let html = render("- item\n\n  :::tip\n  Inside\n  :::\n- next");
+        assert_eq!(html.matches("<aside").count(), 1);
+        let item = &html[html.find("<li>").unwrap()..html.find("</li>").unwrap()];
+        assert!(item.contains("<p>Inside</p></aside>"), "{}", html);
+    }
+}
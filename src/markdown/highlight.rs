@@ -0,0 +1,494 @@
+//! Syntax highlighting for code blocks.
+//!
+//! Code is marked up with CSS classes rather than inline colors, and the
+//! colors come from `/static/css/syntax.css`, generated from the configured
+//! light and dark themes. Each line is wrapped in its own `<span class="line">`
+//! so lines can be numbered and highlighted.
+//!
+//! Besides syntect's built-in grammars, `.sublime-syntax` files in
+//! `SYNTAX_DIR` are loaded; the repo ships Solidity, Vyper, Move and Cairo.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use syntect::highlighting::{Color, FontStyle, Theme, ThemeSet};
+use syntect::html::{line_tokens_to_classed_spans, ClassStyle};
+use syntect::parsing::{
+    ParseState, Scope, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet,
+};
+use syntect::util::LinesWithEndings;
+
+use crate::config::Config;
+
+const CLASS_PREFIX: &str = "hl-";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed {
+    prefix: CLASS_PREFIX,
+};
+
+pub const DEFAULT_LIGHT_THEME: &str = "InspiredGitHub";
+pub const DEFAULT_DARK_THEME: &str = "base16-ocean.dark";
+
+/// The info string of a code fence, e.g. ```` ```rust {3,5-7} ````: the
+/// language, then lines to highlight and `linenos`/`nolinenos` in braces.
+#[derive(Debug, Default, PartialEq)]
+pub struct FenceInfo {
+    pub lang: String,
+    /// Inclusive, 1-based line ranges
+    pub highlight: Vec<(usize, usize)>,
+    /// Overrides `CODE_LINE_NUMBERS` for this block
+    pub line_numbers: Option<bool>,
+}
+
+impl FenceInfo {
+    pub fn parse(info: &str) -> Self {
+        let (lang, attrs) = match info.split_once('{') {
+            Some((lang, attrs)) => (lang, attrs.split('}').next().unwrap_or_default()),
+            None => (info, ""),
+        };
+        let mut fence = FenceInfo {
+            lang: lang
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            ..Default::default()
+        };
+
+        for attr in attrs.split([',', ' ']).map(str::trim) {
+            match attr {
+                "" => {}
+                "linenos" => fence.line_numbers = Some(true),
+                "nolinenos" => fence.line_numbers = Some(false),
+                _ => {
+                    let (start, end) = attr.split_once('-').unwrap_or((attr, attr));
+                    if let (Ok(start), Ok(end)) = (start.trim().parse(), end.trim().parse()) {
+                        if start <= end {
+                            fence.highlight.push((start, end));
+                        }
+                    }
+                }
+            }
+        }
+        fence
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&line))
+    }
+}
+
+/// Highlights code blocks with syntect's built-in grammars plus any
+/// `.sublime-syntax` files in `SYNTAX_DIR`.
+pub struct Highlighter {
+    syntax_set: SyntaxSet,
+    /// Fence token -> syntax name or extension, from `SYNTAX_ALIASES`
+    aliases: HashMap<String, String>,
+    line_numbers: bool,
+}
+
+impl Highlighter {
+    pub fn new(config: &Config) -> Self {
+        let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+        for syntax in load_syntax_dir(Path::new(&config.syntax_dir)) {
+            builder.add(syntax);
+        }
+        let syntax_set = builder.build();
+
+        let aliases = config
+            .syntax_aliases
+            .iter()
+            .filter(|(token, syntax)| {
+                let found = syntax_set.find_syntax_by_token(syntax).is_some();
+                if !found {
+                    tracing::warn!("SYNTAX_ALIASES: no syntax {:?} for {:?}", syntax, token);
+                }
+                found
+            })
+            .cloned()
+            .collect();
+
+        Self {
+            syntax_set,
+            aliases,
+            line_numbers: config.code_line_numbers,
+        }
+    }
+
+    fn find_syntax(&self, token: &str) -> &SyntaxReference {
+        let token = self
+            .aliases
+            .get(&token.to_lowercase())
+            .map_or(token, String::as_str);
+        self.syntax_set
+            .find_syntax_by_token(token)
+            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text())
+    }
+
+    /// Render a code block with one classed `<span class="line">` per line.
+    pub fn highlight(&self, code: &str, fence: &FenceInfo) -> String {
+        let lang = if fence.lang.is_empty() {
+            "txt"
+        } else {
+            &fence.lang
+        };
+        let syntax = self.find_syntax(lang);
+
+        let lines = highlight_lines(&self.syntax_set, syntax, code).unwrap_or_else(|e| {
+            tracing::warn!("Failed to highlight {} code: {}", lang, e);
+            LinesWithEndings::from(code)
+                .map(|line| html_escape::encode_text(line).into_owned())
+                .collect()
+        });
+
+        let mut html = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            let class = if fence.is_highlighted(i + 1) {
+                "line highlighted"
+            } else {
+                "line"
+            };
+            let _ = write!(html, r#"<span class="{}">{}</span>"#, class, line);
+        }
+
+        format!(
+            r#"<div class="code-block{}"><pre><code class="language-{}">{}</code></pre></div>"#,
+            if fence.line_numbers.unwrap_or(self.line_numbers) {
+                " line-numbers"
+            } else {
+                ""
+            },
+            html_escape::encode_double_quoted_attribute(lang),
+            html
+        )
+    }
+}
+
+/// The `.sublime-syntax` files in `dir`. Files that fail to parse are logged
+/// and skipped so one bad grammar doesn't take the others down.
+fn load_syntax_dir(dir: &Path) -> Vec<SyntaxDefinition> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!("Failed to read syntax directory {}: {}", dir.display(), e);
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sublime-syntax"))
+        .collect();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_stem().and_then(|s| s.to_str());
+            let syntax = std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| {
+                    SyntaxDefinition::load_from_str(&source, true, name).map_err(|e| e.to_string())
+                });
+            match syntax {
+                Ok(syntax) => {
+                    tracing::debug!("Loaded syntax {} from {}", syntax.name, path.display());
+                    Some(syntax)
+                }
+                Err(e) => {
+                    tracing::warn!("Skipping syntax {}: {}", path.display(), e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Highlighted HTML for each line, with the spans still open at the end of a
+/// line closed there and reopened on the next one.
+fn highlight_lines(
+    syntax_set: &SyntaxSet,
+    syntax: &SyntaxReference,
+    code: &str,
+) -> Result<Vec<String>, syntect::Error> {
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+
+    for line in LinesWithEndings::from(code) {
+        let ops = state.parse_line(line, syntax_set)?;
+        let mut html = String::new();
+        for scope in stack.as_slice() {
+            let _ = write!(html, r#"<span class="{}">"#, scope_classes(*scope));
+        }
+        let (spans, _) = line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)?;
+        html.push_str(&spans);
+        html.push_str(&"</span>".repeat(stack.len()));
+        lines.push(html);
+    }
+    Ok(lines)
+}
+
+/// The classes syntect gives a scope, e.g. `hl-string hl-quoted`.
+fn scope_classes(scope: Scope) -> String {
+    scope
+        .build_string()
+        .split('.')
+        .map(|atom| format!("{}{}", CLASS_PREFIX, atom))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The stylesheet for highlighted code: the light theme, and the dark theme
+/// when the page is in dark mode.
+pub fn theme_css(config: &Config) -> String {
+    let themes = ThemeSet::load_defaults();
+    let light = load_theme(&themes, &config.code_theme_light, DEFAULT_LIGHT_THEME);
+    let dark = load_theme(&themes, &config.code_theme_dark, DEFAULT_DARK_THEME);
+
+    let mut css = format!(
+        "/* Generated from CODE_THEME_LIGHT ({}) and CODE_THEME_DARK ({}) */\n",
+        config.code_theme_light, config.code_theme_dark
+    );
+    write_theme_css(&mut css, &light, "html:not(.dark)");
+    write_theme_css(&mut css, &dark, "html.dark");
+    css
+}
+
+/// A built-in theme by name, or a `.tmTheme` file by path.
+fn load_theme(themes: &ThemeSet, name: &str, fallback: &str) -> Theme {
+    if name.ends_with(".tmTheme") {
+        match ThemeSet::get_theme(name) {
+            Ok(theme) => return theme,
+            Err(e) => tracing::warn!("Failed to load code theme {}: {}", name, e),
+        }
+    } else if let Some(theme) = themes.themes.get(name) {
+        return theme.clone();
+    } else {
+        let mut names: Vec<_> = themes.themes.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        tracing::warn!(
+            "Unknown code theme {:?}, using {:?} (built-in themes: {})",
+            name,
+            fallback,
+            names.join(", ")
+        );
+    }
+    themes.themes[fallback].clone()
+}
+
+fn write_theme_css(css: &mut String, theme: &Theme, root: &str) {
+    let settings = &theme.settings;
+    let mut block = String::new();
+    if let Some(fg) = settings.foreground {
+        let _ = write!(block, "color: {}; ", hex(fg));
+    }
+    if let Some(bg) = settings.background {
+        let _ = write!(block, "background-color: {}; ", hex(bg));
+    }
+    let _ = writeln!(css, "{} .code-block pre {{ {}}}", root, block);
+
+    let highlight = settings
+        .line_highlight
+        .map_or_else(|| "rgba(139, 92, 246, 0.15)".to_string(), hex);
+    let _ = writeln!(
+        css,
+        "{} .code-block .line.highlighted {{ background-color: {}; }}",
+        root, highlight
+    );
+    if let Some(gutter) = settings.gutter_foreground {
+        let _ = writeln!(
+            css,
+            "{} .code-block.line-numbers .line::before {{ color: {}; }}",
+            root,
+            hex(gutter)
+        );
+    }
+
+    for item in &theme.scopes {
+        let selectors: Vec<String> = item
+            .scope
+            .selectors
+            .iter()
+            .map(|selector| {
+                let path: Vec<String> = selector
+                    .extract_scopes()
+                    .into_iter()
+                    .map(scope_selector)
+                    .collect();
+                format!("{} {}", root, path.join(" "))
+            })
+            .collect();
+
+        let mut rules = String::new();
+        if let Some(fg) = item.style.foreground {
+            let _ = write!(rules, "color: {}; ", hex(fg));
+        }
+        if let Some(bg) = item.style.background {
+            let _ = write!(rules, "background-color: {}; ", hex(bg));
+        }
+        if let Some(font_style) = item.style.font_style {
+            if font_style.contains(FontStyle::BOLD) {
+                rules.push_str("font-weight: bold; ");
+            }
+            if font_style.contains(FontStyle::ITALIC) {
+                rules.push_str("font-style: italic; ");
+            }
+            if font_style.contains(FontStyle::UNDERLINE) {
+                rules.push_str("text-decoration: underline; ");
+            }
+        }
+        if !selectors.is_empty() && !rules.is_empty() {
+            let _ = writeln!(css, "{} {{ {}}}", selectors.join(", "), rules);
+        }
+    }
+}
+
+/// `.hl-string.hl-quoted` for `string.quoted`.
+fn scope_selector(scope: Scope) -> String {
+    scope
+        .build_string()
+        .split('.')
+        .map(|atom| {
+            let mut class = format!(".{}", CLASS_PREFIX);
+            for c in atom.chars() {
+                if !(c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                    class.push('\\');
+                }
+                class.push(c);
+            }
+            class
+        })
+        .collect()
+}
+
+fn hex(color: Color) -> String {
+    if color.a == 0xff {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    } else {
+        format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            color.r, color.g, color.b, color.a
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fence(lang: &str, highlight: &[(usize, usize)], line_numbers: Option<bool>) -> FenceInfo {
+        FenceInfo {
+            lang: lang.to_string(),
+            highlight: highlight.to_vec(),
+            line_numbers,
+        }
+    }
+
+    #[test]
+    fn fence_info_reads_lines_and_line_numbers() {
+        assert_eq!(
+            FenceInfo::parse("rust {3,5-7}"),
+            fence("rust", &[(3, 3), (5, 7)], None)
+        );
+        assert_eq!(
+            FenceInfo::parse("rust{1 linenos}"),
+            fence("rust", &[(1, 1)], Some(true))
+        );
+        assert_eq!(
+            FenceInfo::parse("py {nolinenos, 2-4}"),
+            fence("py", &[(2, 4)], Some(false))
+        );
+        assert_eq!(FenceInfo::parse("rust,ignore"), fence("rust", &[], None));
+        assert_eq!(FenceInfo::parse(""), fence("", &[], None));
+    }
+
+    #[test]
+    fn fence_info_skips_bad_ranges() {
+        assert_eq!(FenceInfo::parse("js {7-5}"), fence("js", &[], None));
+        assert_eq!(
+            FenceInfo::parse("js {x, 2-, -1, 3-x, 1-2-3, 4}"),
+            fence("js", &[(4, 4)], None)
+        );
+        // An unclosed brace still counts, and junk after the closing one doesn't
+        assert_eq!(FenceInfo::parse("js {2"), fence("js", &[(2, 2)], None));
+        assert_eq!(FenceInfo::parse("js {2} 9"), fence("js", &[(2, 2)], None));
+    }
+
+    #[test]
+    fn fence_info_without_a_language() {
+        assert_eq!(
+            FenceInfo::parse("{3,5-7}"),
+            fence("", &[(3, 3), (5, 7)], None)
+        );
+        assert_eq!(FenceInfo::parse(" {linenos}"), fence("", &[], Some(true)));
+    }
+
+    fn highlighter() -> Highlighter {
+        Highlighter::new(&Config::from_env())
+    }
+
+    /// The contents of each `<span class="line…">`, found by counting spans.
+    fn lines(html: &str) -> Vec<&str> {
+        let mut lines = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find(r#"<span class="line"#) {
+            let body = &rest[start..];
+            let mut depth = 0;
+            let mut i = 0;
+            loop {
+                if body[i..].starts_with("<span") {
+                    depth += 1;
+                } else if body[i..].starts_with("</span>") {
+                    depth -= 1;
+                    if depth == 0 {
+                        i += "</span>".len();
+                        break;
+                    }
+                }
+                i += body[i..].chars().next().unwrap().len_utf8();
+            }
+            lines.push(&body[..i]);
+            rest = &body[i..];
+        }
+        lines
+    }
+
+    #[test]
+    fn spans_are_reopened_on_each_line() {
+        let code = "let a = 1; /* one\ntwo\nthree */ let b = \"x\ny\";\n";
+        let html = highlighter().highlight(code, &FenceInfo::parse("rust"));
+        let lines = lines(&html);
+
+        assert_eq!(lines.len(), 4);
+        for line in &lines {
+            assert_eq!(
+                line.matches("<span").count(),
+                line.matches("</span>").count(),
+                "{}",
+                line
+            );
+        }
+        // The comment's scope carries over to the lines it spans
+        for line in &lines[..3] {
+            assert!(line.contains("hl-comment"), "{}", line);
+        }
+        assert!(lines[3].contains("hl-string"), "{}", lines[3]);
+        assert!(html.ends_with("</span></code></pre></div>"));
+    }
+
+    #[test]
+    fn highlighted_lines_and_line_numbers_are_marked() {
+        let html = highlighter().highlight("a\nb\nc\n", &FenceInfo::parse("{2 linenos}"));
+        assert!(html.starts_with(r#"<div class="code-block line-numbers">"#));
+        assert!(html.contains(r#"class="language-txt""#));
+        let lines = lines(&html);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with(r#"<span class="line highlighted">"#));
+        assert!(!lines[0].contains("highlighted") && !lines[2].contains("highlighted"));
+    }
+}
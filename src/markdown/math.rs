@@ -0,0 +1,1149 @@
+//! TeX math in posts (`$...$` inline, `$$...$$` display), rendered to MathML
+//! on the server so readers don't need any JavaScript.
+//!
+//! This covers the LaTeX math people write in blog posts: letters, numbers and
+//! operators, sub/superscripts, `\frac`, `\sqrt`, `\binom`, Greek letters and
+//! the usual symbols, fonts like `\mathbb`, accents, `\left`/`\right`, text, and
+//! the matrix, `cases` and `aligned` environments. Anything else is reported
+//! as an error in place of the formula.
+
+use html_escape::{encode_double_quoted_attribute, encode_text};
+
+use super::sanitize::TrustedHtml;
+use super::scan::{outside_code_blocks, skip_code_span};
+
+/// Deepest nesting of groups and commands a formula may use.
+const MAX_DEPTH: usize = 64;
+
+/// Replace math outside code with placeholders for its rendered MathML.
+pub(crate) fn replace_math(markdown: &str, trusted: &mut TrustedHtml) -> String {
+    outside_code_blocks(markdown, |text, _| replace_in_text(text, trusted))
+}
+
+fn replace_in_text(text: &str, trusted: &mut TrustedHtml) -> String {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => i = skip_code_span(bytes, i),
+            b'$' => {
+                let display = bytes.get(i + 1) == Some(&b'$');
+                let math = if display {
+                    display_math(text, i + 2)
+                } else {
+                    inline_math(text, i + 1)
+                };
+                match math {
+                    Some((tex, end)) => {
+                        out.push_str(&text[copied..i]);
+                        out.push_str(&trusted.insert(render(tex, display)));
+                        copied = end;
+                        i = end;
+                    }
+                    None => i += if display { 2 } else { 1 },
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+/// `$$...$$` starting after the opening `$$`: the TeX and the end offset.
+fn display_math(text: &str, start: usize) -> Option<(&str, usize)> {
+    let end = start + text[start..].find("$$")?;
+    let tex = &text[start..end];
+    (!tex.trim().is_empty() && !has_blank_line(tex)).then_some((tex, end + 2))
+}
+
+/// `$...$` starting after the opening `$`. Like Pandoc, the opening `$` must
+/// be followed by a non-space and the closing one preceded by a non-space and
+/// not followed by a digit, so "$5 and $10" stays text.
+fn inline_math(text: &str, start: usize) -> Option<(&str, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.get(start).is_none_or(|b| b.is_ascii_whitespace()) {
+        return None;
+    }
+
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' if starts_with_blank_line(&text[i + 1..]) => return None,
+            b'$' => {
+                let closes = !bytes[i - 1].is_ascii_whitespace()
+                    && bytes.get(i + 1).is_none_or(|b| !b.is_ascii_digit());
+                return closes.then(|| (&text[start..i], i + 1));
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Whether `text` spans a blank line, which would end the paragraph.
+fn has_blank_line(text: &str) -> bool {
+    text.match_indices('\n')
+        .any(|(i, _)| starts_with_blank_line(&text[i + 1..]))
+}
+
+fn starts_with_blank_line(text: &str) -> bool {
+    text.trim_start_matches([' ', '\t', '\r']).starts_with('\n')
+}
+
+/// MathML for `tex`, or an error box showing the source and what's wrong.
+fn render(tex: &str, display: bool) -> String {
+    match to_mathml(tex, display) {
+        Ok(mathml) => mathml,
+        Err(e) => format!(
+            r#"<span class="math-error{}" title="Invalid TeX: {}"><code>{}</code><span class="math-error-message">{}</span></span>"#,
+            if display { " math-error-display" } else { "" },
+            encode_double_quoted_attribute(&e),
+            encode_text(tex.trim()),
+            encode_text(&e)
+        ),
+    }
+}
+
+/// Convert a TeX formula to a `<math>` element.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String, String> {
+    let mut parser = TexParser::new(tex, 0);
+    let body = parser.parse_row()?;
+    parser.expect_end()?;
+
+    Ok(format!(
+        r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="{}"><semantics><mrow>{}</mrow><annotation encoding="application/x-tex">{}</annotation></semantics></math>"#,
+        if display { "block" } else { "inline" },
+        body,
+        encode_text(tex.trim())
+    ))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Font {
+    Roman,
+    Bold,
+    Italic,
+    BoldItalic,
+    Script,
+    Fraktur,
+    DoubleStruck,
+    SansSerif,
+    Monospace,
+}
+
+/// A parsed piece of a formula; `limits` puts its scripts above and below.
+struct Atom {
+    mathml: String,
+    limits: bool,
+}
+
+impl From<String> for Atom {
+    fn from(mathml: String) -> Self {
+        Atom {
+            mathml,
+            limits: false,
+        }
+    }
+}
+
+struct TexParser<'a> {
+    src: &'a str,
+    pos: usize,
+    depth: usize,
+    font: Option<Font>,
+}
+
+type ParseResult<T> = Result<T, String>;
+
+impl<'a> TexParser<'a> {
+    fn new(src: &'a str, depth: usize) -> Self {
+        Self {
+            src,
+            pos: 0,
+            depth,
+            font: None,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Name of the command at the current position, without consuming it.
+    fn peek_command(&self) -> Option<&'a str> {
+        let rest = self.src[self.pos..].strip_prefix('\\')?;
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        if letters > 0 {
+            Some(&rest[..letters])
+        } else {
+            rest.chars().next().map(|c| &rest[..c.len_utf8()])
+        }
+    }
+
+    fn take_command(&mut self) -> Option<&'a str> {
+        let name = self.peek_command()?;
+        self.pos += 1 + name.len();
+        Some(name)
+    }
+
+    fn expect_end(&mut self) -> ParseResult<()> {
+        self.skip_whitespace();
+        match (self.peek(), self.peek_command()) {
+            (None, _) => Ok(()),
+            (Some('}'), _) => Err("Unexpected }".to_string()),
+            (Some('&'), _) => Err("& is only allowed inside an environment".to_string()),
+            (_, Some("right")) => Err("\\right without a matching \\left".to_string()),
+            (_, Some("end")) => Err("\\end without a matching \\begin".to_string()),
+            (_, Some("\\" | "cr")) => Err("\\\\ is only allowed inside an environment".to_string()),
+            _ => Err("Unexpected input".to_string()),
+        }
+    }
+
+    /// Atoms up to the end of the current group, cell or `\left...\right`.
+    fn parse_row(&mut self) -> ParseResult<String> {
+        let mut out = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None | Some('}') | Some('&') => break,
+                Some('\\')
+                    if matches!(self.peek_command(), Some("\\" | "cr" | "end" | "right")) =>
+                {
+                    break
+                }
+                _ => {}
+            }
+            let atom = self.parse_atom()?;
+            out.push_str(&self.scripts(atom)?);
+        }
+        Ok(out)
+    }
+
+    /// Attach any `_` and `^` that follow `atom`.
+    fn scripts(&mut self, mut atom: Atom) -> ParseResult<String> {
+        let mut sub = None;
+        let mut sup = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek_command() {
+                Some("limits") => {
+                    self.take_command();
+                    atom.limits = true;
+                    continue;
+                }
+                Some("nolimits") => {
+                    self.take_command();
+                    atom.limits = false;
+                    continue;
+                }
+                _ => {}
+            }
+            let (slot, what) = match self.peek() {
+                Some('_') => (&mut sub, "subscript"),
+                Some('^') => (&mut sup, "superscript"),
+                _ => break,
+            };
+            self.bump();
+            if slot.is_some() {
+                return Err(format!("Double {}", what));
+            }
+            *slot = Some(self.parse_argument(what)?);
+        }
+
+        let base = atom.mathml;
+        Ok(match (sub, sup, atom.limits) {
+            (None, None, _) => base,
+            (Some(sub), None, false) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup), false) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup), false) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+            (Some(sub), None, true) => format!("<munder>{}{}</munder>", base, sub),
+            (None, Some(sup), true) => format!("<mover>{}{}</mover>", base, sup),
+            (Some(sub), Some(sup), true) => {
+                format!("<munderover>{}{}{}</munderover>", base, sub, sup)
+            }
+        })
+    }
+
+    /// A single-token argument, as for `x^2` or `\frac12`, or a `{group}`.
+    fn parse_argument(&mut self, what: &str) -> ParseResult<String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('}' | '&' | '^' | '_') => Err(format!("Missing argument for {}", what)),
+            Some(c) if c.is_ascii_digit() => {
+                self.bump();
+                Ok(format!("<mn>{}</mn>", self.styled(c)))
+            }
+            Some(c) if c.is_alphabetic() && self.font != Some(Font::Roman) => {
+                self.bump();
+                Ok(self.letter(c))
+            }
+            _ => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    /// Raw text of a `{...}` argument, for `\text`, `\begin` and the like.
+    fn raw_group(&mut self, what: &str) -> ParseResult<&'a str> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err(format!("Missing argument for {}", what));
+        }
+        self.bump();
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.bump() {
+            match c {
+                '\\' => {
+                    self.bump();
+                }
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(&self.src[start..self.pos - 1]),
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        Err("Missing }".to_string())
+    }
+
+    fn parse_atom(&mut self) -> ParseResult<Atom> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("Formula is nested too deeply".to_string());
+        }
+        let atom = self.parse_atom_inner();
+        self.depth -= 1;
+        atom
+    }
+
+    fn parse_atom_inner(&mut self) -> ParseResult<Atom> {
+        let c = self
+            .peek()
+            .ok_or_else(|| "Unexpected end of formula".to_string())?;
+        let mathml = match c {
+            '{' => {
+                self.bump();
+                let row = self.parse_row()?;
+                if self.bump() != Some('}') {
+                    return Err("Missing }".to_string());
+                }
+                format!("<mrow>{}</mrow>", row)
+            }
+            '\\' => return self.parse_command(),
+            // A script with nothing before it
+            '^' | '_' => "<mrow></mrow>".to_string(),
+            c if c.is_ascii_digit()
+                || c == '.'
+                    && self.src[self.pos + 1..].starts_with(|d: char| d.is_ascii_digit()) =>
+            {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.bump();
+                }
+                let number: String = self.src[start..self.pos]
+                    .chars()
+                    .map(|c| self.styled(c))
+                    .collect();
+                format!("<mn>{}</mn>", number)
+            }
+            c if c.is_alphabetic() && self.font == Some(Font::Roman) => {
+                let start = self.pos;
+                while self.peek().is_some_and(char::is_alphabetic) {
+                    self.bump();
+                }
+                let word = &self.src[start..self.pos];
+                if word.chars().count() == 1 {
+                    format!(r#"<mi mathvariant="normal">{}</mi>"#, word)
+                } else {
+                    format!("<mi>{}</mi>", word)
+                }
+            }
+            c if c.is_alphabetic() => {
+                self.bump();
+                self.letter(c)
+            }
+            _ => {
+                self.bump();
+                match c {
+                    '-' => mo("\u{2212}"),
+                    '*' => mo("\u{2217}"),
+                    '\'' => mo("\u{2032}"),
+                    '~' => space("0.333em"),
+                    '(' | ')' | '[' | ']' | '|' | '/' => {
+                        format!(r#"<mo stretchy="false">{}</mo>"#, c)
+                    }
+                    c => mo(c.encode_utf8(&mut [0; 4])),
+                }
+            }
+        };
+        Ok(mathml.into())
+    }
+
+    fn letter(&self, c: char) -> String {
+        format!("<mi>{}</mi>", self.styled(c))
+    }
+
+    fn styled(&self, c: char) -> char {
+        self.font.map_or(c, |font| styled_char(c, font))
+    }
+
+    fn parse_command(&mut self) -> ParseResult<Atom> {
+        let name = self
+            .take_command()
+            .ok_or_else(|| "Unexpected end of formula after \\".to_string())?;
+        let what = format!("\\{}", name);
+
+        if let Some(symbol) = symbol(name) {
+            return Ok(symbol);
+        }
+
+        let mathml = match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.parse_argument(&what)?;
+                let den = self.parse_argument(&what)?;
+                format!("<mfrac>{}{}</mfrac>", num, den)
+            }
+            "binom" | "dbinom" | "tbinom" => {
+                let n = self.parse_argument(&what)?;
+                let k = self.parse_argument(&what)?;
+                format!(
+                    r#"<mrow><mo>(</mo><mfrac linethickness="0">{}{}</mfrac><mo>)</mo></mrow>"#,
+                    n, k
+                )
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                let index = if self.peek() == Some('[') {
+                    self.bump();
+                    let start = self.pos;
+                    let len = self.src[start..]
+                        .find(']')
+                        .ok_or_else(|| "Missing ] in \\sqrt".to_string())?;
+                    self.pos += len + 1;
+                    let mut inner = TexParser::new(&self.src[start..start + len], self.depth);
+                    let index = inner.parse_row()?;
+                    inner.expect_end()?;
+                    Some(index)
+                } else {
+                    None
+                };
+                let radicand = self.parse_argument(&what)?;
+                match index {
+                    Some(index) => format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index),
+                    None => format!("<msqrt>{}</msqrt>", radicand),
+                }
+            }
+            "left" => {
+                let open = self.delimiter(&what)?;
+                let body = self.parse_row()?;
+                if self.take_command() != Some("right") {
+                    return Err("\\left without a matching \\right".to_string());
+                }
+                let close = self.delimiter("\\right")?;
+                format!("<mrow>{}{}{}</mrow>", fence(open), body, fence(close))
+            }
+            "middle" => fence(self.delimiter(&what)?),
+            "big" | "bigl" | "bigr" | "bigm" | "Big" | "Bigl" | "Bigr" | "Bigm" | "bigg"
+            | "biggl" | "biggr" | "biggm" | "Bigg" | "Biggl" | "Biggr" | "Biggm" => {
+                let size = match name.trim_end_matches(['l', 'r', 'm']) {
+                    "big" => "1.2em",
+                    "Big" => "1.8em",
+                    "bigg" => "2.4em",
+                    _ => "3em",
+                };
+                let delimiter = self.delimiter(&what)?;
+                format!(
+                    r#"<mo fence="true" stretchy="true" minsize="{size}" maxsize="{size}">{}</mo>"#,
+                    encode_text(delimiter)
+                )
+            }
+            "begin" => self.environment()?,
+            "text" | "textrm" | "textnormal" | "textit" | "textbf" | "textsf" | "texttt"
+            | "mbox" => {
+                let text = self.raw_group(&what)?;
+                format!(
+                    "<mtext>{}</mtext>",
+                    encode_text(&text.replace('~', "\u{A0}"))
+                )
+            }
+            "operatorname" | "operatorname*" => {
+                let starred = self.peek() == Some('*') && self.bump().is_some();
+                let text = self.raw_group(&what)?;
+                let op = format!("<mi>{}</mi>", encode_text(text.trim()));
+                return Ok(Atom {
+                    mathml: op,
+                    limits: starred,
+                });
+            }
+            "mathrm" | "mathbf" | "mathit" | "mathbb" | "mathcal" | "mathscr" | "mathfrak"
+            | "mathsf" | "mathtt" | "boldsymbol" | "bm" => {
+                let font = match name {
+                    "mathrm" => Font::Roman,
+                    "mathbf" => Font::Bold,
+                    "mathit" => Font::Italic,
+                    "mathbb" => Font::DoubleStruck,
+                    "mathcal" | "mathscr" => Font::Script,
+                    "mathfrak" => Font::Fraktur,
+                    "mathsf" => Font::SansSerif,
+                    "mathtt" => Font::Monospace,
+                    _ => Font::BoldItalic,
+                };
+                let saved = self.font.replace(font);
+                let arg = self.parse_argument(&what);
+                self.font = saved;
+                arg?
+            }
+            "hat" | "widehat" | "check" | "breve" | "acute" | "grave" | "tilde" | "widetilde"
+            | "bar" | "overline" | "vec" | "overrightarrow" | "overleftarrow" | "dot" | "ddot" => {
+                let (accent, stretchy) = match name {
+                    "hat" => ("^", false),
+                    "widehat" => ("^", true),
+                    "check" => ("\u{2C7}", false),
+                    "breve" => ("\u{2D8}", false),
+                    "acute" => ("\u{B4}", false),
+                    "grave" => ("`", false),
+                    "tilde" => ("~", false),
+                    "widetilde" => ("~", true),
+                    "bar" => ("\u{AF}", false),
+                    "overline" => ("\u{203E}", true),
+                    "vec" => ("\u{2192}", false),
+                    "overrightarrow" => ("\u{2192}", true),
+                    "overleftarrow" => ("\u{2190}", true),
+                    "dot" => ("\u{2D9}", false),
+                    _ => ("\u{A8}", false),
+                };
+                let base = self.parse_argument(&what)?;
+                format!(
+                    r#"<mover accent="true">{}<mo stretchy="{}">{}</mo></mover>"#,
+                    base, stretchy, accent
+                )
+            }
+            "underline" => {
+                let base = self.parse_argument(&what)?;
+                format!(
+                    r#"<munder accentunder="true">{}<mo stretchy="true">_</mo></munder>"#,
+                    base
+                )
+            }
+            "overbrace" | "underbrace" => {
+                let base = self.parse_argument(&what)?;
+                let mathml = if name == "overbrace" {
+                    format!(
+                        r#"<mover>{}<mo stretchy="true">&#x23DE;</mo></mover>"#,
+                        base
+                    )
+                } else {
+                    format!(
+                        r#"<munder>{}<mo stretchy="true">&#x23DF;</mo></munder>"#,
+                        base
+                    )
+                };
+                return Ok(Atom {
+                    mathml,
+                    limits: true,
+                });
+            }
+            "overset" | "stackrel" | "underset" => {
+                let script = self.parse_argument(&what)?;
+                let base = self.parse_argument(&what)?;
+                if name == "underset" {
+                    format!("<munder>{}{}</munder>", base, script)
+                } else {
+                    format!("<mover>{}{}</mover>", base, script)
+                }
+            }
+            "not" => {
+                let relation = self.parse_argument(&what)?;
+                match relation.strip_suffix("</mo>") {
+                    Some(start) => format!("{}\u{338}</mo>", start),
+                    None => return Err("\\not must be followed by a relation".to_string()),
+                }
+            }
+            "pmod" => {
+                let modulus = self.parse_argument(&what)?;
+                format!(
+                    r#"<mspace width="0.444em"/><mo stretchy="false">(</mo><mi>mod</mi><mspace width="0.333em"/>{}<mo stretchy="false">)</mo>"#,
+                    modulus
+                )
+            }
+            "bmod" => r#"<mo lspace="0.222em" rspace="0.222em">mod</mo>"#.to_string(),
+            "mod" => {
+                r#"<mspace width="0.833em"/><mi>mod</mi><mspace width="0.333em"/>"#.to_string()
+            }
+            "phantom" => format!("<mphantom>{}</mphantom>", self.parse_argument(&what)?),
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => String::new(),
+            "," | "thinspace" => space("0.1667em"),
+            ":" | ">" | "medspace" => space("0.2222em"),
+            ";" | "thickspace" => space("0.2778em"),
+            "!" | "negthinspace" => space("-0.1667em"),
+            " " => space("0.333em"),
+            "enspace" => space("0.5em"),
+            "quad" => space("1em"),
+            "qquad" => space("2em"),
+            "{" | "}" | "|" | "#" | "$" | "%" | "&" | "_" => {
+                let text = if name == "|" { "\u{2016}" } else { name };
+                mo(text)
+            }
+            "right" => return Err("\\right without a matching \\left".to_string()),
+            "end" => return Err("\\end without a matching \\begin".to_string()),
+            _ => return Err(format!("Unknown command {}", what)),
+        };
+        Ok(mathml.into())
+    }
+
+    /// The delimiter after `\left`, `\right`, `\big`... (empty for `.`).
+    fn delimiter(&mut self, what: &str) -> ParseResult<&'static str> {
+        self.skip_whitespace();
+        if self.peek() == Some('\\') {
+            let name = self.take_command().unwrap_or_default();
+            return Ok(match name {
+                "{" | "lbrace" => "{",
+                "}" | "rbrace" => "}",
+                "|" | "Vert" | "lVert" | "rVert" => "\u{2016}",
+                "vert" | "lvert" | "rvert" => "|",
+                "langle" => "\u{27E8}",
+                "rangle" => "\u{27E9}",
+                "lfloor" => "\u{230A}",
+                "rfloor" => "\u{230B}",
+                "lceil" => "\u{2308}",
+                "rceil" => "\u{2309}",
+                "backslash" => "\\",
+                "uparrow" => "\u{2191}",
+                "downarrow" => "\u{2193}",
+                "Uparrow" => "\u{21D1}",
+                "Downarrow" => "\u{21D3}",
+                _ => return Err(format!("\\{} is not a delimiter", name)),
+            });
+        }
+        Ok(match self.bump() {
+            Some('(') => "(",
+            Some(')') => ")",
+            Some('[') => "[",
+            Some(']') => "]",
+            Some('|') => "|",
+            Some('/') => "/",
+            Some('<') => "\u{27E8}",
+            Some('>') => "\u{27E9}",
+            Some('.') => "",
+            Some(c) => return Err(format!("{} is not a delimiter", c)),
+            None => return Err(format!("Missing delimiter after {}", what)),
+        })
+    }
+
+    /// `\begin{name}...\end{name}`, with the `\begin` already consumed.
+    fn environment(&mut self) -> ParseResult<String> {
+        let name = self.raw_group("\\begin")?;
+        let (open, close, align) = match name {
+            "matrix" | "smallmatrix" | "array" => ("", "", "center"),
+            "pmatrix" => ("(", ")", "center"),
+            "bmatrix" => ("[", "]", "center"),
+            "Bmatrix" => ("{", "}", "center"),
+            "vmatrix" => ("|", "|", "center"),
+            "Vmatrix" => ("\u{2016}", "\u{2016}", "center"),
+            "cases" => ("{", "", "left"),
+            "aligned" | "align" | "align*" | "split" => ("", "", "right left"),
+            "gathered" | "gather" | "gather*" => ("", "", "center"),
+            _ => return Err(format!("Unknown environment {}", name)),
+        };
+        if name == "array" {
+            self.raw_group("\\begin{array}")?;
+        }
+
+        let mut rows: Vec<Vec<String>> = Vec::new();
+        loop {
+            let mut cells = vec![self.parse_row()?];
+            while self.peek() == Some('&') {
+                self.bump();
+                cells.push(self.parse_row()?);
+            }
+            rows.push(cells);
+            match self.take_command() {
+                Some("\\" | "cr") => continue,
+                Some("end") => break,
+                _ => return Err(format!("Missing \\end{{{}}}", name)),
+            }
+        }
+        let end = self.raw_group("\\end")?;
+        if end != name {
+            return Err(format!("\\begin{{{}}} ended by \\end{{{}}}", name, end));
+        }
+        // A trailing \\ doesn't start another row
+        if rows.len() > 1 && rows.last().is_some_and(|r| r.len() == 1 && r[0].is_empty()) {
+            rows.pop();
+        }
+
+        let columns = rows.iter().map(Vec::len).max().unwrap_or(1);
+        let columnalign = align
+            .split(' ')
+            .cycle()
+            .take(columns)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let mut table = format!(r#"<mtable columnalign="{}">"#, columnalign);
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str(&format!("<mtd><mrow>{}</mrow></mtd>", cell));
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        Ok(format!(
+            "<mrow>{}{}{}</mrow>",
+            fence(open),
+            table,
+            fence(close)
+        ))
+    }
+}
+
+fn mo(text: &str) -> String {
+    format!("<mo>{}</mo>", encode_text(text))
+}
+
+fn space(width: &str) -> String {
+    format!(r#"<mspace width="{}"/>"#, width)
+}
+
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        String::new()
+    } else {
+        format!(
+            r#"<mo fence="true" stretchy="true">{}</mo>"#,
+            encode_text(delimiter)
+        )
+    }
+}
+
+/// Letters, operators and functions that are a single command.
+fn symbol(name: &str) -> Option<Atom> {
+    let mi = |text: &str| Some(Atom::from(format!("<mi>{}</mi>", text)));
+    let upright = |text: &str| {
+        Some(Atom::from(format!(
+            r#"<mi mathvariant="normal">{}</mi>"#,
+            text
+        )))
+    };
+    let op = |text: &str| Some(Atom::from(mo(text)));
+    let big = |text: &str, limits: bool| {
+        Some(Atom {
+            mathml: format!(
+                r#"<mo largeop="true" movablelimits="{}">{}</mo>"#,
+                limits, text
+            ),
+            limits,
+        })
+    };
+    let limit_op = |text: &str| {
+        Some(Atom {
+            mathml: format!(r#"<mo movablelimits="true" form="prefix">{}</mo>"#, text),
+            limits: true,
+        })
+    };
+
+    match name {
+        // Greek
+        "alpha" => mi("α"),
+        "beta" => mi("β"),
+        "gamma" => mi("γ"),
+        "delta" => mi("δ"),
+        "epsilon" => mi("ϵ"),
+        "varepsilon" => mi("ε"),
+        "zeta" => mi("ζ"),
+        "eta" => mi("η"),
+        "theta" => mi("θ"),
+        "vartheta" => mi("ϑ"),
+        "iota" => mi("ι"),
+        "kappa" => mi("κ"),
+        "lambda" => mi("λ"),
+        "mu" => mi("μ"),
+        "nu" => mi("ν"),
+        "xi" => mi("ξ"),
+        "omicron" => mi("ο"),
+        "pi" => mi("π"),
+        "varpi" => mi("ϖ"),
+        "rho" => mi("ρ"),
+        "varrho" => mi("ϱ"),
+        "sigma" => mi("σ"),
+        "varsigma" => mi("ς"),
+        "tau" => mi("τ"),
+        "upsilon" => mi("υ"),
+        "phi" => mi("ϕ"),
+        "varphi" => mi("φ"),
+        "chi" => mi("χ"),
+        "psi" => mi("ψ"),
+        "omega" => mi("ω"),
+        "Gamma" => upright("Γ"),
+        "Delta" => upright("Δ"),
+        "Theta" => upright("Θ"),
+        "Lambda" => upright("Λ"),
+        "Xi" => upright("Ξ"),
+        "Pi" => upright("Π"),
+        "Sigma" => upright("Σ"),
+        "Upsilon" => upright("Υ"),
+        "Phi" => upright("Φ"),
+        "Psi" => upright("Ψ"),
+        "Omega" => upright("Ω"),
+
+        // Letter-like symbols
+        "infty" => mi("∞"),
+        "ell" => mi("ℓ"),
+        "hbar" => mi("ℏ"),
+        "aleph" => mi("ℵ"),
+        "emptyset" | "varnothing" => upright("∅"),
+        "partial" => mi("∂"),
+        "nabla" => upright("∇"),
+        "Re" => upright("ℜ"),
+        "Im" => upright("ℑ"),
+        "wp" => mi("℘"),
+        "top" => upright("⊤"),
+        "bot" => upright("⊥"),
+        "imath" => mi("ı"),
+        "jmath" => mi("ȷ"),
+
+        // Binary operators
+        "pm" => op("±"),
+        "mp" => op("∓"),
+        "times" => op("×"),
+        "div" => op("÷"),
+        "cdot" => op("⋅"),
+        "ast" => op("∗"),
+        "star" => op("⋆"),
+        "circ" => op("∘"),
+        "bullet" => op("∙"),
+        "oplus" => op("⊕"),
+        "ominus" => op("⊖"),
+        "otimes" => op("⊗"),
+        "oslash" => op("⊘"),
+        "odot" => op("⊙"),
+        "cup" => op("∪"),
+        "cap" => op("∩"),
+        "sqcup" => op("⊔"),
+        "sqcap" => op("⊓"),
+        "vee" | "lor" => op("∨"),
+        "wedge" | "land" => op("∧"),
+        "setminus" => op("∖"),
+        "wr" => op("≀"),
+        "dagger" => op("†"),
+        "ddagger" => op("‡"),
+
+        // Relations
+        "leq" | "le" => op("≤"),
+        "geq" | "ge" => op("≥"),
+        "neq" | "ne" => op("≠"),
+        "equiv" => op("≡"),
+        "approx" => op("≈"),
+        "cong" => op("≅"),
+        "sim" => op("∼"),
+        "simeq" => op("≃"),
+        "propto" => op("∝"),
+        "ll" => op("≪"),
+        "gg" => op("≫"),
+        "prec" => op("≺"),
+        "succ" => op("≻"),
+        "preceq" => op("⪯"),
+        "succeq" => op("⪰"),
+        "subset" => op("⊂"),
+        "supset" => op("⊃"),
+        "subseteq" => op("⊆"),
+        "supseteq" => op("⊇"),
+        "subsetneq" => op("⊊"),
+        "supsetneq" => op("⊋"),
+        "in" => op("∈"),
+        "ni" => op("∋"),
+        "notin" => op("∉"),
+        "mid" => op("∣"),
+        "nmid" => op("∤"),
+        "parallel" => op("∥"),
+        "perp" => op("⊥"),
+        "vdash" => op("⊢"),
+        "dashv" => op("⊣"),
+        "models" => op("⊨"),
+        "doteq" => op("≐"),
+        "asymp" => op("≍"),
+        "triangleq" => op("≜"),
+        "coloneqq" => op("≔"),
+        "colon" => op(":"),
+
+        // Arrows
+        "to" | "rightarrow" => op("→"),
+        "gets" | "leftarrow" => op("←"),
+        "leftrightarrow" => op("↔"),
+        "Rightarrow" => op("⇒"),
+        "Leftarrow" => op("⇐"),
+        "Leftrightarrow" => op("⇔"),
+        "implies" => op("⟹"),
+        "impliedby" => op("⟸"),
+        "iff" => op("⟺"),
+        "mapsto" => op("↦"),
+        "longrightarrow" => op("⟶"),
+        "longleftarrow" => op("⟵"),
+        "longmapsto" => op("⟼"),
+        "hookrightarrow" => op("↪"),
+        "hookleftarrow" => op("↩"),
+        "twoheadrightarrow" => op("↠"),
+        "rightleftharpoons" => op("⇌"),
+        "uparrow" => op("↑"),
+        "downarrow" => op("↓"),
+        "updownarrow" => op("↕"),
+        "Uparrow" => op("⇑"),
+        "Downarrow" => op("⇓"),
+        "nearrow" => op("↗"),
+        "searrow" => op("↘"),
+
+        // Logic, dots and delimiters
+        "forall" => op("∀"),
+        "exists" => op("∃"),
+        "nexists" => op("∄"),
+        "neg" | "lnot" => op("¬"),
+        "therefore" => op("∴"),
+        "because" => op("∵"),
+        "ldots" | "dots" | "dotsc" | "dotsb" => op("…"),
+        "cdots" => op("⋯"),
+        "vdots" => op("⋮"),
+        "ddots" => op("⋱"),
+        "langle" => op("⟨"),
+        "rangle" => op("⟩"),
+        "lfloor" => op("⌊"),
+        "rfloor" => op("⌋"),
+        "lceil" => op("⌈"),
+        "rceil" => op("⌉"),
+        "lbrace" => op("{"),
+        "rbrace" => op("}"),
+        "vert" | "lvert" | "rvert" => op("|"),
+        "Vert" | "lVert" | "rVert" => op("‖"),
+        "backslash" => op("\\"),
+        "prime" => op("′"),
+        "angle" => op("∠"),
+        "triangle" => op("△"),
+        "square" | "Box" => op("□"),
+        "diamond" => op("⋄"),
+
+        // Big operators
+        "sum" => big("∑", true),
+        "prod" => big("∏", true),
+        "coprod" => big("∐", true),
+        "bigcup" => big("⋃", true),
+        "bigcap" => big("⋂", true),
+        "bigsqcup" => big("⨆", true),
+        "bigvee" => big("⋁", true),
+        "bigwedge" => big("⋀", true),
+        "bigoplus" => big("⨁", true),
+        "bigotimes" => big("⨂", true),
+        "bigodot" => big("⨀", true),
+        "int" => big("∫", false),
+        "iint" => big("∬", false),
+        "iiint" => big("∭", false),
+        "oint" => big("∮", false),
+
+        // Functions
+        "arccos" | "arcsin" | "arctan" | "arg" | "cos" | "cosh" | "cot" | "coth" | "csc"
+        | "deg" | "dim" | "exp" | "hom" | "ker" | "lg" | "ln" | "log" | "sec" | "sin" | "sinh"
+        | "tan" | "tanh" => mi(name),
+        "det" | "gcd" | "inf" | "lim" | "max" | "min" | "Pr" | "sup" => limit_op(name),
+        "liminf" => limit_op("lim inf"),
+        "limsup" => limit_op("lim sup"),
+
+        _ => None,
+    }
+}
+
+/// `c` in one of the Unicode mathematical alphabets.
+fn styled_char(c: char, font: Font) -> char {
+    // (capital A, small a, digit 0) for each alphabet
+    let (upper, lower, digit) = match font {
+        Font::Roman => return c,
+        Font::Bold => (0x1D400, 0x1D41A, Some(0x1D7CE)),
+        Font::Italic => (0x1D434, 0x1D44E, None),
+        Font::BoldItalic => (0x1D468, 0x1D482, None),
+        Font::Script => (0x1D49C, 0x1D4B6, None),
+        Font::Fraktur => (0x1D504, 0x1D51E, None),
+        Font::DoubleStruck => (0x1D538, 0x1D552, Some(0x1D7D8)),
+        Font::SansSerif => (0x1D5A0, 0x1D5BA, Some(0x1D7E2)),
+        Font::Monospace => (0x1D670, 0x1D68A, Some(0x1D7F6)),
+    };
+
+    // Letters that predate the math alphabets live in Letterlike Symbols
+    let exception = match (font, c) {
+        (Font::Italic, 'h') => Some('ℎ'),
+        (Font::Script, 'B') => Some('ℬ'),
+        (Font::Script, 'E') => Some('ℰ'),
+        (Font::Script, 'F') => Some('ℱ'),
+        (Font::Script, 'H') => Some('ℋ'),
+        (Font::Script, 'I') => Some('ℐ'),
+        (Font::Script, 'L') => Some('ℒ'),
+        (Font::Script, 'M') => Some('ℳ'),
+        (Font::Script, 'R') => Some('ℛ'),
+        (Font::Script, 'e') => Some('ℯ'),
+        (Font::Script, 'g') => Some('ℊ'),
+        (Font::Script, 'o') => Some('ℴ'),
+        (Font::Fraktur, 'C') => Some('ℭ'),
+        (Font::Fraktur, 'H') => Some('ℌ'),
+        (Font::Fraktur, 'I') => Some('ℑ'),
+        (Font::Fraktur, 'R') => Some('ℜ'),
+        (Font::Fraktur, 'Z') => Some('ℨ'),
+        (Font::DoubleStruck, 'C') => Some('ℂ'),
+        (Font::DoubleStruck, 'H') => Some('ℍ'),
+        (Font::DoubleStruck, 'N') => Some('ℕ'),
+        (Font::DoubleStruck, 'P') => Some('ℙ'),
+        (Font::DoubleStruck, 'Q') => Some('ℚ'),
+        (Font::DoubleStruck, 'R') => Some('ℝ'),
+        (Font::DoubleStruck, 'Z') => Some('ℤ'),
+        _ => None,
+    };
+    if let Some(exception) = exception {
+        return exception;
+    }
+
+    let code = match c {
+        'A'..='Z' => upper + (c as u32 - 'A' as u32),
+        'a'..='z' => lower + (c as u32 - 'a' as u32),
+        '0'..='9' => match digit {
+            Some(digit) => digit + (c as u32 - '0' as u32),
+            None => return c,
+        },
+        _ => return c,
+    };
+    char::from_u32(code).unwrap_or(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, markdown::MarkdownParser};
+
+    /// `markdown` after the math pass, with each formula shown as `[tex]`.
+    fn math_in(markdown: &str) -> String {
+        const TEX: &str = r#"<annotation encoding="application/x-tex">"#;
+        let mut trusted = TrustedHtml::default();
+        let replaced = replace_math(markdown, &mut trusted);
+        let html = trusted.restore(&replaced);
+
+        let mut out = String::new();
+        let mut rest = html.as_str();
+        while let Some(start) = rest.find("<math") {
+            out.push_str(&rest[..start]);
+            let tex = &rest[rest.find(TEX).unwrap() + TEX.len()..];
+            out.push_str(&format!("[{}]", &tex[..tex.find("</annotation>").unwrap()]));
+            rest = &rest[rest.find("</math>").unwrap() + "</math>".len()..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    #[test]
+    fn code_is_left_alone() {
+        assert_eq!(
+            math_in("Text\n\n    indented $x^2$ code\n\nAnd $y$ here\n"),
+            "Text\n\n    indented $x^2$ code\n\nAnd [y] here\n"
+        );
+        assert_eq!(
+            math_in("```\n$x$\n```\n$z$ and `$w$`\n"),
+            "```\n$x$\n```\n[z] and `$w$`\n"
+        );
+        assert_eq!(math_in("> ```\n> $q$\n> ```\n"), "> ```\n> $q$\n> ```\n");
+    }
+
+    #[test]
+    fn indented_text_that_is_not_code_still_gets_math() {
+        assert_eq!(math_in("Para\n    more $x$\n"), "Para\n    more [x]\n");
+        assert_eq!(
+            math_in("- item\n\n    list para $x$\n"),
+            "- item\n\n    list para [x]\n"
+        );
+    }
+
+    #[test]
+    fn indented_code_renders_as_written() {
+        let parser = MarkdownParser::new(&Config::from_env());
+        let html = parser.render("    indented $x^2$ code\n").unwrap().html;
+
+        assert!(html.contains("indented $x^2$ code"), "{}", html);
+        assert!(!html.contains("<math"), "{}", html);
+    }
+
+    #[test]
+    fn dollar_amounts_stay_text() {
+        assert_eq!(
+            math_in("costs $5 and $10 today\n"),
+            "costs $5 and $10 today\n"
+        );
+        assert_eq!(math_in("from $ 5 to $ 10\n"), "from $ 5 to $ 10\n");
+        assert_eq!(math_in("$5 or $x$\n"), "$5 or [x]\n");
+        assert_eq!(math_in(r"\$x$ is escaped\n"), r"\$x$ is escaped\n");
+        assert_eq!(math_in("$a\n\nb$\n"), "$a\n\nb$\n");
+        assert_eq!(math_in("$$\na + b\n$$\n"), "[a + b]\n");
+    }
+
+    #[test]
+    fn tex_converts_to_mathml() {
+        let body = |tex: &str| {
+            let mathml = to_mathml(tex, false).unwrap();
+            let start = mathml.find("<mrow>").unwrap() + 6;
+            let end = mathml.rfind("</mrow><annotation").unwrap();
+            mathml[start..end].to_string()
+        };
+
+        assert_eq!(body("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(body(r"\alpha_i"), "<msub><mi>α</mi><mi>i</mi></msub>");
+        assert_eq!(
+            body(r"\frac{a}{b}"),
+            "<mfrac><mrow><mi>a</mi></mrow><mrow><mi>b</mi></mrow></mfrac>"
+        );
+        assert_eq!(body(r"\sqrt{x}"), "<msqrt><mrow><mi>x</mi></mrow></msqrt>");
+        assert_eq!(body(r"\mathbb{R}"), "<mrow><mi>ℝ</mi></mrow>");
+        assert_eq!(body("a<b"), "<mi>a</mi><mo>&lt;</mo><mi>b</mi>");
+        assert!(body(r"\sum_{i=1}^n i").starts_with("<munderover><mo largeop"));
+        assert!(body(r"\begin{cases} 1 & x \\ 0 & y \end{cases}").contains("<mtable"));
+    }
+
+    #[test]
+    fn display_and_annotation() {
+        let mathml = to_mathml(" a<b ", true).unwrap();
+        assert!(mathml.contains(r#"display="block""#));
+        assert!(mathml.contains(r#"encoding="application/x-tex">a&lt;b</annotation>"#));
+        assert!(to_mathml("x", false)
+            .unwrap()
+            .contains(r#"display="inline""#));
+    }
+
+    #[test]
+    fn bad_tex_is_an_error() {
+        assert_eq!(
+            to_mathml(r"\frac{a}", false).unwrap_err(),
+            r"Missing argument for \frac"
+        );
+        assert_eq!(
+            to_mathml(r"\nosuch", false).unwrap_err(),
+            r"Unknown command \nosuch"
+        );
+        assert_eq!(
+            to_mathml(&"{".repeat(100), false).unwrap_err(),
+            "Formula is nested too deeply"
+        );
+
+        let rendered = render(r"\nosuch<b>", true);
+        assert!(rendered.starts_with(r#"<span class="math-error math-error-display""#));
+        assert!(rendered.contains(r"<code>\nosuch&lt;b&gt;</code>"));
+    }
+}
@@ -1,3 +1,10 @@
+mod admonitions;
+pub mod highlight;
+pub mod math;
 pub mod parser;
+pub mod sanitize;
+mod scan;
+pub mod shortcodes;
 
 pub use parser::*;
+pub use sanitize::HtmlPolicy;
@@ -1,100 +1,175 @@
 use anyhow::Result;
 use pulldown_cmark::{html, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
-use syntect::highlighting::ThemeSet;
-use syntect::html::highlighted_html_for_string;
-use syntect::parsing::SyntaxSet;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use super::admonitions::{render_admonitions, replace_containers};
+use super::highlight::{FenceInfo, Highlighter};
+use super::math::replace_math;
+use super::sanitize::{sanitize, HtmlPolicy, TrustedHtml};
+use super::shortcodes::Shortcodes;
+use crate::config::Config;
+
+/// A heading in a rendered post, linked from the table of contents.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub title: String,
+}
 
-pub struct MarkdownParser {
-    syntax_set: SyntaxSet,
-    theme_set: ThemeSet,
+/// A rendered post: its HTML and the headings in it.
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
 }
 
-impl Default for MarkdownParser {
-    fn default() -> Self {
-        Self::new()
-    }
+pub struct MarkdownParser {
+    highlighter: Highlighter,
+    shortcodes: Shortcodes,
+    policy: HtmlPolicy,
+    heading_anchors: bool,
 }
 
 impl MarkdownParser {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         Self {
-            syntax_set: SyntaxSet::load_defaults_newlines(),
-            theme_set: ThemeSet::load_defaults(),
+            highlighter: Highlighter::new(config),
+            shortcodes: Shortcodes::default(),
+            policy: config.html_policy.clone(),
+            heading_anchors: config.heading_anchors,
         }
     }
 
-    pub fn parse(&self, markdown: &str) -> Result<String> {
+    /// Render markdown to HTML that is safe to show as-is: raw HTML in the
+    /// source is cleaned according to the parser's [`HtmlPolicy`]. Headings
+    /// get unique ids (from `{#id}` or their text) and make up the TOC.
+    /// `$...$` and `$$...$$` math is rendered to MathML, and `> [!NOTE]` and
+    /// `:::note` callouts become admonitions. Shortcodes are
+    /// expanded; any that fail are returned as [`ShortcodeErrors`] with
+    /// their line numbers.
+    ///
+    /// [`ShortcodeErrors`]: super::shortcodes::ShortcodeErrors
+    pub fn render(&self, markdown: &str) -> Result<Rendered> {
+        let markdown = TrustedHtml::strip_markers(markdown);
+        let mut trusted = TrustedHtml::default();
+        let markdown = self.shortcodes.replace(&markdown, &mut trusted)?;
+        let (markdown, containers) = replace_containers(&markdown, &mut trusted);
+        let markdown = replace_math(&markdown, &mut trusted);
         let options = Options::all();
-        let parser = Parser::new_ext(markdown, options);
+        let parser = Parser::new_ext(&markdown, options);
 
         let mut in_code_block = false;
-        let mut code_lang = String::new();
+        let mut fence = FenceInfo::default();
         let mut code_content = String::new();
 
+        let mut heading_start: Option<usize> = None;
+        let mut heading_text = String::new();
+        let mut heading_ids: HashSet<String> = HashSet::new();
+        let mut toc = Vec::new();
+
         let mut events: Vec<Event> = Vec::new();
 
         for event in parser {
             match event {
                 Event::Start(Tag::CodeBlock(kind)) => {
                     in_code_block = true;
-                    code_lang = match kind {
-                        CodeBlockKind::Fenced(lang) => lang.to_string(),
-                        CodeBlockKind::Indented => String::new(),
+                    fence = match kind {
+                        CodeBlockKind::Fenced(info) => FenceInfo::parse(&info),
+                        CodeBlockKind::Indented => FenceInfo::default(),
                     };
                     code_content.clear();
                 }
                 Event::End(TagEnd::CodeBlock) => {
                     in_code_block = false;
-                    let highlighted = self.highlight_code(&code_content, &code_lang);
-                    events.push(Event::Html(highlighted.into()));
+                    let highlighted = self.highlighter.highlight(&code_content, &fence);
+                    events.push(Event::Html(trusted.insert(highlighted).into()));
                 }
                 Event::Text(text) if in_code_block => {
                     code_content.push_str(&text);
                 }
+                Event::Start(Tag::Heading { .. }) => {
+                    heading_start = Some(events.len());
+                    heading_text.clear();
+                    events.push(event);
+                }
+                Event::Text(ref text) | Event::Code(ref text) if heading_start.is_some() => {
+                    heading_text.push_str(&TrustedHtml::remove_placeholders(text));
+                    events.push(event);
+                }
+                Event::End(TagEnd::Heading(level)) => {
+                    if let Some(Event::Start(Tag::Heading { id, .. })) =
+                        heading_start.take().and_then(|start| events.get_mut(start))
+                    {
+                        let base = match id.take() {
+                            Some(id) => id.to_string(),
+                            None => heading_id(&heading_text),
+                        };
+                        let unique = unique_id(&mut heading_ids, base);
+                        *id = Some(unique.clone().into());
+
+                        if self.heading_anchors {
+                            events.push(Event::InlineHtml(
+                                format!(
+                                    r##"<a class="heading-anchor" href="#{}">#</a>"##,
+                                    html_escape::encode_double_quoted_attribute(&unique)
+                                )
+                                .into(),
+                            ));
+                        }
+                        toc.push(TocEntry {
+                            level: level as u8,
+                            id: unique,
+                            title: heading_text
+                                .split_whitespace()
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                        });
+                    }
+                    events.push(event);
+                }
                 _ => {
                     events.push(event);
                 }
             }
         }
 
+        let events = render_admonitions(events, &containers, &mut trusted);
         let mut html_output = String::new();
         html::push_html(&mut html_output, events.into_iter());
 
-        Ok(html_output)
+        Ok(Rendered {
+            html: trusted.restore(&sanitize(&html_output, &self.policy)),
+            toc,
+        })
     }
+}
 
-    fn highlight_code(&self, code: &str, lang: &str) -> String {
-        let lang = if lang.is_empty() { "txt" } else { lang };
-
-        let syntax = self
-            .syntax_set
-            .find_syntax_by_token(lang)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-
-        let theme = &self.theme_set.themes["base16-ocean.dark"];
-
-        match highlighted_html_for_string(code, &self.syntax_set, syntax, theme) {
-            Ok(html) => format!(
-                r#"<div class="code-block"><pre><code class="language-{}">{}</code></pre></div>"#,
-                lang, html
-            ),
-            Err(_) => format!(
-                r#"<div class="code-block"><pre><code class="language-{}">{}</code></pre></div>"#,
-                lang,
-                html_escape::encode_text(code)
-            ),
+/// Anchor id for a heading: lowercase words joined by dashes.
+fn heading_id(text: &str) -> String {
+    let mut id = String::new();
+    for c in text.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() || c == '_' {
+            id.push(c);
+        } else if (c.is_whitespace() || c == '-') && !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
         }
     }
+    let id = id.trim_end_matches('-');
+    if id.is_empty() {
+        "section".to_string()
+    } else {
+        id.to_string()
+    }
 }
 
-pub fn parse_frontmatter(content: &str) -> Result<(crate::models::post::PostFrontmatter, String)> {
-    let matter = gray_matter::Matter::<gray_matter::engine::YAML>::new();
-    let result = matter.parse(content);
-
-    let frontmatter: crate::models::post::PostFrontmatter = result
-        .data
-        .ok_or_else(|| anyhow::anyhow!("No frontmatter found"))?
-        .deserialize()?;
-
-    Ok((frontmatter, result.content))
+/// `base`, or `base-1`, `base-2`... if it's already taken.
+fn unique_id(taken: &mut HashSet<String>, base: String) -> String {
+    let mut id = base.clone();
+    let mut n = 1;
+    while !taken.insert(id.clone()) {
+        id = format!("{}-{}", base, n);
+        n += 1;
+    }
+    id
 }
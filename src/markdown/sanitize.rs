@@ -0,0 +1,633 @@
+//! Allowlist-based cleanup of rendered post HTML.
+//!
+//! Markdown lets writers embed raw HTML, so the rendered output is run
+//! through [`sanitize`] before it is stored. The sanitizer never copies input
+//! markup through: it tokenizes the HTML and writes a fresh document that
+//! contains only allowed tags and attributes, with every text node and
+//! attribute value re-escaped.
+
+use std::fmt::Write;
+
+/// What raw HTML in posts may contain beyond the built-in allowlist.
+#[derive(Debug, Clone)]
+pub struct HtmlPolicy {
+    /// Hosts iframes may be loaded from (e.g. `www.youtube-nocookie.com`)
+    pub iframe_hosts: Vec<String>,
+    /// Schemes allowed in `href`/`src`; relative URLs are always allowed
+    pub url_schemes: Vec<String>,
+}
+
+impl Default for HtmlPolicy {
+    fn default() -> Self {
+        Self {
+            iframe_hosts: [
+                "www.youtube.com",
+                "www.youtube-nocookie.com",
+                "player.vimeo.com",
+            ]
+            .map(String::from)
+            .to_vec(),
+            url_schemes: ["http", "https", "mailto"].map(String::from).to_vec(),
+        }
+    }
+}
+
+#[rustfmt::skip]
+const ALLOWED_TAGS: &[&str] = &[
+    "a", "abbr", "b", "blockquote", "br", "caption", "cite", "code", "col", "colgroup", "dd",
+    "del", "details", "dfn", "div", "dl", "dt", "em", "figcaption", "figure", "h1", "h2", "h3",
+    "h4", "h5", "h6", "hr", "i", "iframe", "img", "input", "ins", "kbd", "li", "mark", "ol", "p",
+    "pre", "q", "s", "samp", "small", "span", "strike", "strong", "sub", "summary", "sup", "table",
+    "tbody", "td", "tfoot", "th", "thead", "time", "tr", "u", "ul", "var", "wbr",
+];
+
+const VOID_TAGS: &[&str] = &["br", "col", "hr", "img", "input", "wbr"];
+
+/// Elements whose content is raw text; they are dropped together with it.
+#[rustfmt::skip]
+const RAW_TEXT_TAGS: &[&str] = &[
+    "iframe", "noembed", "noframes", "noscript", "plaintext", "script", "style", "textarea",
+    "title", "xmp",
+];
+
+const GLOBAL_ATTRS: &[&str] = &["class", "dir", "id", "lang", "title"];
+
+const URL_ATTRS: &[&str] = &["cite", "href", "src"];
+
+/// Sandbox given to every embedded iframe.
+pub(crate) const IFRAME_SANDBOX: &str =
+    "allow-scripts allow-same-origin allow-popups allow-presentation";
+
+fn tag_attrs(tag: &str) -> &'static [&'static str] {
+    match tag {
+        "a" => &["href", "hreflang"],
+        "blockquote" | "del" | "ins" | "q" => &["cite"],
+        "col" | "colgroup" => &["span"],
+        "details" => &["open"],
+        "iframe" => &[
+            "allow",
+            "allowfullscreen",
+            "height",
+            "loading",
+            "src",
+            "width",
+        ],
+        "img" => &["alt", "height", "loading", "src", "width"],
+        "input" => &["checked", "type"],
+        "li" => &["value"],
+        "ol" => &["reversed", "start", "type"],
+        "td" | "th" => &["colspan", "rowspan", "scope", "style"],
+        "time" => &["datetime"],
+        _ => &[],
+    }
+}
+
+struct Tag<'a> {
+    name: String,
+    attrs: Vec<(String, &'a str)>,
+    closing: bool,
+}
+
+/// Clean `html` according to `policy`.
+pub fn sanitize(html: &str, policy: &HtmlPolicy) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut open: Vec<&'static str> = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        push_text(&mut out, &rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+            continue;
+        }
+
+        let Some((tag, len)) = parse_tag(rest) else {
+            out.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[len..];
+
+        if tag.closing {
+            if let Some(pos) = open.iter().rposition(|&t| t == tag.name) {
+                for name in open.drain(pos..).rev() {
+                    let _ = write!(out, "</{}>", name);
+                }
+            }
+            continue;
+        }
+
+        let raw_text = RAW_TEXT_TAGS.contains(&tag.name.as_str());
+        if raw_text {
+            rest = skip_raw_text(rest, &tag.name);
+        }
+
+        let Some(&name) = ALLOWED_TAGS.iter().find(|&&t| t == tag.name) else {
+            continue;
+        };
+        if let Some(attrs) = clean_attrs(name, &tag.attrs, policy) {
+            let _ = write!(out, "<{}{}>", name, attrs);
+            if raw_text {
+                let _ = write!(out, "</{}>", name);
+            } else if !VOID_TAGS.contains(&name) {
+                open.push(name);
+            }
+        }
+    }
+    push_text(&mut out, rest);
+
+    for name in open.into_iter().rev() {
+        let _ = write!(out, "</{}>", name);
+    }
+    out
+}
+
+/// Parse the tag at the start of `s` (which begins with `<`), returning it and
+/// its length. `None` means the `<` doesn't start a tag and is plain text.
+fn parse_tag(s: &str) -> Option<(Tag<'_>, usize)> {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+    let closing = bytes.get(i) == Some(&b'/');
+    if closing {
+        i += 1;
+    }
+    if !bytes.get(i)?.is_ascii_alphabetic() {
+        return None;
+    }
+
+    let name_start = i;
+    while i < bytes.len() && !is_tag_delimiter(bytes[i]) {
+        i += 1;
+    }
+    let name = s[name_start..i].to_ascii_lowercase();
+
+    let mut attrs = Vec::new();
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        match bytes.get(i)? {
+            b'>' => break,
+            _ => {
+                let attr_start = i;
+                i += 1;
+                while i < bytes.len() && !is_tag_delimiter(bytes[i]) && bytes[i] != b'=' {
+                    i += 1;
+                }
+                let attr = s[attr_start..i].to_ascii_lowercase();
+
+                while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                    i += 1;
+                }
+                let mut value = "";
+                if bytes.get(i) == Some(&b'=') {
+                    i += 1;
+                    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                        i += 1;
+                    }
+                    match bytes.get(i)? {
+                        &quote @ (b'"' | b'\'') => {
+                            let end = s[i + 1..].find(quote as char)? + i + 1;
+                            value = &s[i + 1..end];
+                            i = end + 1;
+                        }
+                        _ => {
+                            let value_start = i;
+                            while i < bytes.len()
+                                && !bytes[i].is_ascii_whitespace()
+                                && bytes[i] != b'>'
+                            {
+                                i += 1;
+                            }
+                            value = &s[value_start..i];
+                        }
+                    }
+                }
+                attrs.push((attr, value));
+            }
+        }
+    }
+
+    Some((
+        Tag {
+            name,
+            attrs,
+            closing,
+        },
+        i + 1,
+    ))
+}
+
+fn is_tag_delimiter(b: u8) -> bool {
+    b.is_ascii_whitespace() || b == b'/' || b == b'>'
+}
+
+/// Skip past the content and end tag of a raw text element.
+fn skip_raw_text<'a>(rest: &'a str, name: &str) -> &'a str {
+    if name == "plaintext" {
+        return "";
+    }
+    let lower = rest.to_ascii_lowercase();
+    let close = format!("</{}", name);
+    let mut from = 0;
+    while let Some(pos) = lower[from..].find(&close) {
+        let end = from + pos + close.len();
+        if lower
+            .as_bytes()
+            .get(end)
+            .is_none_or(|&b| is_tag_delimiter(b))
+        {
+            return rest[end..].find('>').map_or("", |gt| &rest[end + gt + 1..]);
+        }
+        from = end;
+    }
+    ""
+}
+
+/// Serialized allowed attributes of `tag`, or `None` if the element must be
+/// dropped (an image without a usable source, a disallowed iframe...).
+fn clean_attrs(tag: &str, attrs: &[(String, &str)], policy: &HtmlPolicy) -> Option<String> {
+    let mut out = String::new();
+    let mut seen: Vec<&str> = Vec::new();
+    let mut kept: Vec<&str> = Vec::new();
+
+    for (name, raw) in attrs {
+        let name = name.as_str();
+        if seen.contains(&name) {
+            continue;
+        }
+        seen.push(name);
+        if !GLOBAL_ATTRS.contains(&name) && !tag_attrs(tag).contains(&name) {
+            continue;
+        }
+
+        let mut value = TrustedHtml::remove_placeholders(&decode_entities(raw));
+        if URL_ATTRS.contains(&name) {
+            match clean_url(&value, policy) {
+                Some(url) => value = url,
+                None => continue,
+            }
+        }
+        if name == "style" {
+            match text_align(&value) {
+                Some(style) => value = style,
+                None => continue,
+            }
+        }
+        let _ = write!(
+            out,
+            " {}=\"{}\"",
+            name,
+            html_escape::encode_double_quoted_attribute(&value)
+        );
+        kept.push(name);
+    }
+
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| decode_entities(v))
+    };
+    match tag {
+        "img" if !kept.contains(&"src") => None,
+        "input" => {
+            // Task list checkboxes only, and never editable
+            attr("type")
+                .filter(|t| t.trim().eq_ignore_ascii_case("checkbox"))
+                .map(|_| out + " disabled=\"\"")
+        }
+        "iframe" => {
+            let src = attr("src").and_then(|src| clean_url(&src, policy))?;
+            let url = url::Url::parse(&src).ok()?;
+            let host = url.host_str()?;
+            if url.scheme() != "https"
+                || !policy
+                    .iframe_hosts
+                    .iter()
+                    .any(|h| h.eq_ignore_ascii_case(host))
+            {
+                return None;
+            }
+            Some(format!("{} sandbox=\"{}\"", out, IFRAME_SANDBOX))
+        }
+        _ => Some(out),
+    }
+}
+
+/// `url` with the characters browsers ignore removed, if its scheme is allowed.
+fn clean_url(url: &str, policy: &HtmlPolicy) -> Option<String> {
+    let url: String = url
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let url = url.trim_matches(|c: char| c <= ' ');
+
+    match url.find([':', '/', '?', '#']) {
+        Some(pos) if url.as_bytes()[pos] == b':' => {
+            let scheme = url[..pos].to_ascii_lowercase();
+            policy
+                .url_schemes
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&scheme))
+                .then(|| url.to_string())
+        }
+        _ => Some(url.to_string()),
+    }
+}
+
+/// The only inline style kept: table cell alignment from markdown tables.
+fn text_align(style: &str) -> Option<String> {
+    let compact: String = style
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let align = compact.strip_prefix("text-align:")?.trim_end_matches(';');
+    matches!(align, "left" | "center" | "right").then(|| format!("text-align: {}", align))
+}
+
+/// Escape text, keeping well-formed character references as they are.
+fn push_text(out: &mut String, text: &str) {
+    let mut rest = text;
+    while let Some(pos) = rest.find(['&', '<', '>', '"']) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        match rest.as_bytes()[0] {
+            b'<' => out.push_str("&lt;"),
+            b'>' => out.push_str("&gt;"),
+            b'"' => out.push_str("&quot;"),
+            _ => {
+                let reference = character_reference_len(rest);
+                if reference > 0 {
+                    out.push_str(&rest[..reference]);
+                    rest = &rest[reference..];
+                    continue;
+                }
+                out.push_str("&amp;");
+            }
+        }
+        rest = &rest[1..];
+    }
+    out.push_str(rest);
+}
+
+/// Length of the `&name;` / `&#123;` / `&#x7b;` reference at the start of `s`, or 0.
+fn character_reference_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let (start, valid): (usize, fn(&u8) -> bool) = match (bytes.get(1), bytes.get(2)) {
+        (Some(b'#'), Some(b'x' | b'X')) => (3, u8::is_ascii_hexdigit),
+        (Some(b'#'), _) => (2, u8::is_ascii_digit),
+        (Some(b), _) if b.is_ascii_alphabetic() => (1, u8::is_ascii_alphanumeric),
+        _ => return 0,
+    };
+    let len = bytes[start..].iter().take_while(|b| valid(b)).count();
+    match bytes.get(start + len) {
+        Some(b';') if len > 0 => start + len + 1,
+        _ => 0,
+    }
+}
+
+/// Decode character references in an attribute value. Unknown named
+/// references are left as they are (and end up escaped).
+fn decode_entities(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        let (decoded, len) = decode_reference(rest).unwrap_or(('&', 1));
+        out.push(decoded);
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn decode_reference(s: &str) -> Option<(char, usize)> {
+    let bytes = s.as_bytes();
+    if bytes.get(1) == Some(&b'#') {
+        let (start, radix) = match bytes.get(2) {
+            Some(b'x' | b'X') => (3, 16),
+            _ => (2, 10),
+        };
+        let len = bytes[start..]
+            .iter()
+            .take_while(|b| b.is_ascii_hexdigit() && (radix == 16 || b.is_ascii_digit()))
+            .count();
+        if len == 0 {
+            return None;
+        }
+        let c = u32::from_str_radix(&s[start..start + len], radix)
+            .ok()
+            .and_then(char::from_u32)
+            .filter(|&c| c != '\0')
+            .unwrap_or('\u{FFFD}');
+        let semicolon = usize::from(bytes.get(start + len) == Some(&b';'));
+        return Some((c, start + len + semicolon));
+    }
+
+    let len = bytes[1..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric())
+        .count();
+    let c = match &s[1..1 + len] {
+        "amp" | "AMP" => '&',
+        "lt" | "LT" => '<',
+        "gt" | "GT" => '>',
+        "quot" | "QUOT" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{A0}',
+        "colon" => ':',
+        "sol" => '/',
+        "Tab" => '\t',
+        "NewLine" => '\n',
+        _ => return None,
+    };
+    let semicolon = usize::from(bytes.get(1 + len) == Some(&b';'));
+    Some((c, 1 + len + semicolon))
+}
+
+/// HTML the renderer produces itself (code blocks, math, shortcodes), kept out of
+/// the sanitizer and spliced back in afterwards.
+#[derive(Default)]
+pub(crate) struct TrustedHtml(Vec<String>);
+
+const MARKER_START: char = '\u{E002}';
+const MARKER_END: char = '\u{E003}';
+
+impl TrustedHtml {
+    /// Strip marker characters from user input so markers can't be forged.
+    pub fn strip_markers(markdown: &str) -> std::borrow::Cow<'_, str> {
+        if markdown.contains([MARKER_START, MARKER_END]) {
+            markdown.replace([MARKER_START, MARKER_END], "").into()
+        } else {
+            markdown.into()
+        }
+    }
+
+    /// Drop placeholders from text that can't hold markup, like attribute values.
+    pub fn remove_placeholders(text: &str) -> String {
+        let mut parts = text.split(MARKER_START);
+        let mut out = parts.next().unwrap_or_default().to_string();
+        for part in parts {
+            out.push_str(part.split_once(MARKER_END).map_or(part, |(_, rest)| rest));
+        }
+        out
+    }
+
+    /// Store `html` and return the placeholder to render in its place.
+    pub fn insert(&mut self, html: String) -> String {
+        self.0.push(html);
+        format!("{}{}{}", MARKER_START, self.0.len() - 1, MARKER_END)
+    }
+
+    /// Replace the placeholders in sanitized `html` with the stored HTML.
+    pub fn restore(self, html: &str) -> String {
+        if self.0.is_empty() {
+            return html.to_string();
+        }
+        let mut out = String::with_capacity(html.len());
+        let mut parts = html.split(MARKER_START);
+        out.push_str(parts.next().unwrap_or_default());
+        for part in parts {
+            let trusted = part.split_once(MARKER_END).and_then(|(index, rest)| {
+                let html = self.0.get(index.parse::<usize>().ok()?)?;
+                Some((html, rest))
+            });
+            match trusted {
+                Some((html, rest)) => {
+                    out.push_str(html);
+                    out.push_str(rest);
+                }
+                None => out.push_str(part),
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(html: &str) -> String {
+        sanitize(html, &HtmlPolicy::default())
+    }
+
+    #[test]
+    fn scripts_are_dropped_with_their_content() {
+        assert_eq!(clean("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(clean("<SCRIPT src=x></SCRIPT >c"), "c");
+        assert_eq!(clean("<svg><script>alert(1)</script></svg>ok"), "ok");
+        assert_eq!(clean("<p>x<script>unclosed"), "<p>x</p>");
+    }
+
+    #[test]
+    fn event_handlers_are_dropped() {
+        assert_eq!(
+            clean(r#"<img src="x.png" onerror="alert(1)">"#),
+            r#"<img src="x.png">"#
+        );
+        assert_eq!(clean("<img src=x onerror=alert(1)>"), r#"<img src="x">"#);
+        assert_eq!(
+            clean(r#"<p onclick="alert(1)" class="note">t</p>"#),
+            r#"<p class="note">t</p>"#
+        );
+    }
+
+    #[test]
+    fn script_urls_are_dropped_however_they_are_spelled() {
+        for href in [
+            "javascript:alert(1)",
+            " JaVaScRiPt:alert(1)",
+            "jav&#x09;ascript:alert(1)",
+            "jav\tascript:alert(1)",
+            "javascript&colon;alert(1)",
+            "data:text/html,<script>alert(1)</script>",
+        ] {
+            assert_eq!(
+                clean(&format!(r#"<a href="{}">x</a>"#, href)),
+                "<a>x</a>",
+                "{}",
+                href
+            );
+        }
+        assert_eq!(
+            clean(r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#),
+            r#"<a href="https://example.com/?a=1&amp;b=2">x</a>"#
+        );
+        assert_eq!(
+            clean(r#"<a href="/post/x">x</a>"#),
+            r#"<a href="/post/x">x</a>"#
+        );
+    }
+
+    #[test]
+    fn only_allowlisted_https_iframes_are_kept() {
+        assert_eq!(
+            clean(r#"<iframe src="https://evil.example/x"></iframe>after"#),
+            "after"
+        );
+        assert_eq!(
+            clean(r#"<iframe src="http://www.youtube.com/embed/x"></iframe>"#),
+            ""
+        );
+        assert_eq!(clean("<iframe>no src</iframe>"), "");
+        assert_eq!(
+            clean(
+                r#"<iframe src="https://www.youtube-nocookie.com/embed/x" onload="x()"></iframe>"#
+            ),
+            format!(
+                r#"<iframe src="https://www.youtube-nocookie.com/embed/x" sandbox="{}"></iframe>"#,
+                IFRAME_SANDBOX
+            )
+        );
+    }
+
+    #[test]
+    fn trusted_html_survives_sanitizing() {
+        let mut trusted = TrustedHtml::default();
+        let code = trusted.insert(r#"<pre><code onclick="kept()">x</code></pre>"#.to_string());
+        let math = trusted.insert("<math><mi>x</mi></math>".to_string());
+
+        let html = sanitize(&format!("<p>{}</p>{}", math, code), &HtmlPolicy::default());
+        assert_eq!(
+            trusted.restore(&html),
+            r#"<p><math><mi>x</mi></math></p><pre><code onclick="kept()">x</code></pre>"#
+        );
+    }
+
+    #[test]
+    fn placeholders_cannot_be_forged() {
+        let trusted = || {
+            let mut trusted = TrustedHtml::default();
+            trusted.insert("<script>trusted()</script>".to_string());
+            trusted
+        };
+
+        // A writer typing the marker characters gets plain text, not slot 0
+        let forged = format!("<p>{}0{}</p>", MARKER_START, MARKER_END);
+        let input = TrustedHtml::strip_markers(&forged);
+        assert_eq!(input, "<p>0</p>");
+        assert_eq!(
+            trusted().restore(&sanitize(&input, &HtmlPolicy::default())),
+            "<p>0</p>"
+        );
+
+        // Nor through character references, which only decode in attributes
+        let html = sanitize(
+            r#"<p title="&#xE002;0&#xE003;">&#xE002;0&#xE003;</p>"#,
+            &HtmlPolicy::default(),
+        );
+        assert_eq!(html, r#"<p title="">&#xE002;0&#xE003;</p>"#);
+        assert_eq!(trusted().restore(&html), html);
+    }
+}
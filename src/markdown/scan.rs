@@ -0,0 +1,73 @@
+//! Helpers for passes that rewrite markdown source before it's parsed.
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::ops::Range;
+
+/// Rewrite the markdown outside code blocks, fenced or indented. `f` is
+/// given each run of text between code blocks and the zero-based line it
+/// starts on.
+pub(crate) fn outside_code_blocks(
+    markdown: &str,
+    mut f: impl FnMut(&str, usize) -> String,
+) -> String {
+    let line_of = |offset: usize| markdown[..offset].matches('\n').count();
+    let mut out = String::with_capacity(markdown.len());
+    let mut copied = 0;
+
+    for block in code_blocks(markdown) {
+        out.push_str(&f(&markdown[copied..block.start], line_of(copied)));
+        out.push_str(&markdown[block.clone()]);
+        copied = block.end;
+    }
+    out.push_str(&f(&markdown[copied..], line_of(copied)));
+    out
+}
+
+/// The code blocks in `markdown` as found by the same parser that renders
+/// it, so an indented line only counts as code where it really is code (not
+/// when it continues a paragraph or a list item). Ranges cover whole lines.
+fn code_blocks(markdown: &str) -> Vec<Range<usize>> {
+    let line_start = |i: usize| markdown[..i].rfind('\n').map_or(0, |n| n + 1);
+    let line_end = |i: usize| {
+        markdown[i..]
+            .find('\n')
+            .map_or(markdown.len(), |n| i + n + 1)
+    };
+    let mut blocks: Vec<Range<usize>> = Vec::new();
+
+    for (event, range) in Parser::new_ext(markdown, Options::all()).into_offset_iter() {
+        if !matches!(event, Event::Start(Tag::CodeBlock(_))) {
+            continue;
+        }
+        let block = line_start(range.start)..line_end(range.end.max(range.start + 1) - 1);
+        match blocks.last_mut() {
+            Some(last) if block.start < last.end => last.end = last.end.max(block.end),
+            _ => blocks.push(block),
+        }
+    }
+    blocks
+}
+
+/// Offset just past the code span whose opening backticks start at `i`, or
+/// past the opening run if the span is never closed.
+pub(crate) fn skip_code_span(bytes: &[u8], i: usize) -> usize {
+    let run = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+    closing_backticks(bytes, i + run, run).unwrap_or(i + run)
+}
+
+/// End of the code span whose closing run of `run` backticks starts at or after `from`.
+fn closing_backticks(bytes: &[u8], from: usize, run: usize) -> Option<usize> {
+    let mut i = from;
+    while i < bytes.len() {
+        if bytes[i] == b'`' {
+            let len = bytes[i..].iter().take_while(|&&b| b == b'`').count();
+            if len == run {
+                return Some(i + len);
+            }
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,691 @@
+//! Shortcodes: `{{< name arg key="value" >}}` in a post, rendered to HTML by
+//! Rust functions. Used to embed on-chain things (transactions, addresses,
+//! prices), tip buttons and videos inline.
+//!
+//! Arguments are positional or `key=value`, quoted with `"..."` when they
+//! contain spaces. A shortcode inside code isn't expanded, and neither is one
+//! written as `\{{< ... >}}`.
+
+use html_escape::{encode_double_quoted_attribute as attr, encode_text as text};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::sanitize::{TrustedHtml, IFRAME_SANDBOX};
+use super::scan::{outside_code_blocks, skip_code_span};
+
+const OPEN: &str = "{{<";
+const CLOSE: &str = ">}}";
+
+/// Renders a shortcode to HTML, or says what's wrong with its arguments.
+/// The HTML is used inline, so it should only contain phrasing elements.
+pub type ShortcodeFn = fn(&ShortcodeArgs) -> Result<String, String>;
+
+struct Shortcode {
+    params: &'static [&'static str],
+    render: ShortcodeFn,
+}
+
+/// The shortcodes posts can use, by name.
+pub struct Shortcodes(HashMap<&'static str, Shortcode>);
+
+impl Default for Shortcodes {
+    fn default() -> Self {
+        let mut shortcodes = Self(HashMap::new());
+        shortcodes.register("tx", &["hash", "chain", "label"], tx);
+        shortcodes.register("address", &["address", "chain", "label"], address);
+        shortcodes.register("price", &["symbol", "vs", "id"], price);
+        shortcodes.register("tip", &["amount", "currency", "label"], tip);
+        shortcodes.register("youtube", &["id", "start", "title"], youtube);
+        shortcodes
+    }
+}
+
+impl Shortcodes {
+    /// Add a shortcode. Positional arguments fill `params` in order; named
+    /// ones must be in `params`.
+    pub fn register(
+        &mut self,
+        name: &'static str,
+        params: &'static [&'static str],
+        render: ShortcodeFn,
+    ) {
+        self.0.insert(name, Shortcode { params, render });
+    }
+
+    /// Replace the shortcodes outside code with placeholders for their HTML.
+    pub(crate) fn replace(
+        &self,
+        markdown: &str,
+        trusted: &mut TrustedHtml,
+    ) -> Result<String, ShortcodeErrors> {
+        let mut errors = Vec::new();
+        let out = outside_code_blocks(markdown, |text, first_line| {
+            self.replace_in_text(text, first_line, trusted, &mut errors)
+        });
+        if errors.is_empty() {
+            Ok(out)
+        } else {
+            Err(ShortcodeErrors(errors))
+        }
+    }
+
+    fn replace_in_text(
+        &self,
+        text: &str,
+        first_line: usize,
+        trusted: &mut TrustedHtml,
+        errors: &mut Vec<(usize, String)>,
+    ) -> String {
+        let bytes = text.as_bytes();
+        let mut out = String::with_capacity(text.len());
+        let mut copied = 0;
+        let mut i = 0;
+
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'`' => i = skip_code_span(bytes, i),
+                b'{' if text[i..].starts_with(OPEN) => {
+                    let line = first_line + text[..i].matches('\n').count() + 1;
+                    let Some(len) = text[i..].find(CLOSE) else {
+                        errors.push((line, format!("`{}` is never closed with `{}`", OPEN, CLOSE)));
+                        break;
+                    };
+                    let end = i + len + CLOSE.len();
+                    match self.render(&text[i + OPEN.len()..i + len]) {
+                        Ok(html) => {
+                            out.push_str(&text[copied..i]);
+                            out.push_str(&trusted.insert(html));
+                            copied = end;
+                        }
+                        Err(message) => errors.push((line, message)),
+                    }
+                    i = end;
+                }
+                _ => i += 1,
+            }
+        }
+        out.push_str(&text[copied..]);
+        out
+    }
+
+    fn render(&self, source: &str) -> Result<String, String> {
+        let mut tokens = tokenize(source)?.into_iter();
+        let name = match tokens.next() {
+            Some((None, name)) => name,
+            _ => return Err("shortcode has no name".to_string()),
+        };
+        let shortcode = self
+            .0
+            .get(name.as_str())
+            .ok_or_else(|| format!("unknown shortcode `{}`", name))?;
+
+        let mut args = ShortcodeArgs::default();
+        let mut position = 0;
+        for (key, value) in tokens {
+            let key = match key {
+                Some(key) if shortcode.params.contains(&key.as_str()) => key,
+                Some(key) => return Err(format!("`{}` has no argument `{}`", name, key)),
+                None => {
+                    let key = shortcode.params.get(position).ok_or_else(|| {
+                        format!("`{}` takes at most {} positional arguments", name, position)
+                    })?;
+                    position += 1;
+                    key.to_string()
+                }
+            };
+            if args.0.insert(key.clone(), value).is_some() {
+                return Err(format!("`{}` is given more than once", key));
+            }
+        }
+        (shortcode.render)(&args).map_err(|e| format!("{}: {}", name, e))
+    }
+}
+
+/// Split shortcode source into `(key, value)` pairs; bare values have no key.
+fn tokenize(source: &str) -> Result<Vec<(Option<String>, String)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.trim().chars().peekable();
+
+    while chars.peek().is_some() {
+        let mut key = None;
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => break,
+                '=' if key.is_none() && !value.is_empty() => key = Some(std::mem::take(&mut value)),
+                '"' => loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => value.extend(chars.next()),
+                        Some(c) => value.push(c),
+                        None => return Err("unclosed `\"` in shortcode".to_string()),
+                    }
+                },
+                c => value.push(c),
+            }
+        }
+        tokens.push((key, value));
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+    Ok(tokens)
+}
+
+/// A shortcode's arguments, by parameter name.
+#[derive(Default)]
+pub struct ShortcodeArgs(HashMap<String, String>);
+
+impl ShortcodeArgs {
+    pub fn get(&self, param: &str) -> Option<&str> {
+        self.0.get(param).map(String::as_str)
+    }
+
+    pub fn require(&self, param: &str) -> Result<&str, String> {
+        self.get(param)
+            .ok_or_else(|| format!("missing `{}`", param))
+    }
+}
+
+/// Shortcodes that failed to render, with the line each is on.
+#[derive(Debug)]
+pub struct ShortcodeErrors(pub Vec<(usize, String)>);
+
+impl ShortcodeErrors {
+    /// Shift line numbers by `lines`, e.g. to count a file's frontmatter.
+    pub fn offset_lines(mut self, lines: usize) -> Self {
+        for (line, _) in &mut self.0 {
+            *line += lines;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ShortcodeErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (line, message)) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "line {}: {}", line, message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ShortcodeErrors {}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ChainKind {
+    Evm,
+    Solana,
+    Bitcoin,
+}
+
+struct Chain {
+    name: &'static str,
+    label: &'static str,
+    explorer: &'static str,
+    kind: ChainKind,
+}
+
+const CHAINS: &[Chain] = &[
+    Chain {
+        name: "ethereum",
+        label: "Ethereum",
+        explorer: "https://etherscan.io",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "sepolia",
+        label: "Sepolia",
+        explorer: "https://sepolia.etherscan.io",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "base",
+        label: "Base",
+        explorer: "https://basescan.org",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "polygon",
+        label: "Polygon",
+        explorer: "https://polygonscan.com",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "arbitrum",
+        label: "Arbitrum",
+        explorer: "https://arbiscan.io",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "optimism",
+        label: "Optimism",
+        explorer: "https://optimistic.etherscan.io",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "avalanche",
+        label: "Avalanche",
+        explorer: "https://snowtrace.io",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "bsc",
+        label: "BNB Chain",
+        explorer: "https://bscscan.com",
+        kind: ChainKind::Evm,
+    },
+    Chain {
+        name: "solana",
+        label: "Solana",
+        explorer: "https://solscan.io",
+        kind: ChainKind::Solana,
+    },
+    Chain {
+        name: "bitcoin",
+        label: "Bitcoin",
+        explorer: "https://mempool.space",
+        kind: ChainKind::Bitcoin,
+    },
+];
+
+fn chain(args: &ShortcodeArgs) -> Result<&'static Chain, String> {
+    let name = args.get("chain").unwrap_or("ethereum");
+    CHAINS
+        .iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| {
+            let names: Vec<_> = CHAINS.iter().map(|c| c.name).collect();
+            format!(
+                "unknown chain `{}` (expected one of {})",
+                name,
+                names.join(", ")
+            )
+        })
+}
+
+fn is_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_base58(s: &str, min: usize, max: usize) -> bool {
+    (min..=max).contains(&s.len())
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() && !b"0OIl".contains(&b))
+}
+
+/// `0x1234…cdef` for long hashes and addresses.
+fn shorten(s: &str) -> String {
+    if s.len() > 14 {
+        format!("{}…{}", &s[..6], &s[s.len() - 4..])
+    } else {
+        s.to_string()
+    }
+}
+
+/// `{{< tx 0x… chain=base label="Mint" >}}`: a card linking to a block explorer.
+fn tx(args: &ShortcodeArgs) -> Result<String, String> {
+    let hash = args.require("hash")?;
+    let chain = chain(args)?;
+    let valid = match chain.kind {
+        ChainKind::Evm => hash.strip_prefix("0x").is_some_and(|h| is_hex(h, 64)),
+        ChainKind::Solana => is_base58(hash, 64, 88),
+        ChainKind::Bitcoin => is_hex(hash, 64),
+    };
+    if !valid {
+        return Err(format!(
+            "`{}` isn't a valid {} transaction hash",
+            hash, chain.label
+        ));
+    }
+
+    let label = args
+        .get("label")
+        .map(|l| format!(r#"<span class="embed-tx-label">{}</span>"#, text(l)))
+        .unwrap_or_default();
+    Ok(format!(
+        r#"<a class="embed-tx" href="{}/tx/{}" target="_blank" rel="noopener noreferrer" title="{}"><span class="embed-tx-chain">{} tx</span>{}<code>{}</code></a>"#,
+        chain.explorer,
+        attr(hash),
+        attr(hash),
+        chain.label,
+        label,
+        text(&shorten(hash)),
+    ))
+}
+
+/// `{{< address vitalik.eth >}}` or `{{< address 0x… label="Treasury" >}}`.
+fn address(args: &ShortcodeArgs) -> Result<String, String> {
+    let address = args.require("address")?;
+    let chain = chain(args)?;
+    let is_ens = address.len() > 4
+        && address.ends_with(".eth")
+        && address
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.');
+
+    let (href, shown) = if is_ens {
+        if chain.kind != ChainKind::Evm {
+            return Err(format!("ENS names don't resolve on {}", chain.label));
+        }
+        (
+            format!("https://app.ens.domains/{}", address),
+            address.to_string(),
+        )
+    } else {
+        let valid = match chain.kind {
+            ChainKind::Evm => address.strip_prefix("0x").is_some_and(|a| is_hex(a, 40)),
+            ChainKind::Solana => is_base58(address, 32, 44),
+            ChainKind::Bitcoin => {
+                (26..=62).contains(&address.len())
+                    && address.bytes().all(|b| b.is_ascii_alphanumeric())
+            }
+        };
+        if !valid {
+            return Err(format!(
+                "`{}` isn't a valid {} address",
+                address, chain.label
+            ));
+        }
+        let path = if chain.kind == ChainKind::Solana {
+            "account"
+        } else {
+            "address"
+        };
+        (
+            format!("{}/{}/{}", chain.explorer, path, address),
+            shorten(address),
+        )
+    };
+
+    let label = args
+        .get("label")
+        .map(|l| format!(r#"<span class="embed-address-label">{}</span>"#, text(l)))
+        .unwrap_or_default();
+    Ok(format!(
+        r#"<a class="embed-address" href="{}" target="_blank" rel="noopener noreferrer" title="{}">{}<code>{}</code></a>"#,
+        attr(&href),
+        attr(address),
+        label,
+        text(&shown),
+    ))
+}
+
+/// Ticker symbols and their CoinGecko ids.
+const COINS: &[(&str, &str)] = &[
+    ("btc", "bitcoin"),
+    ("eth", "ethereum"),
+    ("sol", "solana"),
+    ("avax", "avalanche-2"),
+    ("usdt", "tether"),
+    ("usdc", "usd-coin"),
+    ("dai", "dai"),
+    ("bnb", "binancecoin"),
+    ("pol", "polygon-ecosystem-token"),
+    ("arb", "arbitrum"),
+    ("op", "optimism"),
+    ("link", "chainlink"),
+];
+
+/// `{{< price eth >}}` or `{{< price id=uniswap symbol=UNI vs=eur >}}`: a live
+/// price, filled in by the post page from CoinGecko.
+fn price(args: &ShortcodeArgs) -> Result<String, String> {
+    let symbol = args.get("symbol");
+    let id = match (args.get("id"), symbol) {
+        (Some(id), _) => id,
+        (None, Some(symbol)) => COINS
+            .iter()
+            .find(|(s, _)| s.eq_ignore_ascii_case(symbol))
+            .map(|(_, id)| *id)
+            .ok_or_else(|| format!("unknown symbol `{}`, give its CoinGecko `id`", symbol))?,
+        (None, None) => return Err("missing `symbol` or `id`".to_string()),
+    };
+    if id.is_empty()
+        || !id
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    {
+        return Err(format!("`{}` isn't a CoinGecko id", id));
+    }
+    let vs = args.get("vs").unwrap_or("usd").to_ascii_lowercase();
+    if !(3..=5).contains(&vs.len()) || !vs.bytes().all(|b| b.is_ascii_lowercase()) {
+        return Err(format!("`{}` isn't a currency code", vs));
+    }
+
+    Ok(format!(
+        r#"<span class="embed-price" data-coin="{}" data-vs="{}"><span class="embed-price-symbol">{}</span><span class="embed-price-value">…</span></span>"#,
+        id,
+        vs,
+        text(&symbol.unwrap_or(id).to_ascii_uppercase()),
+    ))
+}
+
+/// Currencies the post page's tip box accepts.
+const TIP_CURRENCIES: &[&str] = &[
+    "eth",
+    "avax",
+    "sol",
+    "btc",
+    "usdt-eth",
+    "usdt-avax",
+    "usdt-sol",
+];
+
+/// `{{< tip 5 currency=sol >}}`: a button that sends a tip of that many USD.
+fn tip(args: &ShortcodeArgs) -> Result<String, String> {
+    let amount = args.require("amount")?.trim_start_matches('$');
+    if !amount
+        .parse::<f64>()
+        .is_ok_and(|a| a > 0.0 && a <= 10_000.0)
+    {
+        return Err(format!("`{}` isn't an amount between 0 and 10000", amount));
+    }
+    let currency = args.get("currency").unwrap_or("eth").to_ascii_lowercase();
+    if !TIP_CURRENCIES.contains(&currency.as_str()) {
+        return Err(format!(
+            "unknown currency `{}` (expected one of {})",
+            currency,
+            TIP_CURRENCIES.join(", ")
+        ));
+    }
+    let label = match args.get("label") {
+        Some(label) => label.to_string(),
+        None => format!("Tip ${}", amount),
+    };
+
+    Ok(format!(
+        r#"<button type="button" class="embed-tip btn btn-primary" data-tip-amount="{}" data-tip-currency="{}">{}</button>"#,
+        attr(amount),
+        currency,
+        text(&label),
+    ))
+}
+
+/// `{{< youtube dQw4w9WgXcQ start=42 >}}`: a privacy-enhanced YouTube player.
+fn youtube(args: &ShortcodeArgs) -> Result<String, String> {
+    let id = args.require("id")?;
+    if id.len() != 11
+        || !id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        return Err(format!("`{}` isn't a YouTube video id", id));
+    }
+    let start = match args.get("start") {
+        Some(start) => {
+            let seconds: u32 = start
+                .parse()
+                .map_err(|_| format!("`start` should be seconds, got `{}`", start))?;
+            format!("?start={}", seconds)
+        }
+        None => String::new(),
+    };
+    let title = args.get("title").unwrap_or("YouTube video");
+
+    Ok(format!(
+        r#"<span class="embed-video"><iframe src="https://www.youtube-nocookie.com/embed/{}{}" title="{}" loading="lazy" sandbox="{}" allow="encrypted-media; picture-in-picture; fullscreen" allowfullscreen referrerpolicy="strict-origin-when-cross-origin"></iframe></span>"#,
+        id,
+        start,
+        attr(title),
+        IFRAME_SANDBOX,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(pairs: &[(&str, &str)]) -> ShortcodeArgs {
+        ShortcodeArgs(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    fn render(source: &str) -> Result<String, String> {
+        Shortcodes::default().render(source)
+    }
+
+    #[test]
+    fn tokenize_splits_keys_values_and_quotes() {
+        let token = |key: Option<&str>, value: &str| (key.map(String::from), value.to_string());
+
+        assert_eq!(
+            tokenize(r#"  tx 0xab chain=base label="Mint a \"thing\""  "#).unwrap(),
+            [
+                token(None, "tx"),
+                token(None, "0xab"),
+                token(Some("chain"), "base"),
+                token(Some("label"), r#"Mint a "thing""#),
+            ]
+        );
+        assert_eq!(tokenize("a=b=c").unwrap(), [token(Some("a"), "b=c")]);
+        assert_eq!(tokenize("=x").unwrap(), [token(None, "=x")]);
+        assert_eq!(tokenize(r#"k="""#).unwrap(), [token(Some("k"), "")]);
+        assert!(tokenize("").unwrap().is_empty());
+        assert_eq!(
+            tokenize(r#"x "unclosed"#).unwrap_err(),
+            "unclosed `\"` in shortcode"
+        );
+    }
+
+    #[test]
+    fn args_fill_params_by_position_or_name() {
+        let args = args(&[("amount", "5")]);
+        assert_eq!(args.get("amount"), Some("5"));
+        assert_eq!(args.get("currency"), None);
+        assert_eq!(args.require("amount"), Ok("5"));
+        assert_eq!(args.require("currency").unwrap_err(), "missing `currency`");
+
+        assert_eq!(render("tip 5 sol"), render("tip currency=sol amount=5"));
+        assert_eq!(render("").unwrap_err(), "shortcode has no name");
+        assert_eq!(render("nope").unwrap_err(), "unknown shortcode `nope`");
+        assert_eq!(render("tip").unwrap_err(), "tip: missing `amount`");
+        assert_eq!(
+            render("tip 5 6 7 8").unwrap_err(),
+            "`tip` takes at most 3 positional arguments"
+        );
+        assert_eq!(
+            render("tip 5 amount=6").unwrap_err(),
+            "`amount` is given more than once"
+        );
+        assert_eq!(
+            render("tip 5 bogus=1").unwrap_err(),
+            "`tip` has no argument `bogus`"
+        );
+    }
+
+    #[test]
+    fn tx_and_address_check_the_chain_format() {
+        let evm_hash = format!("0x{}", "ab".repeat(32));
+        let evm_address = format!("0x{}", "0".repeat(40));
+
+        assert!(render(&format!("tx {}", evm_hash))
+            .unwrap()
+            .contains(r#"href="https://etherscan.io/tx/0xabab"#));
+        assert!(render(&format!("tx {} chain=Base", evm_hash))
+            .unwrap()
+            .contains("https://basescan.org/tx/"));
+        assert!(render(&format!("tx {} chain=bitcoin", evm_hash)).is_err());
+        assert_eq!(
+            render("tx 0x12").unwrap_err(),
+            "tx: `0x12` isn't a valid Ethereum transaction hash"
+        );
+        assert!(render(&format!("tx {} chain=nowhere", evm_hash))
+            .unwrap_err()
+            .starts_with("tx: unknown chain `nowhere`"));
+
+        assert!(render(&format!("address {}", evm_address))
+            .unwrap()
+            .contains("<code>0x0000…0000</code>"));
+        assert!(render("address vitalik.eth")
+            .unwrap()
+            .contains(r#"href="https://app.ens.domains/vitalik.eth""#));
+        assert_eq!(
+            render("address vitalik.eth chain=solana").unwrap_err(),
+            "address: ENS names don't resolve on Solana"
+        );
+        assert!(render("address 0OIl0OIl0OIl0OIl0OIl0OIl0OIl0OIl chain=solana").is_err());
+        assert!(
+            render("address 7EcDhSYGxXyscszYEp35KHN8vvw3svAuLKTzXwCFLtV chain=solana")
+                .unwrap()
+                .contains("https://solscan.io/account/")
+        );
+    }
+
+    #[test]
+    fn price_tip_and_youtube_validate_their_arguments() {
+        assert!(render("price eth vs=EUR")
+            .unwrap()
+            .contains(r#"data-coin="ethereum" data-vs="eur""#));
+        assert!(render("price id=uniswap symbol=UNI").is_ok());
+        assert_eq!(
+            render("price doge").unwrap_err(),
+            "price: unknown symbol `doge`, give its CoinGecko `id`"
+        );
+        assert!(render(r#"price id="Bad Id""#).is_err());
+        assert!(render("price eth vs=dollars").is_err());
+
+        assert!(render("tip $2.50")
+            .unwrap()
+            .contains(r#"data-tip-amount="2.50" data-tip-currency="eth">Tip $2.50<"#));
+        assert!(render("tip 0").is_err());
+        assert!(render("tip 10001").is_err());
+        assert!(render("tip 5 currency=doge").is_err());
+
+        assert!(render("youtube dQw4w9WgXcQ start=42").is_ok());
+        assert!(render("youtube short").is_err());
+        assert_eq!(
+            render("youtube dQw4w9WgXcQ start=x").unwrap_err(),
+            "youtube: `start` should be seconds, got `x`"
+        );
+    }
+
+    #[test]
+    fn errors_give_the_line_of_the_shortcode() {
+        let markdown = "Intro\n\n{{< tip 0 >}}\n```\n{{< nope >}}\n```\n    {{< nope >}}\n\n`{{< nope >}}` \\{{< nope >}}\n{{< nope >}}\n{{< tip";
+        let errors = Shortcodes::default()
+            .replace(markdown, &mut TrustedHtml::default())
+            .unwrap_err();
+
+        assert_eq!(
+            errors.to_string(),
+            "line 3: tip: `0` isn't an amount between 0 and 10000; \
+             line 10: unknown shortcode `nope`; \
+             line 11: `{{<` is never closed with `>}}`"
+        );
+        assert_eq!(
+            errors
+                .offset_lines(4)
+                .0
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            [7, 14, 15]
+        );
+    }
+}
@@ -1,3 +1,6 @@
+// Typed mirrors of the activity payloads the frontend sends; not all of
+// them are consumed server-side yet.
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
@@ -56,30 +59,6 @@ impl std::str::FromStr for ActivityType {
     }
 }
 
-/// Target types for activities
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-#[serde(rename_all = "snake_case")]
-pub enum TargetType {
-    Post,
-    Tip,
-    Network,
-    User,
-    Link,
-}
-
-impl std::fmt::Display for TargetType {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let s = match self {
-            TargetType::Post => "post",
-            TargetType::Tip => "tip",
-            TargetType::Network => "network",
-            TargetType::User => "user",
-            TargetType::Link => "link",
-        };
-        write!(f, "{}", s)
-    }
-}
-
 /// User activity record stored in database
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct UserActivity {
@@ -97,45 +76,12 @@ pub struct UserActivity {
 }
 
 /// Request to create a new activity
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct CreateActivityRequest {
     pub wallet_address: String,
     pub activity_type: String,
     pub target_type: Option<String>,
     pub target_id: Option<String>,
-    pub metadata: Option<serde_json::Value>,
+    pub metadata: Option<String>,
     pub chain: Option<String>,
 }
-
-/// Activity metadata for specific activity types
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PostReadMetadata {
-    pub duration_seconds: u64,
-    pub scroll_depth_percent: Option<u8>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct NetworkSwitchMetadata {
-    pub from_chain: String,
-    pub to_chain: String,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TipMetadata {
-    pub amount: String,
-    pub currency: String,
-    pub tx_hash: String,
-    pub post_slug: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct LinkClickMetadata {
-    pub url: String,
-    pub link_text: Option<String>,
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ShareMetadata {
-    pub platform: String, // twitter, facebook, copy_link, etc.
-    pub content_type: String, // post, tip, etc.
-}
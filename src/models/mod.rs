@@ -1,9 +1,15 @@
 pub mod activity;
 pub mod post;
+pub mod revision;
+pub mod search;
+pub mod tag;
 pub mod tip;
 pub mod user;
 
-pub use activity::{ActivityType, CreateActivityRequest, TargetType, UserActivity};
-pub use post::Post;
+pub use activity::{ActivityType, CreateActivityRequest, UserActivity};
+pub use post::{Post, PostCursor};
+pub use revision::PostRevision;
+pub use search::SearchQuery;
+pub use tag::{normalize_tag, parse_tags, TagCount};
 pub use tip::Tip;
-pub use user::User;
+pub use user::{Session, User, UserType};
@@ -1,6 +1,12 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sha2::{Digest, Sha256};
+use sqlx::{types::Json, FromRow};
+use std::collections::BTreeMap;
+use yaml_rust2::{yaml, Yaml, YamlEmitter};
+
+use super::parse_tags;
+use crate::markdown::TocEntry;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Post {
@@ -10,27 +16,296 @@ pub struct Post {
     pub description: String,
     pub content: String,
     pub html_content: String,
+    /// Headings in `html_content`
+    pub toc: Json<Vec<TocEntry>>,
     pub author: String,
     pub tags: String,
     pub published: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub views: i64,
+    pub author_wallet: Option<String>,
+    pub publish_at: Option<DateTime<Utc>>,
+}
+
+/// Position in the newest-first post list, handed out as an opaque string by
+/// the `/api/posts` endpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl PostCursor {
+    pub fn after(post: &Post) -> Self {
+        PostCursor {
+            created_at: post.created_at,
+            id: post.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        hex::encode(format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            self.id
+        ))
+    }
+
+    pub fn decode(value: &str) -> Option<Self> {
+        let decoded = String::from_utf8(hex::decode(value).ok()?).ok()?;
+        let (created_at, id) = decoded.split_once('|')?;
+        Some(PostCursor {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .ok()?
+                .with_timezone(&Utc),
+            id: id.parse().ok()?,
+        })
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, PartialEq, Deserialize)]
 pub struct PostFrontmatter {
     pub title: String,
     pub description: Option<String>,
     pub author: Option<String>,
     pub tags: Option<Vec<String>>,
     pub published: Option<bool>,
+    /// Original publication date; the post's `created_at`
     pub date: Option<String>,
+    /// Last significant edit; defaults to `date`
+    #[serde(alias = "updated_at", alias = "last_modified")]
+    pub updated: Option<String>,
+    pub publish_at: Option<String>,
+    /// Keys the blog doesn't use, kept so they survive saving from the editor
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+impl PostFrontmatter {
+    /// Split a post file into its frontmatter and markdown body. Also returns
+    /// the (zero-based) line of the file the body starts on, so errors in the
+    /// body can point at the right line of the file.
+    pub fn parse(content: &str) -> anyhow::Result<(Self, String, usize)> {
+        let matter = gray_matter::Matter::<gray_matter::engine::YAML>::new();
+        let result = matter.parse(content);
+
+        let frontmatter: Self = result
+            .data
+            .ok_or_else(|| anyhow::anyhow!("No frontmatter found"))?
+            .deserialize()?;
+
+        // The body follows the closing `---`, minus the blank lines the
+        // parser trims from its start
+        let mut lines = content.lines().enumerate().skip(1);
+        let close = lines
+            .find(|(_, line)| line.trim_end() == "---")
+            .map_or(0, |(n, _)| n);
+        let body_line = lines
+            .find(|(_, line)| !line.is_empty())
+            .map_or(close + 1, |(n, _)| n);
+
+        Ok((frontmatter, result.content, body_line))
+    }
+
+    /// Write a post file that [`PostFrontmatter::parse`] reads back unchanged.
+    /// Values go through a YAML emitter, so quotes, colons and newlines in
+    /// titles are escaped.
+    pub fn render(&self, content: &str) -> anyhow::Result<String> {
+        let mut map = yaml::Hash::new();
+        let mut insert = |key: &str, value: Yaml| {
+            map.insert(Yaml::String(key.to_string()), value);
+        };
+        let string = |value: &str| Yaml::String(value.to_string());
+
+        insert("title", string(&self.title));
+        if let Some(description) = &self.description {
+            insert("description", string(description));
+        }
+        if let Some(author) = &self.author {
+            insert("author", string(author));
+        }
+        if let Some(tags) = &self.tags {
+            insert(
+                "tags",
+                Yaml::Array(tags.iter().map(|t| string(t)).collect()),
+            );
+        }
+        if let Some(date) = &self.date {
+            insert("date", string(date));
+        }
+        if let Some(updated) = &self.updated {
+            insert("updated", string(updated));
+        }
+        if let Some(published) = self.published {
+            insert("published", Yaml::Boolean(published));
+        }
+        if let Some(publish_at) = &self.publish_at {
+            insert("publish_at", string(publish_at));
+        }
+        for (key, value) in &self.extra {
+            insert(key, json_to_yaml(value));
+        }
+
+        let mut out = String::new();
+        YamlEmitter::new(&mut out)
+            .dump(&Yaml::Hash(map))
+            .map_err(|e| anyhow::anyhow!("Failed to write frontmatter: {:?}", e))?;
+
+        Ok(format!("{}\n---\n\n{}", out, content))
+    }
+}
+
+fn json_to_yaml(value: &serde_json::Value) -> Yaml {
+    use serde_json::Value;
+
+    match value {
+        Value::Null => Yaml::Null,
+        Value::Bool(b) => Yaml::Boolean(*b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        Value::String(s) => Yaml::String(s.clone()),
+        Value::Array(items) => Yaml::Array(items.iter().map(json_to_yaml).collect()),
+        Value::Object(fields) => Yaml::Hash(
+            fields
+                .iter()
+                .map(|(k, v)| (Yaml::String(k.clone()), json_to_yaml(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parse a frontmatter date or timestamp. Accepts RFC 3339, RFC 2822,
+/// `YYYY-MM-DD[ HH:MM[:SS]]` (with `T` or a space, optionally followed by an
+/// offset), `YYYY/MM/DD` and `January 15, 2024` / `Jan 15, 2024`. Times
+/// without an offset are taken as UTC.
+pub fn parse_frontmatter_date(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc2822(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S %z",
+        "%Y-%m-%d %H:%M %z",
+        "%Y-%m-%dT%H:%M%:z",
+    ] {
+        if let Ok(dt) = DateTime::parse_from_str(value, format) {
+            return Some(dt.with_timezone(&Utc));
+        }
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(dt) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(dt.and_utc());
+        }
+    }
+    [
+        "%Y-%m-%d",
+        "%Y/%m/%d",
+        "%B %d, %Y",
+        "%b %d, %Y",
+        "%d %B %Y",
+        "%d %b %Y",
+    ]
+    .iter()
+    .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+    .and_then(|d| d.and_hms_opt(0, 0, 0))
+    .map(|dt| dt.and_utc())
 }
 
 impl Post {
     pub fn tags_list(&self) -> Vec<&str> {
-        self.tags.split(',').filter(|s| !s.is_empty()).collect()
+        self.tags
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// Whether `wallet_address` owns this post (EVM addresses compare case-insensitively).
+    pub fn is_owned_by(&self, wallet_address: &str) -> bool {
+        self.author_wallet
+            .as_deref()
+            .map(|w| {
+                if w.starts_with("0x") {
+                    w.eq_ignore_ascii_case(wallet_address)
+                } else {
+                    w == wallet_address
+                }
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether readers can see the post: published and past its publish time.
+    pub fn is_live(&self) -> bool {
+        self.published && self.publish_at.is_none_or(|at| at <= Utc::now())
+    }
+
+    /// "draft", "scheduled" or "published", for the admin UI.
+    pub fn status(&self) -> &'static str {
+        if !self.published {
+            "draft"
+        } else if self.is_live() {
+            "published"
+        } else {
+            "scheduled"
+        }
+    }
+
+    /// Whether the post was edited on a later day than it was published.
+    pub fn was_updated(&self) -> bool {
+        self.updated_at.date_naive() > self.created_at.date_naive()
+    }
+
+    /// When readers last saw the post change: its last edit, or when a
+    /// scheduled post went live if that came later.
+    pub fn modified_at(&self) -> DateTime<Utc> {
+        let modified = self.updated_at.max(self.created_at);
+        match self.publish_at {
+            Some(at) if at <= Utc::now() => modified.max(at),
+            _ => modified,
+        }
+    }
+
+    /// Whether the reader-visible fields differ from `other`, used to decide
+    /// if a reload from disk should bump `updated_at`.
+    pub fn content_differs(&self, other: &Post) -> bool {
+        self.title != other.title
+            || self.description != other.description
+            || self.content.trim() != other.content.trim()
+            || self.author != other.author
+            || parse_tags(&self.tags) != parse_tags(&other.tags)
+    }
+
+    /// Hash of everything a post file carries apart from its dates, used to
+    /// tell whether the post changed in the database since the last sync.
+    pub fn content_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            self.title.as_str(),
+            self.description.as_str(),
+            self.author.as_str(),
+            &parse_tags(&self.tags).join(","),
+            self.content.trim(),
+            if self.published { "published" } else { "draft" },
+            &self
+                .publish_at
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_default(),
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
     }
 
     pub fn reading_time(&self) -> u32 {
@@ -38,3 +313,122 @@ impl Post {
         ((words as f64 / 200.0).ceil() as u32).max(1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Parse `file`, render it back and parse that again; both parses must
+    /// agree and rendering again must give the same file.
+    fn round_trip(file: &str) -> PostFrontmatter {
+        let (frontmatter, body, _) = PostFrontmatter::parse(file).unwrap();
+        let rendered = frontmatter.render(&body).unwrap();
+        let (reparsed, reparsed_body, _) = PostFrontmatter::parse(&rendered).unwrap();
+
+        assert_eq!(reparsed, frontmatter);
+        assert_eq!(reparsed_body.trim(), body.trim());
+        assert_eq!(reparsed.render(&reparsed_body).unwrap(), rendered);
+        frontmatter
+    }
+
+    #[test]
+    fn every_field_survives_a_round_trip() {
+        let frontmatter = round_trip(
+            r#"---
+title: "Colons: quotes \" and # hashes"
+description: |
+  Two
+  lines
+author: alice.eth
+tags: [rust, "web 3"]
+published: false
+date: 2024-01-15
+updated: "2024-02-01T10:30:00+02:00"
+publish_at: 2030-05-06 07:08
+series: intro
+part: 2
+weight: 1.5
+draft_of: null
+cover:
+  src: /img/cover.png
+  size: [1200, 630]
+---
+
+Body with `code` and a [link](https://example.com).
+"#,
+        );
+
+        assert_eq!(frontmatter.title, r#"Colons: quotes " and # hashes"#);
+        assert_eq!(frontmatter.description.as_deref(), Some("Two\nlines\n"));
+        assert_eq!(frontmatter.author.as_deref(), Some("alice.eth"));
+        assert_eq!(
+            frontmatter.tags,
+            Some(vec!["rust".to_string(), "web 3".to_string()])
+        );
+        assert_eq!(frontmatter.published, Some(false));
+        assert_eq!(frontmatter.date.as_deref(), Some("2024-01-15"));
+        assert_eq!(
+            frontmatter.updated.as_deref(),
+            Some("2024-02-01T10:30:00+02:00")
+        );
+        assert_eq!(frontmatter.publish_at.as_deref(), Some("2030-05-06 07:08"));
+        assert_eq!(frontmatter.extra["series"], json!("intro"));
+        assert_eq!(frontmatter.extra["part"], json!(2));
+        assert_eq!(frontmatter.extra["weight"], json!(1.5));
+        assert_eq!(frontmatter.extra["draft_of"], json!(null));
+        assert_eq!(
+            frontmatter.extra["cover"],
+            json!({ "src": "/img/cover.png", "size": [1200, 630] })
+        );
+    }
+
+    #[test]
+    fn dates_read_back_as_the_same_instant() {
+        let frontmatter = round_trip(
+            "---\ntitle: Dates\ndate: 2024-01-15\nupdated_at: 2024-03-01 12:00:00\n---\n\nBody\n",
+        );
+
+        // `updated_at` is read as `updated` and written back under that name
+        assert_eq!(
+            parse_frontmatter_date(frontmatter.updated.as_deref().unwrap()),
+            parse_frontmatter_date("2024-03-01T12:00:00Z")
+        );
+        assert_eq!(
+            parse_frontmatter_date(frontmatter.date.as_deref().unwrap()),
+            parse_frontmatter_date("2024-01-15T00:00:00Z")
+        );
+    }
+
+    #[test]
+    fn missing_fields_stay_missing() {
+        let frontmatter = round_trip("---\ntitle: Just a title\n---\n\nBody\n");
+
+        assert_eq!(
+            frontmatter,
+            PostFrontmatter {
+                title: "Just a title".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn body_line_points_at_the_first_body_line() {
+        for (file, line) in [
+            ("---\ntitle: A\n---\nBody\n", 3),
+            ("---\ntitle: A\n---\n\n\nBody\n", 5),
+            ("---\r\ntitle: A\r\ntags: [x]\r\n---  \r\n\r\nBody\r\n", 5),
+            ("---\ntitle: A\n---\n", 3),
+        ] {
+            let (_, body, body_line) = PostFrontmatter::parse(file).unwrap();
+            assert_eq!(body_line, line, "{:?}", file);
+            assert_eq!(
+                file.lines().nth(body_line),
+                body.lines().next(),
+                "{:?}",
+                file
+            );
+        }
+    }
+}
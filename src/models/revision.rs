@@ -0,0 +1,19 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// A saved version of a post. `editor_wallet` is `None` for versions picked
+/// up from the markdown files on disk.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct PostRevision {
+    pub id: i64,
+    pub post_id: i64,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    pub tags: String,
+    pub content: String,
+    pub editor_wallet: Option<String>,
+    pub note: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
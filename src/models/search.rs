@@ -0,0 +1,193 @@
+use super::normalize_tag;
+
+/// A reader's search box input, split into full-text terms and filters.
+///
+/// `rust "smart contracts" tag:solana author:alice` searches for posts
+/// matching `rust` and the phrase `smart contracts`, tagged `solana` and
+/// written by `alice`. Filter values can be quoted: `author:"Jane Doe"`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<SearchTerm>,
+    pub tags: Vec<String>,
+    pub author: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchTerm {
+    Word(String),
+    Phrase(String),
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+        let mut chars = input.chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            if c == '"' {
+                chars.next();
+                let phrase = take_until_quote(&mut chars);
+                if has_word_chars(&phrase) {
+                    query.terms.push(SearchTerm::Phrase(phrase));
+                }
+                continue;
+            }
+
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+                // `key:"quoted value"`
+                if c == ':' && chars.peek() == Some(&'"') {
+                    chars.next();
+                    token.push_str(&take_until_quote(&mut chars));
+                    break;
+                }
+            }
+
+            match token.split_once(':') {
+                Some((key, value)) if key.eq_ignore_ascii_case("tag") && !value.is_empty() => {
+                    query.tags.push(normalize_tag(value));
+                }
+                Some((key, value)) if key.eq_ignore_ascii_case("author") && !value.is_empty() => {
+                    query.author = Some(value.trim().to_string());
+                }
+                _ if has_word_chars(&token) => query.terms.push(SearchTerm::Word(token)),
+                _ => {}
+            }
+        }
+
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.tags.is_empty() && self.author.is_none()
+    }
+
+    /// The terms as an FTS5 MATCH expression. Every term is quoted so user
+    /// input can't inject FTS5 operators; the last bare word also matches as
+    /// a prefix.
+    pub fn fts_query(&self) -> Option<String> {
+        if self.terms.is_empty() {
+            return None;
+        }
+
+        let last = self.terms.len() - 1;
+        let parts: Vec<String> = self
+            .terms
+            .iter()
+            .enumerate()
+            .map(|(i, term)| match term {
+                SearchTerm::Word(w) if i == last => format!("{}*", quote(w)),
+                SearchTerm::Word(w) | SearchTerm::Phrase(w) => quote(w),
+            })
+            .collect();
+
+        Some(parts.join(" "))
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+fn has_word_chars(value: &str) -> bool {
+    value.chars().any(char::is_alphanumeric)
+}
+
+fn take_until_quote(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut value = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            break;
+        }
+        value.push(c);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(query: &SearchQuery) -> Vec<&str> {
+        query
+            .terms
+            .iter()
+            .map(|term| match term {
+                SearchTerm::Word(w) | SearchTerm::Phrase(w) => w.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filters_are_split_from_terms() {
+        let query = SearchQuery::parse(r#"rust "smart contracts" TAG:Solana author:alice"#);
+        assert_eq!(
+            query.terms,
+            vec![
+                SearchTerm::Word("rust".to_string()),
+                SearchTerm::Phrase("smart contracts".to_string()),
+            ]
+        );
+        assert_eq!(query.tags, vec!["solana"]);
+        assert_eq!(query.author.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn filter_values_can_be_quoted() {
+        let query = SearchQuery::parse(r#"tag:"Web  Three" author:"Jane Doe" zk"#);
+        assert_eq!(query.tags, vec!["web three"]);
+        assert_eq!(query.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(words(&query), vec!["zk"]);
+    }
+
+    #[test]
+    fn empty_filters_and_punctuation_are_not_terms() {
+        let query = SearchQuery::parse(r#"tag: author: - * "" ()"#);
+        assert!(query.tags.is_empty());
+        assert_eq!(query.author, None);
+        // `tag:` and `author:` with no value are just words
+        assert_eq!(words(&query), vec!["tag:", "author:"]);
+        assert!(SearchQuery::parse("  - * \"\" ").is_empty());
+    }
+
+    #[test]
+    fn fts_query_quotes_every_term() {
+        let query = SearchQuery::parse(r#""smart contracts" rust"#);
+        assert_eq!(
+            query.fts_query().as_deref(),
+            Some(r#""smart contracts" "rust"*"#)
+        );
+        assert_eq!(SearchQuery::parse("tag:rust").fts_query(), None);
+    }
+
+    #[test]
+    fn fts_operators_in_input_stay_literal() {
+        let cases = [
+            ("a OR b", r#""a" "OR" "b"*"#),
+            ("NEAR(a b)", r#""NEAR(a" "b)"*"#),
+            ("-secret", r#""-secret"*"#),
+            ("title:rust", r#""title:rust"*"#),
+            ("pre* ^start", r#""pre*" "^start"*"#),
+            ("say\"hi AND", r#""say""hi" "AND"*"#),
+            ("it's a\"\"b", r#""it's" "a""""b"*"#),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                SearchQuery::parse(input).fts_query().as_deref(),
+                Some(expected),
+                "{:?}",
+                input
+            );
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// A tag and how many posts carry it.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TagCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// The stored form of a tag: trimmed, lowercase, single spaces.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Split a comma-joined tag list into normalized tags, dropping blanks and
+/// duplicates.
+pub fn parse_tags(tags: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags.split(',').map(normalize_tag) {
+        if !tag.is_empty() && !parsed.contains(&tag) {
+            parsed.push(tag);
+        }
+    }
+    parsed
+}
@@ -2,7 +2,9 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
 pub enum WalletType {
     Ethereum,
     Solana,
@@ -17,20 +19,64 @@ impl std::fmt::Display for WalletType {
     }
 }
 
+/// Role of a user, stored in `users.user_type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum UserType {
+    Admin,
+    Writer,
+    Reader,
+}
+
+impl std::fmt::Display for UserType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserType::Admin => write!(f, "admin"),
+            UserType::Writer => write!(f, "writer"),
+            UserType::Reader => write!(f, "reader"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: i64,
     pub wallet_address: String,
     pub wallet_type: String,
     pub nonce: String,
+    pub nonce_issued_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub last_login: Option<DateTime<Utc>>,
+    pub user_type: UserType,
+    pub account_id: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// An authenticated session, resolved from the session cookie by the
+/// `Session` extractor in `auth`.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Session {
+    #[serde(skip_serializing)]
+    pub token: String,
     pub user_id: i64,
+    pub account_id: i64,
     pub wallet_address: String,
     pub wallet_type: WalletType,
+    pub user_type: UserType,
     pub expires_at: DateTime<Utc>,
 }
+
+impl Session {
+    pub fn is_admin(&self) -> bool {
+        self.user_type == UserType::Admin
+    }
+
+    pub fn can_write(&self) -> bool {
+        matches!(self.user_type, UserType::Admin | UserType::Writer)
+    }
+
+    /// Admins may edit any post; writers only the posts they own.
+    pub fn can_edit(&self, post: &crate::models::Post) -> bool {
+        self.is_admin() || (self.can_write() && post.is_owned_by(&self.wallet_address))
+    }
+}
@@ -0,0 +1,216 @@
+//! Rendered pages kept in memory, so popular pages don't hit the database or
+//! templates on every request, and the validators (`ETag`, `Last-Modified`)
+//! that let clients revalidate them.
+
+use axum::{
+    body::Bytes,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Content type of the pages served from the cache.
+pub const HTML: &str = "text/html; charset=utf-8";
+
+/// A rendered response body and its validators.
+pub struct Page {
+    body: Bytes,
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+impl Page {
+    pub fn new(body: String, last_modified: Option<DateTime<Utc>>) -> Self {
+        let etag = format!(
+            "\"{}\"",
+            &hex::encode(Sha256::digest(body.as_bytes()))[..32]
+        );
+        Self {
+            body: body.into(),
+            etag,
+            last_modified,
+        }
+    }
+
+    /// `304 Not Modified` if the client's copy is current, otherwise the body
+    /// as `content_type`.
+    pub fn respond(&self, request: &HeaderMap, content_type: &'static str) -> Response {
+        let mut headers = HeaderMap::new();
+        if let Ok(value) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, value);
+        }
+        if let Some(modified) = self.last_modified {
+            if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified.into())) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+
+        if not_modified(request, &self.etag, self.last_modified) {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        (headers, self.body.clone()).into_response()
+    }
+}
+
+/// Whether the client's cached copy is still current. `If-None-Match` wins
+/// over `If-Modified-Since` when both are sent.
+fn not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim().trim_start_matches("W/"))
+            .any(|tag| tag == "*" || tag == etag);
+    }
+
+    let since = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok());
+    match (since, last_modified) {
+        // HTTP dates have whole-second precision
+        (Some(since), Some(modified)) => {
+            SystemTime::from(modified) < since + Duration::from_secs(1)
+        }
+        _ => false,
+    }
+}
+
+/// Rendered pages by path. Everything is dropped whenever a post changes, and
+/// entries expire after the TTL anyway so scheduled posts show up; a TTL of
+/// zero turns the cache off.
+pub struct PageCache {
+    ttl: Duration,
+    generation: AtomicU64,
+    pages: RwLock<HashMap<String, (Instant, Arc<Page>)>>,
+}
+
+impl PageCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            generation: AtomicU64::new(0),
+            pages: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Arc<Page>> {
+        let pages = self.pages.read().unwrap_or_else(|e| e.into_inner());
+        pages
+            .get(key)
+            .filter(|(cached_at, _)| cached_at.elapsed() < self.ttl)
+            .map(|(_, page)| page.clone())
+    }
+
+    /// Take this before loading what a page shows, and pass it to
+    /// [`insert`](Self::insert).
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Cache `page` unless posts changed since `generation` was taken, in
+    /// which case it may already be out of date.
+    pub fn insert(&self, key: &str, generation: u64, page: Page) -> Arc<Page> {
+        let page = Arc::new(page);
+        if !self.ttl.is_zero() {
+            let mut pages = self.pages.write().unwrap_or_else(|e| e.into_inner());
+            if self.generation() == generation {
+                pages.insert(key.to_string(), (Instant::now(), page.clone()));
+            }
+        }
+        page
+    }
+
+    /// Drop every page, after a post was created, changed or removed.
+    pub fn clear(&self) {
+        let mut pages = self.pages.write().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        pages.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn pages_without_a_date_revalidate_by_etag_only() {
+        let page = Page::new("<p>12 views</p>".to_string(), None);
+        let fresh = page.respond(&HeaderMap::new(), HTML);
+        assert_eq!(fresh.status(), StatusCode::OK);
+        assert!(fresh.headers().get(header::LAST_MODIFIED).is_none());
+
+        let etag = fresh.headers()[header::ETAG].to_str().unwrap();
+        let cached = page.respond(&request(header::IF_NONE_MATCH, etag), HTML);
+        assert_eq!(cached.status(), StatusCode::NOT_MODIFIED);
+
+        // Once the count moves, the old copy is stale however recent it is
+        let page = Page::new("<p>13 views</p>".to_string(), None);
+        assert_eq!(
+            page.respond(&request(header::IF_NONE_MATCH, etag), HTML)
+                .status(),
+            StatusCode::OK
+        );
+        let since = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+        assert_eq!(
+            page.respond(&request(header::IF_MODIFIED_SINCE, &since), HTML)
+                .status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn dated_pages_also_answer_if_modified_since() {
+        let modified = DateTime::parse_from_rfc3339("2024-01-15T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let page = Page::new("<feed/>".to_string(), Some(modified));
+        let at = |date: &str| request(header::IF_MODIFIED_SINCE, date);
+
+        assert_eq!(
+            page.respond(&at("Mon, 15 Jan 2024 10:00:00 GMT"), HTML)
+                .status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            page.respond(&at("Mon, 15 Jan 2024 09:59:59 GMT"), HTML)
+                .status(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn etag_wins_over_if_modified_since() {
+        let modified = DateTime::parse_from_rfc3339("2024-01-15T10:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let page = Page::new("<feed/>".to_string(), Some(modified));
+
+        // A copy with another ETag is stale even if its date looks current
+        let mut headers = request(header::IF_NONE_MATCH, "\"old\"");
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Mon, 15 Jan 2024 10:00:00 GMT"),
+        );
+        assert_eq!(page.respond(&headers, HTML).status(), StatusCode::OK);
+    }
+}
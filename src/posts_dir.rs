@@ -0,0 +1,401 @@
+//! Reading posts from the markdown files in `posts/`, and watching the
+//! directory so changes are synced as they happen.
+
+use anyhow::Result;
+use chrono::Utc;
+use notify_debouncer_mini::{
+    new_debouncer,
+    notify::{self, RecursiveMode},
+    DebounceEventResult,
+};
+use sqlx::{types::Json, SqlitePool};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    db,
+    markdown::{shortcodes::ShortcodeErrors, MarkdownParser},
+    models::{self, post::PostFrontmatter, Post},
+    sync, AppState,
+};
+
+pub const POSTS_DIR: &str = "posts";
+
+/// The slug a post file maps to: its name without the `.md` extension.
+pub fn slug_for(path: &Path) -> String {
+    path.file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("untitled")
+        .to_string()
+}
+
+/// Markdown files only, skipping editor lock and backup files like `.#post.md`.
+pub fn is_post_file(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "md")
+        && !path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .is_some_and(|n| n.starts_with('.'))
+}
+
+/// A post file that parsed, with the hash of its raw bytes.
+pub struct PostFile {
+    pub path: PathBuf,
+    pub post: Post,
+    pub hash: String,
+}
+
+pub enum FileState {
+    Missing,
+    /// Unreadable, or the frontmatter didn't parse
+    Invalid(String),
+    Parsed(Box<PostFile>),
+}
+
+/// Slugs become file names and URLs, so only `[a-z0-9-]` is allowed; that
+/// also rules out `/`, `\` and `..` escaping `posts/`.
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// The file a post is kept in. Every path into `posts/` is built here.
+pub fn post_path(slug: &str) -> Result<PathBuf> {
+    if !is_valid_slug(slug) {
+        anyhow::bail!("invalid slug {:?}: use only a-z, 0-9 and -", slug);
+    }
+    Ok(Path::new(POSTS_DIR).join(format!("{}.md", slug)))
+}
+
+/// Read a post file into the post it describes. Dates missing from the
+/// frontmatter are taken from the post already in the database, so reloading
+/// a file doesn't reorder posts or reset `created_at`.
+pub async fn read_post_file(
+    pool: &SqlitePool,
+    parser: &MarkdownParser,
+    path: &Path,
+) -> Result<FileState> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileState::Missing),
+        Err(e) => return Ok(FileState::Invalid(e.to_string())),
+    };
+    let hash = sync::hash_bytes(&bytes);
+    let Ok(content) = String::from_utf8(bytes) else {
+        return Ok(FileState::Invalid("file is not valid UTF-8".to_string()));
+    };
+
+    let (frontmatter, markdown_content, body_line) = match PostFrontmatter::parse(&content) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return Ok(FileState::Invalid(format!(
+                "failed to parse frontmatter: {}",
+                e
+            )))
+        }
+    };
+
+    let rendered = match parser.render(&markdown_content) {
+        Ok(rendered) => rendered,
+        Err(e) => match e.downcast::<ShortcodeErrors>() {
+            // Report lines of the file, not of the post body
+            Ok(errors) => {
+                return Ok(FileState::Invalid(
+                    errors.offset_lines(body_line).to_string(),
+                ))
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    let slug = slug_for(path);
+    let existing = db::get_any_post_by_slug(pool, &slug).await?;
+    let now = Utc::now();
+
+    let mut post = Post {
+        id: 0,
+        slug,
+        title: frontmatter.title,
+        description: frontmatter.description.unwrap_or_default(),
+        content: markdown_content,
+        html_content: rendered.html,
+        toc: Json(rendered.toc),
+        author: frontmatter
+            .author
+            .unwrap_or_else(|| "Anonymous".to_string()),
+        tags: frontmatter.tags.unwrap_or_default().join(", "),
+        published: frontmatter.published.unwrap_or(true),
+        created_at: now,
+        updated_at: now,
+        views: 0,
+        author_wallet: None,
+        publish_at: frontmatter
+            .publish_at
+            .as_deref()
+            .and_then(models::post::parse_frontmatter_date),
+    };
+
+    // Dates from the frontmatter win; otherwise keep what the
+    // database already has so restarts don't reorder posts
+    let date = frontmatter
+        .date
+        .as_deref()
+        .and_then(models::post::parse_frontmatter_date);
+    let updated = frontmatter
+        .updated
+        .as_deref()
+        .and_then(models::post::parse_frontmatter_date);
+    if frontmatter.date.is_some() && date.is_none() {
+        tracing::warn!("Unrecognized date {:?} in {:?}", frontmatter.date, path);
+    }
+    post.created_at = date
+        .or(existing.as_ref().map(|e| e.created_at))
+        .unwrap_or(now);
+    post.updated_at = match (updated, &existing) {
+        (Some(updated), _) => updated,
+        (None, Some(e)) if post.content_differs(e) => now,
+        (None, Some(e)) => e.updated_at,
+        (None, None) => post.created_at,
+    }
+    .max(post.created_at);
+
+    Ok(FileState::Parsed(Box::new(PostFile {
+        path: path.to_path_buf(),
+        post,
+        hash,
+    })))
+}
+
+/// Write a post to its file, returning the path. Custom frontmatter keys in
+/// the file being replaced are carried over.
+pub fn write_post_file(post: &Post) -> Result<PathBuf> {
+    let path = post_path(&post.slug)?;
+    std::fs::create_dir_all(POSTS_DIR)?;
+
+    let extra = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|existing| PostFrontmatter::parse(&existing).ok())
+        .map(|(frontmatter, _, _)| frontmatter.extra)
+        .unwrap_or_default();
+
+    let frontmatter = PostFrontmatter {
+        title: post.title.clone(),
+        description: Some(post.description.clone()),
+        author: Some(post.author.clone()),
+        tags: Some(post.tags_list().into_iter().map(String::from).collect()),
+        published: Some(post.published),
+        date: Some(post.created_at.to_rfc3339()),
+        updated: Some(post.updated_at.to_rfc3339()),
+        publish_at: post.publish_at.map(|at| at.to_rfc3339()),
+        extra,
+    };
+
+    std::fs::write(&path, frontmatter.render(&post.content)?)?;
+
+    Ok(path)
+}
+
+fn hash_file(path: &Path) -> Option<String> {
+    std::fs::read(path)
+        .ok()
+        .map(|bytes| sync::hash_bytes(&bytes))
+}
+
+/// Hashes of the post files currently in `dir`.
+fn hash_dir(dir: &Path) -> HashMap<PathBuf, String> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return HashMap::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_post_file(path))
+        .filter_map(|path| Some((path.clone(), hash_file(&path)?)))
+        .collect()
+}
+
+/// Add the post files in a batch of debounced events to `paths`.
+fn collect_paths(dir: &Path, events: DebounceEventResult, paths: &mut HashSet<PathBuf>) {
+    match events {
+        Ok(events) => paths.extend(
+            events
+                .into_iter()
+                .filter_map(|event| event.path.file_name().map(|name| dir.join(name)))
+                .filter(|path| is_post_file(path)),
+        ),
+        Err(e) => tracing::warn!("Error watching {}/: {}", POSTS_DIR, e),
+    }
+}
+
+/// Watch `posts/` and sync each file that changes (see [`sync::sync_post`]).
+/// A file renamed without changes renames the post, keeping its views, tips
+/// and history; the hashes of the files as last loaded tell a rename apart
+/// from a delete plus an unrelated new file.
+///
+/// Changes come from the OS (inotify on Linux) and are debounced: a file is
+/// only picked up once it has been quiet for `WATCH_DEBOUNCE_MS`, so
+/// half-written saves and editors that write in several steps settle first.
+pub async fn watch(state: Arc<AppState>) {
+    let (pool, parser) = (&state.db, &state.parser);
+    let mode = state.config.sync_mode;
+    let delay = Duration::from_millis(state.config.watch_debounce_ms);
+    let dir = Path::new(POSTS_DIR);
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let debouncer = std::fs::create_dir_all(dir)
+        .map_err(notify::Error::io)
+        .and_then(|()| {
+            new_debouncer(delay, move |events| {
+                let _ = tx.send(events);
+            })
+        })
+        .and_then(|mut debouncer| {
+            debouncer
+                .watcher()
+                .watch(dir, RecursiveMode::NonRecursive)?;
+            Ok(debouncer)
+        });
+    // Dropping the debouncer stops the watch, so keep it for the whole loop
+    let _debouncer = match debouncer {
+        Ok(debouncer) => debouncer,
+        Err(e) => {
+            tracing::error!("Cannot watch {}/ for changes: {}", POSTS_DIR, e);
+            return;
+        }
+    };
+
+    let mut loaded = hash_dir(dir);
+
+    tracing::info!("Watching {}/ for changes", POSTS_DIR);
+
+    while let Some(events) = rx.recv().await {
+        let mut paths = HashSet::new();
+        collect_paths(dir, events, &mut paths);
+
+        // The two halves of a rename can be debounced into separate batches,
+        // so give a file that went away a moment for its new name to show up
+        while paths.iter().any(|path| !path.exists()) {
+            match tokio::time::timeout(delay * 2, rx.recv()).await {
+                Ok(Some(events)) => collect_paths(dir, events, &mut paths),
+                _ => break,
+            }
+        }
+
+        let mut removed = Vec::new();
+        let mut written = Vec::new();
+        for path in paths {
+            match hash_file(&path) {
+                Some(hash) if loaded.get(&path) != Some(&hash) => written.push((path, hash)),
+                None if loaded.contains_key(&path) => removed.push(path),
+                // Touched without changes, or gone before it was ever loaded
+                _ => {}
+            }
+        }
+
+        let changed = !written.is_empty() || !removed.is_empty();
+
+        for (path, hash) in written {
+            // A new file with the same content as one that just went away
+            let renamed_from = (!loaded.contains_key(&path) && is_valid_slug(&slug_for(&path)))
+                .then(|| {
+                    removed
+                        .iter()
+                        .position(|old| loaded.get(old) == Some(&hash))
+                })
+                .flatten()
+                .map(|i| removed.swap_remove(i));
+
+            if let Some(old_path) = renamed_from {
+                let (old_slug, new_slug) = (slug_for(&old_path), slug_for(&path));
+                match db::rename_post_slug(pool, &old_slug, &new_slug).await {
+                    Ok(true) => tracing::info!("Renamed post {} to {}", old_slug, new_slug),
+                    Ok(false) => {
+                        tracing::warn!("Could not rename post {} to {}", old_slug, new_slug);
+                        removed.push(old_path.clone());
+                    }
+                    Err(e) => tracing::error!("Failed to rename post {}: {}", old_slug, e),
+                }
+                loaded.remove(&old_path);
+            }
+
+            let entry = sync::sync_post(pool, parser, mode, &slug_for(&path), true).await;
+            sync::log_entry(&entry);
+            loaded.insert(path, hash);
+        }
+
+        for path in removed {
+            let entry = sync::sync_post(pool, parser, mode, &slug_for(&path), true).await;
+            sync::log_entry(&entry);
+            loaded.remove(&path);
+        }
+
+        if changed {
+            state.pages.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[test]
+    fn slugs_stay_inside_posts_dir() {
+        for slug in ["hello-web3", "2024-recap", "a"] {
+            assert!(is_valid_slug(slug), "{slug}");
+            assert_eq!(
+                post_path(slug).unwrap(),
+                Path::new(POSTS_DIR).join(format!("{slug}.md"))
+            );
+        }
+        for slug in [
+            "",
+            "../README",
+            "..",
+            "a/b",
+            "a\\b",
+            "Hello",
+            "a.b",
+            "a b",
+            "é",
+        ] {
+            assert!(!is_valid_slug(slug), "{slug}");
+            assert!(post_path(slug).is_err(), "{slug}");
+        }
+    }
+
+    #[tokio::test]
+    async fn shortcode_errors_point_at_lines_of_the_file() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        let parser = MarkdownParser::new(&Config::from_env());
+
+        let path = std::env::temp_dir().join(format!("shortcode-lines-{}.md", std::process::id()));
+        std::fs::write(
+            &path,
+            "---\ntitle: Lines\ntags:\n  - a\n---\n\n\nIntro\n\n{{< tip 0 >}}\n",
+        )
+        .unwrap();
+        let state = read_post_file(&pool, &parser, &path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        match state {
+            FileState::Invalid(message) => {
+                assert!(message.starts_with("line 10: tip:"), "{message}")
+            }
+            _ => panic!("expected the shortcode error"),
+        }
+    }
+}
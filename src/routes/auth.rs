@@ -1,4 +1,4 @@
-use axum::{routing::post, Router};
+use axum::{routing::{delete, get, post}, Router};
 use std::sync::Arc;
 
 use crate::{handlers::wallet, AppState};
@@ -7,4 +7,9 @@ pub fn router() -> Router<Arc<AppState>> {
     Router::new()
         .route("/nonce", post(wallet::get_nonce))
         .route("/verify", post(wallet::verify_signature))
+        .route("/session", get(wallet::current_session))
+        .route("/logout", post(wallet::logout))
+        .route("/link", post(wallet::link_wallet))
+        .route("/wallets", get(wallet::list_wallets))
+        .route("/wallets/:id", delete(wallet::unlink_wallet))
 }
@@ -0,0 +1,73 @@
+//! sitemap.xml, robots.txt and structured data for search engines.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::json;
+use std::fmt::Write;
+
+use crate::{config::Config, feed::xml_escape, models::Post};
+
+pub struct SitemapUrl {
+    /// Absolute URL
+    pub loc: String,
+    pub lastmod: Option<DateTime<Utc>>,
+}
+
+pub fn sitemap(urls: &[SitemapUrl]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    for url in urls {
+        xml.push_str("<url>");
+        let _ = write!(xml, "<loc>{}</loc>", xml_escape(&url.loc));
+        if let Some(lastmod) = url.lastmod {
+            let _ = write!(
+                xml,
+                "<lastmod>{}</lastmod>",
+                lastmod.to_rfc3339_opts(SecondsFormat::Secs, true)
+            );
+        }
+        xml.push_str("</url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    xml
+}
+
+pub fn robots_txt(config: &Config) -> String {
+    let mut robots = String::from("User-agent: *\n");
+    if config.allow_indexing {
+        for path in &config.robots_disallow {
+            let _ = writeln!(robots, "Disallow: {}", path);
+        }
+        if config.robots_disallow.is_empty() {
+            robots.push_str("Allow: /\n");
+        }
+    } else {
+        robots.push_str("Disallow: /\n");
+    }
+    let _ = writeln!(robots, "\nSitemap: {}/sitemap.xml", config.base_url);
+    robots
+}
+
+/// schema.org `BlogPosting` for a post, safe to embed in a `<script>` tag.
+pub fn blog_posting_json_ld(post: &Post, url: &str, config: &Config) -> String {
+    let data = json!({
+        "@context": "https://schema.org",
+        "@type": "BlogPosting",
+        "headline": post.title,
+        "description": post.description,
+        "url": url,
+        "mainEntityOfPage": { "@type": "WebPage", "@id": url },
+        "datePublished": post.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "dateModified": post.updated_at.max(post.created_at).to_rfc3339_opts(SecondsFormat::Secs, true),
+        "author": { "@type": "Person", "name": post.author },
+        "publisher": { "@type": "Organization", "name": config.blog_title, "url": config.base_url },
+        "keywords": post.tags_list().join(", "),
+        "wordCount": post.content.split_whitespace().count(),
+    });
+
+    // Keep `</script>` and friends in strings from closing the tag early
+    data.to_string()
+        .replace('<', "\\u003c")
+        .replace('>', "\\u003e")
+        .replace('&', "\\u0026")
+}
@@ -0,0 +1,312 @@
+//! Two-way sync between `posts/<slug>.md` and the `posts` table.
+//!
+//! Each post remembers the hash of its file and of its own content as of the
+//! last sync. A change on only one side is copied to the other; a change on
+//! both is a conflict, settled by the configured [`SyncMode`].
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use std::{collections::BTreeSet, path::Path, str::FromStr};
+
+use crate::{
+    db,
+    markdown::MarkdownParser,
+    models::Post,
+    posts_dir::{self, FileState, PostFile},
+};
+
+/// Which side wins when a post changed both in its file and in the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// The file replaces the database version (kept in the revision history)
+    Files,
+    /// The database version is written back over the file
+    Database,
+    /// Neither is touched; the conflict is reported until someone resolves it
+    Manual,
+}
+
+impl FromStr for SyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "files" | "file" => Ok(SyncMode::Files),
+            "database" | "db" => Ok(SyncMode::Database),
+            "manual" => Ok(SyncMode::Manual),
+            other => Err(format!(
+                "unknown sync mode {:?} (expected files, database or manual)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncAction {
+    Unchanged,
+    /// The file was loaded into the database
+    Imported,
+    /// The database version was written to the file
+    Exported,
+    /// The file was removed, so the post was too
+    Deleted,
+    /// Both sides changed and were left as they are
+    Conflict,
+    /// The file couldn't be read or parsed
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncEntry {
+    pub slug: String,
+    pub action: SyncAction,
+    /// Set when both sides had changed
+    pub conflict: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    pub entries: Vec<SyncEntry>,
+}
+
+impl SyncReport {
+    /// Conflicts left for someone to resolve.
+    pub fn unresolved(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.action == SyncAction::Conflict)
+            .count()
+    }
+
+    pub fn log(&self) {
+        self.entries.iter().for_each(log_entry);
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Write a post to its file and record both sides as in sync. Used for every
+/// change made through the admin, so the file isn't mistaken for an edit.
+pub async fn export_post(pool: &SqlitePool, post: &Post) -> Result<()> {
+    let written = std::fs::read(posts_dir::write_post_file(post)?)?;
+    db::mark_post_synced(
+        pool,
+        &post.slug,
+        &hash_bytes(&written),
+        &post.content_hash(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Keep a copy of a file that is about to be overwritten as `<name>.md.bak`,
+/// which isn't picked up as a post.
+fn backup_file(path: &Path) -> Result<()> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(".bak");
+    std::fs::copy(path, backup)?;
+
+    Ok(())
+}
+
+async fn import_post(
+    pool: &SqlitePool,
+    file: &PostFile,
+    existing: Option<&Post>,
+    conflict: bool,
+) -> Result<()> {
+    if conflict {
+        // Make sure the edit being replaced can be restored from the history
+        let note = format!("Database version before loading {}", file.path.display());
+        db::create_revision(pool, &file.post.slug, None, Some(&note)).await?;
+    }
+
+    let changed = existing.is_none_or(|e| file.post.content_differs(e));
+    db::upsert_post(pool, &file.post).await?;
+    if changed {
+        let note = format!("Loaded from {}", file.path.display());
+        db::create_revision(pool, &file.post.slug, None, Some(&note)).await?;
+    }
+    db::mark_post_synced(pool, &file.post.slug, &file.hash, &file.post.content_hash()).await?;
+
+    Ok(())
+}
+
+/// Bring one post and its file in line. With `apply` false nothing is
+/// changed and the entry says what would happen.
+pub async fn sync_post(
+    pool: &SqlitePool,
+    parser: &MarkdownParser,
+    mode: SyncMode,
+    slug: &str,
+    apply: bool,
+) -> SyncEntry {
+    let entry = |action, conflict, message: Option<String>| SyncEntry {
+        slug: slug.to_string(),
+        action,
+        conflict,
+        message,
+    };
+
+    let result: Result<SyncEntry> = async {
+        let existing = db::get_any_post_by_slug(pool, slug).await?;
+        let state = db::get_post_sync_state(pool, slug)
+            .await?
+            .unwrap_or_default();
+        let file = posts_dir::read_post_file(pool, parser, &posts_dir::post_path(slug)?).await?;
+
+        // An unknown synced hash (posts from before syncing) counts as unchanged,
+        // so the first sync behaves like a plain load from the files
+        let db_changed = match (&existing, &state.synced_hash) {
+            (Some(post), Some(hash)) => post.content_hash() != *hash,
+            _ => false,
+        };
+
+        match (file, existing) {
+            (FileState::Missing, None) => Ok(entry(SyncAction::Unchanged, false, None)),
+
+            (FileState::Invalid(e), _) => Ok(entry(SyncAction::Failed, false, Some(e))),
+
+            (FileState::Parsed(file), None) => {
+                if apply {
+                    import_post(pool, &file, None, false).await?;
+                }
+                Ok(entry(SyncAction::Imported, false, None))
+            }
+
+            // Never written to a file, e.g. created before posts were synced
+            (FileState::Missing, Some(post)) if state.file_hash.is_none() => {
+                if apply {
+                    export_post(pool, &post).await?;
+                }
+                Ok(entry(SyncAction::Exported, false, None))
+            }
+
+            (FileState::Missing, Some(post)) => {
+                let message = db_changed
+                    .then(|| "file was deleted but the post was edited since".to_string());
+                match (db_changed, mode) {
+                    (true, SyncMode::Manual) => Ok(entry(SyncAction::Conflict, true, message)),
+                    (true, SyncMode::Database) => {
+                        if apply {
+                            export_post(pool, &post).await?;
+                        }
+                        Ok(entry(SyncAction::Exported, true, message))
+                    }
+                    (conflict, _) => {
+                        if apply {
+                            db::delete_post(pool, slug).await?;
+                        }
+                        Ok(entry(SyncAction::Deleted, conflict, message))
+                    }
+                }
+            }
+
+            (FileState::Parsed(file), Some(post)) => {
+                let file_changed = state.file_hash.as_deref() != Some(file.hash.as_str());
+                let same_content = file.post.content_hash() == post.content_hash();
+
+                if !file_changed && !db_changed {
+                    return Ok(entry(SyncAction::Unchanged, false, None));
+                }
+                if same_content {
+                    // Both sides already agree; just remember that
+                    if apply {
+                        db::mark_post_synced(pool, slug, &file.hash, &post.content_hash()).await?;
+                    }
+                    return Ok(entry(SyncAction::Unchanged, false, None));
+                }
+
+                let conflict = file_changed && db_changed;
+                let message = conflict.then(|| {
+                    "both the file and the post were edited since the last sync".to_string()
+                });
+                let take_file = match (file_changed, db_changed) {
+                    (true, false) => true,
+                    (false, _) => false,
+                    (true, true) => match mode {
+                        SyncMode::Files => true,
+                        SyncMode::Database => false,
+                        SyncMode::Manual => {
+                            return Ok(entry(SyncAction::Conflict, true, message));
+                        }
+                    },
+                };
+
+                if take_file {
+                    if apply {
+                        import_post(pool, &file, Some(&post), conflict).await?;
+                    }
+                    Ok(entry(SyncAction::Imported, conflict, message))
+                } else {
+                    if apply {
+                        if conflict {
+                            backup_file(&file.path)?;
+                        }
+                        export_post(pool, &post).await?;
+                    }
+                    Ok(entry(SyncAction::Exported, conflict, message))
+                }
+            }
+        }
+    }
+    .await;
+
+    result.unwrap_or_else(|e| entry(SyncAction::Failed, false, Some(e.to_string())))
+}
+
+/// Sync every post that has a file, a database row, or both.
+pub async fn sync_all(
+    pool: &SqlitePool,
+    parser: &MarkdownParser,
+    mode: SyncMode,
+    apply: bool,
+) -> Result<SyncReport> {
+    let mut slugs: BTreeSet<String> = db::get_all_post_slugs(pool).await?.into_iter().collect();
+    let dir = Path::new(posts_dir::POSTS_DIR);
+    if dir.exists() {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if posts_dir::is_post_file(&path) {
+                slugs.insert(posts_dir::slug_for(&path));
+            }
+        }
+    }
+
+    let mut report = SyncReport::default();
+    for slug in slugs {
+        report
+            .entries
+            .push(sync_post(pool, parser, mode, &slug, apply).await);
+    }
+
+    Ok(report)
+}
+
+pub fn log_entry(entry: &SyncEntry) {
+    let message = entry.message.as_deref().unwrap_or("");
+    match entry.action {
+        SyncAction::Unchanged => tracing::debug!("Post unchanged: {}", entry.slug),
+        SyncAction::Failed => tracing::warn!("Could not sync {}: {}", entry.slug, message),
+        SyncAction::Conflict => tracing::warn!("Sync conflict on {}: {}", entry.slug, message),
+        action if entry.conflict => {
+            tracing::warn!(
+                "Sync conflict on {} ({}), {:?}",
+                entry.slug,
+                message,
+                action
+            )
+        }
+        action => tracing::info!("Synced {}: {:?}", entry.slug, action),
+    }
+}
@@ -1,52 +1,230 @@
 use ethers::{
-    providers::{Http, Middleware, Provider},
-    types::{Address, Signature, H256},
+    abi::{self, Token},
+    providers::{Http, Middleware, Provider, ProviderError},
+    types::{Address, Signature, TransactionRequest, H256},
 };
 use siwe::Message;
 use std::str::FromStr;
 
-pub fn create_siwe_message(address: &str, nonce: &str) -> String {
-    format!(
-        r#"Sign in to Web3 Blog
-
-URI: http://localhost:3000
-Version: 1
-Chain ID: 1
-Nonce: {}
-Issued At: {}
-Address: {}"#,
-        nonce,
-        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
-        address
-    )
+use super::sign_in::{format_timestamp, parse_timestamp, MessageParams, SignInError, SignInFields};
+
+pub fn create_siwe_message(address: &str, params: &MessageParams) -> Result<String, SignInError> {
+    let malformed = |e: &dyn std::fmt::Display| SignInError::Malformed(e.to_string());
+
+    let address = Address::from_str(address).map_err(|e| malformed(&e))?;
+    let message = Message {
+        domain: params.domain.parse().map_err(|e| malformed(&e))?,
+        address: address.0,
+        statement: Some(params.statement.to_string()),
+        uri: params.uri.parse().map_err(|e| malformed(&e))?,
+        version: siwe::Version::V1,
+        chain_id: params.chain_id.parse().map_err(|e| malformed(&e))?,
+        nonce: params.nonce.to_string(),
+        issued_at: format_timestamp(params.issued_at)
+            .parse()
+            .map_err(|e| malformed(&e))?,
+        expiration_time: Some(
+            format_timestamp(params.expiration_time)
+                .parse()
+                .map_err(|e| malformed(&e))?,
+        ),
+        not_before: None,
+        request_id: None,
+        resources: Vec::new(),
+    };
+
+    Ok(message.to_string())
+}
+
+/// Parse an EIP-4361 message into the fields the server validates.
+pub fn parse_siwe_message(message: &str) -> Result<SignInFields, SignInError> {
+    let message = Message::from_str(message).map_err(|e| SignInError::Malformed(e.to_string()))?;
+
+    Ok(SignInFields {
+        domain: message.domain.to_string(),
+        address: siwe::eip55(&message.address),
+        uri: message.uri.to_string(),
+        chain_id: message.chain_id.to_string(),
+        nonce: message.nonce,
+        issued_at: parse_timestamp(&message.issued_at.to_string())?,
+        expiration_time: message
+            .expiration_time
+            .map(|t| parse_timestamp(&t.to_string()))
+            .transpose()?,
+        not_before: message
+            .not_before
+            .map(|t| parse_timestamp(&t.to_string()))
+            .transpose()?,
+    })
 }
 
-pub async fn verify_signature(address: &str, message: &str, signature: &str) -> bool {
+/// `bytes4(keccak256("isValidSignature(bytes32,bytes)"))`, returned by ERC-1271
+/// wallets when a signature is valid.
+const ERC1271_MAGIC_VALUE: [u8; 4] = [0x16, 0x26, 0xba, 0x7e];
+
+/// Suffix marking an EIP-6492 wrapped signature from a not-yet-deployed account.
+const ERC6492_MAGIC_SUFFIX: [u8; 32] = [
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+    0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92, 0x64, 0x92,
+];
+
+/// Creation code of the `ValidateSigOffchain` contract from EIP-6492. Called
+/// with `abi.encode(signer, hash, signature)` appended, it returns `0x01` when
+/// the signature is valid for an EOA, a deployed ERC-1271 wallet, or a
+/// counterfactual one.
+const UNIVERSAL_SIG_VALIDATOR: &str = "\
+    60806040523480156200001157600080fd5b506040516200070038038062000700833981016040819052620000349162\
+    00056f565b6000620000438484846200004f565b9050806000526001601ff35b600080846001600160a01b0316803b80\
+    6020016040519081016040528181526000908060200190933c90507f6492649264926492649264926492649264926492\
+    649264926492649264926492620000a68462000451565b036200021f57600060608085806020019051810190620000c7\
+    9190620005ce565b8651929550909350915060000362000192576000836001600160a01b031683604051620000f59190\
+    62000643565b6000604051808303816000865af19150503d806000811462000134576040519150601f19603f3d011682\
+    016040523d82523d6000602084013e62000139565b606091505b5050905080620001905760405162461bcd60e51b8152\
+    60206004820152601e60248201527f5369676e617475726556616c696461746f723a206465706c6f796d656e74000060\
+    448201526064015b60405180910390fd5b505b604051630b135d3f60e11b808252906001600160a01b038a1690631626\
+    ba7e90620001c4908b90869060040162000661565b602060405180830381865afa158015620001e2573d6000803e3d60\
+    00fd5b505050506040513d601f19601f820116820180604052508101906200020891906200069d565b6001600160e01b\
+    031916149450505050506200044a565b805115620002b157604051630b135d3f60e11b808252906001600160a01b0387\
+    1690631626ba7e9062000259908890889060040162000661565b602060405180830381865afa15801562000277573d60\
+    00803e3d6000fd5b505050506040513d601f19601f820116820180604052508101906200029d91906200069d565b6001\
+    600160e01b031916149150506200044a565b8251604114620003195760405162461bcd60e51b81526020600482015260\
+    3a6024820152600080516020620006e083398151915260448201527f3a20696e76616c6964207369676e617475726520\
+    6c656e677468000000000000606482015260840162000187565b620003236200046b565b506020830151604080850151\
+    855186939260009185919081106200034b576200034b620006c9565b016020015160f81c9050601b8114801590620003\
+    6b57508060ff16601c14155b15620003cf5760405162461bcd60e51b815260206004820152603b602482015260008051\
+    6020620006e083398151915260448201527f3a20696e76616c6964207369676e617475726520762076616c7565000000\
+    0000606482015260840162000187565b6040805160008152602081018083528a905260ff831691810191909152606081\
+    01849052608081018390526001600160a01b038a169060019060a0016020604051602081039080840390855afa158015\
+    6200042e573d6000803e3d6000fd5b505050602060405103516001600160a01b031614955050505050505b9392505050\
+    565b60006020825110156200046357600080fd5b508051015190565b6040518060600160405280600390602082028036\
+    8337509192915050565b6001600160a01b03811681146200049f57600080fd5b50565b634e487b7160e01b6000526041\
+    60045260246000fd5b60005b83811015620004d5578181015183820152602001620004bb565b50506000910152565b60\
+    0082601f830112620004f057600080fd5b81516001600160401b03808211156200050d576200050d620004a2565b6040\
+    51601f8301601f19908116603f01168101908282118183101715620005385762000538620004a2565b81604052838152\
+    8660208588010111156200055257600080fd5b62000565846020830160208901620004b8565b9695505050505050565b\
+    6000806000606084860312156200058557600080fd5b8351620005928162000489565b60208501516040860151919450\
+    92506001600160401b03811115620005b657600080fd5b620005c486828701620004de565b9150509250925092565b60\
+    0080600060608486031215620005e457600080fd5b8351620005f18162000489565b6020850151909350600160016040\
+    1b03808211156200060f57600080fd5b6200061d87838801620004de565b935060408601519150808211156200063457\
+    600080fd5b50620005c486828701620004de565b6000825162000657818460208701620004b8565b9190910192915050\
+    565b828152604060208201526000825180604084015262000688816060850160208701620004b8565b601f01601f1916\
+    919091016060019392505050565b600060208284031215620006b057600080fd5b81516001600160e01b031981168114\
+    6200044a57600080fd5b634e487b7160e01b600052603260045260246000fdfe5369676e617475726556616c69646174\
+    6f72237265636f7665725369676e6572";
+
+/// Verify a personal_sign signature from either an EOA (ECDSA recovery) or a
+/// smart-contract wallet (ERC-1271, or EIP-6492 for undeployed accounts).
+/// Contract checks go through the JSON-RPC endpoint at `rpc_url`.
+pub async fn verify_signature(
+    rpc_url: &str,
+    address: &str,
+    message: &str,
+    signature: &str,
+) -> bool {
     let sig_bytes = match hex::decode(signature.trim_start_matches("0x")) {
         Ok(bytes) => bytes,
         Err(_) => return false,
     };
 
-    if sig_bytes.len() != 65 {
-        return false;
-    }
-
-    let signature = match Signature::try_from(sig_bytes.as_slice()) {
-        Ok(sig) => sig,
+    let expected = match Address::from_str(address) {
+        Ok(addr) => addr,
         Err(_) => return false,
     };
 
     let message_hash = ethers::utils::hash_message(message);
 
-    match signature.recover(message_hash) {
-        Ok(recovered) => {
-            let expected = match Address::from_str(address) {
-                Ok(addr) => addr,
-                Err(_) => return false,
-            };
-            recovered == expected
+    if !sig_bytes.ends_with(&ERC6492_MAGIC_SUFFIX)
+        && recover_signer(&sig_bytes, message_hash) == Some(expected)
+    {
+        return true;
+    }
+
+    let provider = match Provider::<Http>::try_from(rpc_url) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+    match verify_contract_signature(&provider, expected, message_hash, &sig_bytes).await {
+        Ok(valid) => valid,
+        Err(e) => {
+            tracing::warn!("Contract signature check for {:?} failed: {}", expected, e);
+            false
         }
-        Err(_) => false,
+    }
+}
+
+fn recover_signer(sig_bytes: &[u8], message_hash: H256) -> Option<Address> {
+    if sig_bytes.len() != 65 {
+        return None;
+    }
+    let signature = Signature::try_from(sig_bytes).ok()?;
+    signature.recover(message_hash).ok()
+}
+
+async fn verify_contract_signature(
+    provider: &Provider<Http>,
+    account: Address,
+    hash: H256,
+    sig_bytes: &[u8],
+) -> anyhow::Result<bool> {
+    if sig_bytes.ends_with(&ERC6492_MAGIC_SUFFIX) {
+        return validate_sig_offchain(provider, account, hash, sig_bytes).await;
+    }
+
+    // Plain signature: only a deployed contract can vouch for it
+    let deployed = !provider.get_code(account, None).await?.is_empty();
+    Ok(deployed && is_valid_signature(provider, account, hash, sig_bytes).await?)
+}
+
+/// EIP-6492: run the validator as a deployless `eth_call`. It deploys the
+/// account through its factory if there's no code there yet, then checks the
+/// unwrapped signature, so nothing is ever sent on chain.
+async fn validate_sig_offchain(
+    provider: &Provider<Http>,
+    account: Address,
+    hash: H256,
+    signature: &[u8],
+) -> anyhow::Result<bool> {
+    let mut data = hex::decode(UNIVERSAL_SIG_VALIDATOR)?;
+    data.extend(abi::encode(&[
+        Token::Address(account),
+        Token::FixedBytes(hash.as_bytes().to_vec()),
+        Token::Bytes(signature.to_vec()),
+    ]));
+    let tx = TransactionRequest::new().data(data);
+
+    match provider.call(&tx.into(), None).await {
+        Ok(result) => Ok(result.as_ref() == [1]),
+        Err(ProviderError::JsonRpcClientError(e)) if e.as_error_response().is_some() => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn is_valid_signature_calldata(hash: H256, signature: &[u8]) -> Vec<u8> {
+    let mut data = ERC1271_MAGIC_VALUE.to_vec();
+    data.extend(abi::encode(&[
+        Token::FixedBytes(hash.as_bytes().to_vec()),
+        Token::Bytes(signature.to_vec()),
+    ]));
+    data
+}
+
+/// ERC-1271: ask the account contract whether `signature` is valid for `hash`.
+async fn is_valid_signature(
+    provider: &Provider<Http>,
+    account: Address,
+    hash: H256,
+    signature: &[u8],
+) -> anyhow::Result<bool> {
+    let tx = TransactionRequest::new()
+        .to(account)
+        .data(is_valid_signature_calldata(hash, signature));
+
+    // A revert just means the wallet rejected the signature
+    match provider.call(&tx.into(), None).await {
+        Ok(result) => Ok(result.starts_with(&ERC1271_MAGIC_VALUE)),
+        Err(ProviderError::JsonRpcClientError(e)) if e.as_error_response().is_some() => Ok(false),
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -78,9 +256,268 @@ pub async fn verify_transaction(rpc_url: &str, tx_hash: &str, to_address: &str)
     }
 }
 
-pub async fn get_eth_balance(rpc_url: &str, address: &str) -> Option<String> {
-    let provider = Provider::<Http>::try_from(rpc_url).ok()?;
-    let address = Address::from_str(address).ok()?;
-    let balance = provider.get_balance(address, None).await.ok()?;
-    Some(ethers::utils::format_ether(balance))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use ethers::{
+        abi::ParamType,
+        signers::{LocalWallet, Signer},
+        types::Bytes,
+    };
+    use serde_json::{json, Value};
+    use std::{collections::HashMap, sync::Arc};
+
+    const MESSAGE: &str = "Sign in to Thoughnuts";
+
+    /// A stand-in for an anvil node: contract wallets that accept one
+    /// signature each, and factories that deploy one of them when called.
+    #[derive(Default)]
+    struct Chain {
+        wallets: HashMap<Address, Vec<u8>>,
+        factories: HashMap<Address, (Vec<u8>, Address, Vec<u8>)>,
+    }
+
+    impl Chain {
+        fn accepts(&self, account: Address, hash: &[u8], signature: &[u8]) -> bool {
+            hash_matches(hash) && self.wallets.get(&account).is_some_and(|s| s == signature)
+        }
+
+        fn call(&self, tx: &Value) -> Result<Vec<u8>, ()> {
+            let data = hex::decode(tx["data"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+
+            let Some(to) = tx.get("to").and_then(|to| to.as_str()) else {
+                return Ok(vec![self.validate_sig_offchain(&data) as u8]);
+            };
+            let account = Address::from_str(to).unwrap();
+            assert_eq!(
+                data[..4],
+                ERC1271_MAGIC_VALUE,
+                "not an isValidSignature call"
+            );
+            let args = decode(&[ParamType::FixedBytes(32), ParamType::Bytes], &data[4..]);
+
+            if self.accepts(account, &args[0], &args[1]) {
+                let mut result = ERC1271_MAGIC_VALUE.to_vec();
+                result.resize(32, 0);
+                Ok(result)
+            } else {
+                Err(())
+            }
+        }
+
+        /// What the validator contract does, minus the ECDSA fallback.
+        fn validate_sig_offchain(&self, data: &[u8]) -> bool {
+            let validator = hex::decode(UNIVERSAL_SIG_VALIDATOR).unwrap();
+            let args = data
+                .strip_prefix(validator.as_slice())
+                .expect("a deployless call to the EIP-6492 validator");
+            let args = decode(
+                &[
+                    ParamType::Address,
+                    ParamType::FixedBytes(32),
+                    ParamType::Bytes,
+                ],
+                args,
+            );
+            let account = Address::from_slice(&args[0]);
+
+            let Some(wrapped) = args[2].strip_suffix(&ERC6492_MAGIC_SUFFIX) else {
+                return self.accepts(account, &args[1], &args[2]);
+            };
+            let wrapped = decode(
+                &[ParamType::Address, ParamType::Bytes, ParamType::Bytes],
+                wrapped,
+            );
+            // eth_call state is thrown away, so the deployment doesn't stick
+            let factory = Address::from_slice(&wrapped[0]);
+            let deployed = match self.factories.get(&factory) {
+                Some((calldata, deploys, accepts))
+                    if *calldata == wrapped[1] && *deploys == account =>
+                {
+                    Some(accepts)
+                }
+                _ => self.wallets.get(&account),
+            };
+            hash_matches(&args[1]) && deployed.is_some_and(|s| *s == wrapped[2])
+        }
+    }
+
+    fn hash_matches(hash: &[u8]) -> bool {
+        hash == ethers::utils::hash_message(MESSAGE).as_bytes()
+    }
+
+    /// ABI-decode into raw bytes: addresses as 20 bytes, everything else as is.
+    fn decode(types: &[ParamType], data: &[u8]) -> Vec<Vec<u8>> {
+        abi::decode(types, data)
+            .unwrap()
+            .into_iter()
+            .map(|token| match token {
+                Token::Address(a) => a.as_bytes().to_vec(),
+                Token::FixedBytes(b) | Token::Bytes(b) => b,
+                other => panic!("unexpected token {:?}", other),
+            })
+            .collect()
+    }
+
+    async fn rpc(State(chain): State<Arc<Chain>>, Json(req): Json<Value>) -> Json<Value> {
+        let params = &req["params"];
+        let result = match req["method"].as_str().unwrap() {
+            "eth_getCode" => {
+                let account = Address::from_str(params[0].as_str().unwrap()).unwrap();
+                let code = if chain.wallets.contains_key(&account) {
+                    vec![0x60, 0x00]
+                } else {
+                    Vec::new()
+                };
+                Ok(json!(Bytes::from(code)))
+            }
+            "eth_call" => chain.call(&params[0]).map(|out| json!(Bytes::from(out))),
+            method => panic!("unexpected RPC call {}", method),
+        };
+
+        Json(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": req["id"], "result": result }),
+            Err(()) => json!({
+                "jsonrpc": "2.0",
+                "id": req["id"],
+                "error": { "code": 3, "message": "execution reverted", "data": "0x" },
+            }),
+        })
+    }
+
+    async fn serve(chain: Chain) -> String {
+        let app = Router::new()
+            .route("/", post(rpc))
+            .with_state(Arc::new(chain));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn wrap_6492(factory: Address, calldata: &[u8], signature: &[u8]) -> String {
+        let mut wrapped = abi::encode(&[
+            Token::Address(factory),
+            Token::Bytes(calldata.to_vec()),
+            Token::Bytes(signature.to_vec()),
+        ]);
+        wrapped.extend(ERC6492_MAGIC_SUFFIX);
+        hex::encode(wrapped)
+    }
+
+    #[tokio::test]
+    async fn ecdsa_signatures_need_no_rpc() {
+        let wallet: LocalWallet =
+            "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
+                .parse()
+                .unwrap();
+        let signature = wallet.sign_message(MESSAGE).await.unwrap().to_string();
+        let address = format!("{:?}", wallet.address());
+        let unreachable = "http://127.0.0.1:9";
+
+        assert!(verify_signature(unreachable, &address, MESSAGE, &signature).await);
+        assert!(!verify_signature(unreachable, &address, "another message", &signature).await);
+        assert!(!verify_signature(unreachable, &address, MESSAGE, "0xdeadbeef").await);
+    }
+
+    #[tokio::test]
+    async fn erc1271_wallets_are_asked_through_rpc() {
+        let safe = Address::repeat_byte(0x5a);
+        let mut chain = Chain::default();
+        chain.wallets.insert(safe, vec![0xaa; 65]);
+        let url = serve(chain).await;
+        let address = format!("{:?}", safe);
+
+        assert!(verify_signature(&url, &address, MESSAGE, &hex::encode([0xaa; 65])).await);
+        assert!(!verify_signature(&url, &address, MESSAGE, &hex::encode([0xbb; 65])).await);
+        assert!(
+            !verify_signature(&url, &address, "another message", &hex::encode([0xaa; 65])).await
+        );
+
+        // No code at the address, so nothing can vouch for the signature
+        let eoa = format!("{:?}", Address::repeat_byte(0x01));
+        assert!(!verify_signature(&url, &eoa, MESSAGE, &hex::encode([0xaa; 65])).await);
+    }
+
+    #[tokio::test]
+    async fn erc6492_wrapped_signatures_deploy_counterfactual_wallets() {
+        let factory = Address::repeat_byte(0xfa);
+        let account = Address::repeat_byte(0xc0);
+        let calldata = vec![0x12; 36];
+        let mut chain = Chain::default();
+        chain
+            .factories
+            .insert(factory, (calldata.clone(), account, vec![0xaa; 65]));
+        let url = serve(chain).await;
+        let address = format!("{:?}", account);
+
+        let wrapped = wrap_6492(factory, &calldata, &[0xaa; 65]);
+        assert!(verify_signature(&url, &address, MESSAGE, &wrapped).await);
+
+        // Still not deployed, so the wrapper is needed every time
+        let unwrapped = hex::encode([0xaa; 65]);
+        assert!(!verify_signature(&url, &address, MESSAGE, &unwrapped).await);
+
+        let wrong_signature = wrap_6492(factory, &calldata, &[0xbb; 65]);
+        assert!(!verify_signature(&url, &address, MESSAGE, &wrong_signature).await);
+
+        let wrong_factory = wrap_6492(Address::repeat_byte(0xfb), &calldata, &[0xaa; 65]);
+        let other = format!("{:?}", Address::repeat_byte(0xc1));
+        assert!(!verify_signature(&url, &other, MESSAGE, &wrong_factory).await);
+    }
+
+    fn siwe_params(nonce: &str) -> MessageParams<'_> {
+        let issued_at = parse_timestamp("2024-05-01T12:00:00Z").unwrap();
+        MessageParams {
+            domain: "blog.example",
+            uri: "https://blog.example/",
+            chain_id: "1",
+            statement: MESSAGE,
+            nonce,
+            issued_at,
+            expiration_time: issued_at + chrono::Duration::minutes(10),
+        }
+    }
+
+    #[test]
+    fn siwe_messages_parse_back_into_their_fields() {
+        let params = siwe_params("n0nce1234");
+        let address = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed";
+        let message = create_siwe_message(address, &params).unwrap();
+        let fields = parse_siwe_message(&message).unwrap();
+
+        assert_eq!(fields.domain, "blog.example");
+        // Addresses come back in EIP-55 checksum casing
+        assert_eq!(fields.address, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+        assert_eq!(fields.uri, "https://blog.example/");
+        assert_eq!(fields.chain_id, "1");
+        assert_eq!(fields.nonce, "n0nce1234");
+        assert_eq!(fields.issued_at, params.issued_at);
+        assert_eq!(fields.expiration_time, Some(params.expiration_time));
+        assert_eq!(fields.not_before, None);
+    }
+
+    #[test]
+    fn malformed_siwe_messages_are_rejected() {
+        let address = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        assert!(matches!(
+            create_siwe_message("not an address", &siwe_params("n0nce1234")),
+            Err(SignInError::Malformed(_))
+        ));
+
+        let message = create_siwe_message(address, &siwe_params("n0nce1234")).unwrap();
+        for broken in [
+            message.replace("Version: 1", "Version: 2"),
+            message.replace("Chain ID: 1", "Chain ID: one"),
+            message.replace("Nonce: n0nce1234\n", ""),
+            message.replace("2024-05-01T12:00:00Z", "yesterday"),
+        ] {
+            assert!(
+                matches!(parse_siwe_message(&broken), Err(SignInError::Malformed(_))),
+                "accepted {:?}",
+                broken
+            );
+        }
+    }
 }
@@ -1,2 +1,3 @@
 pub mod ethereum;
+pub mod sign_in;
 pub mod solana;
@@ -0,0 +1,221 @@
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+
+/// Allowed clock drift between the wallet and the server.
+const CLOCK_SKEW_SECS: i64 = 60;
+
+#[derive(Debug, Error)]
+pub enum SignInError {
+    #[error("malformed sign-in message: {0}")]
+    Malformed(String),
+    #[error("domain does not match")]
+    Domain,
+    #[error("URI does not match")]
+    Uri,
+    #[error("chain ID does not match")]
+    ChainId,
+    #[error("address does not match")]
+    Address,
+    #[error("nonce does not match")]
+    Nonce,
+    #[error("message has expired")]
+    Expired,
+    #[error("message is not yet valid")]
+    NotYetValid,
+}
+
+/// The fields of a SIWE/SIWS message that the server checks.
+#[derive(Debug, Clone)]
+pub struct SignInFields {
+    pub domain: String,
+    pub address: String,
+    pub uri: String,
+    pub chain_id: String,
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+    pub not_before: Option<DateTime<Utc>>,
+}
+
+/// Server-chosen values for a freshly issued sign-in message.
+pub struct MessageParams<'a> {
+    pub domain: &'a str,
+    pub uri: &'a str,
+    pub chain_id: &'a str,
+    pub statement: &'a str,
+    pub nonce: &'a str,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: DateTime<Utc>,
+}
+
+/// What the server expects a signed message to contain.
+pub struct Expected<'a> {
+    pub domain: &'a str,
+    pub uri: &'a str,
+    pub chain_id: &'a str,
+    pub address: &'a str,
+    pub nonce: &'a str,
+    pub max_age: Duration,
+}
+
+impl SignInFields {
+    pub fn validate(&self, expected: &Expected, now: DateTime<Utc>) -> Result<(), SignInError> {
+        if self.domain != expected.domain {
+            return Err(SignInError::Domain);
+        }
+        if self.uri.trim_end_matches('/') != expected.uri.trim_end_matches('/') {
+            return Err(SignInError::Uri);
+        }
+        if self.chain_id != expected.chain_id {
+            return Err(SignInError::ChainId);
+        }
+
+        // Ethereum addresses may differ only in EIP-55 checksum casing
+        let address_matches = if expected.address.starts_with("0x") {
+            self.address.eq_ignore_ascii_case(expected.address)
+        } else {
+            self.address == expected.address
+        };
+        if !address_matches {
+            return Err(SignInError::Address);
+        }
+
+        if expected.nonce.is_empty() || self.nonce != expected.nonce {
+            return Err(SignInError::Nonce);
+        }
+
+        let skew = Duration::seconds(CLOCK_SKEW_SECS);
+        if self.issued_at > now + skew {
+            return Err(SignInError::NotYetValid);
+        }
+        if now - self.issued_at > expected.max_age {
+            return Err(SignInError::Expired);
+        }
+        if matches!(self.expiration_time, Some(exp) if exp <= now) {
+            return Err(SignInError::Expired);
+        }
+        if matches!(self.not_before, Some(nbf) if nbf > now + skew) {
+            return Err(SignInError::NotYetValid);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn format_timestamp(value: DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+pub fn parse_timestamp(value: &str) -> Result<DateTime<Utc>, SignInError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| SignInError::Malformed(format!("invalid timestamp {:?}: {}", value, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    fn now() -> DateTime<Utc> {
+        parse_timestamp("2024-05-01T12:00:00Z").unwrap()
+    }
+
+    fn expected() -> Expected<'static> {
+        Expected {
+            domain: "blog.example",
+            uri: "https://blog.example",
+            chain_id: "1",
+            address: ADDRESS,
+            nonce: "n0nce",
+            max_age: Duration::minutes(10),
+        }
+    }
+
+    fn fields() -> SignInFields {
+        SignInFields {
+            domain: "blog.example".to_string(),
+            address: ADDRESS.to_string(),
+            uri: "https://blog.example/".to_string(),
+            chain_id: "1".to_string(),
+            nonce: "n0nce".to_string(),
+            issued_at: now() - Duration::minutes(1),
+            expiration_time: Some(now() + Duration::minutes(5)),
+            not_before: None,
+        }
+    }
+
+    fn check(edit: impl FnOnce(&mut SignInFields)) -> Result<(), SignInError> {
+        let mut fields = fields();
+        edit(&mut fields);
+        fields.validate(&expected(), now())
+    }
+
+    #[test]
+    fn accepts_a_fresh_matching_message() {
+        assert!(check(|_| {}).is_ok());
+        // Checksum casing and clock skew are tolerated
+        assert!(check(|f| f.address = ADDRESS.to_lowercase()).is_ok());
+        assert!(check(|f| f.issued_at = now() + Duration::seconds(30)).is_ok());
+    }
+
+    #[test]
+    fn rejects_mismatched_fields() {
+        assert!(matches!(
+            check(|f| f.domain = "evil.example".to_string()),
+            Err(SignInError::Domain)
+        ));
+        assert!(matches!(
+            check(|f| f.uri = "https://evil.example".to_string()),
+            Err(SignInError::Uri)
+        ));
+        assert!(matches!(
+            check(|f| f.chain_id = "137".to_string()),
+            Err(SignInError::ChainId)
+        ));
+        assert!(matches!(
+            check(|f| f.address = "0x0000000000000000000000000000000000000001".to_string()),
+            Err(SignInError::Address)
+        ));
+        assert!(matches!(
+            check(|f| f.nonce = "other".to_string()),
+            Err(SignInError::Nonce)
+        ));
+    }
+
+    #[test]
+    fn an_empty_expected_nonce_matches_nothing() {
+        let mut fields = fields();
+        fields.nonce = String::new();
+        let expected = Expected {
+            nonce: "",
+            ..expected()
+        };
+        assert!(matches!(
+            fields.validate(&expected, now()),
+            Err(SignInError::Nonce)
+        ));
+    }
+
+    #[test]
+    fn rejects_messages_outside_their_time_window() {
+        assert!(matches!(
+            check(|f| f.issued_at = now() - Duration::minutes(11)),
+            Err(SignInError::Expired)
+        ));
+        assert!(matches!(
+            check(|f| f.issued_at = now() + Duration::minutes(2)),
+            Err(SignInError::NotYetValid)
+        ));
+        assert!(matches!(
+            check(|f| f.expiration_time = Some(now())),
+            Err(SignInError::Expired)
+        ));
+        assert!(matches!(
+            check(|f| f.not_before = Some(now() + Duration::minutes(2))),
+            Err(SignInError::NotYetValid)
+        ));
+        assert!(check(|f| f.not_before = Some(now() + Duration::seconds(30))).is_ok());
+    }
+}
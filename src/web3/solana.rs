@@ -1,20 +1,79 @@
 use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 
-pub fn create_siws_message(address: &str, nonce: &str) -> String {
+use super::sign_in::{format_timestamp, parse_timestamp, MessageParams, SignInError, SignInFields};
+
+const PREAMBLE: &str = " wants you to sign in with your Solana account:";
+
+/// Build a Sign-In With Solana message, mirroring the EIP-4361 layout.
+pub fn create_siws_message(address: &str, params: &MessageParams) -> String {
     format!(
-        r#"Sign in to Web3 Blog
+        r#"{}{}
+{}
+
+{}
 
-URI: http://localhost:3000
+URI: {}
 Version: 1
+Chain ID: {}
 Nonce: {}
 Issued At: {}
-Address: {}"#,
-        nonce,
-        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
-        address
+Expiration Time: {}"#,
+        params.domain,
+        PREAMBLE,
+        address,
+        params.statement,
+        params.uri,
+        params.chain_id,
+        params.nonce,
+        format_timestamp(params.issued_at),
+        format_timestamp(params.expiration_time),
     )
 }
 
+/// Parse a SIWS message into the fields the server validates.
+pub fn parse_siws_message(message: &str) -> Result<SignInFields, SignInError> {
+    let malformed = |what: &str| SignInError::Malformed(what.to_string());
+
+    let mut lines = message.lines();
+    let domain = lines
+        .next()
+        .and_then(|l| l.strip_suffix(PREAMBLE))
+        .ok_or_else(|| malformed("missing preamble"))?;
+    let address = lines.next().ok_or_else(|| malformed("missing address"))?;
+
+    let field = |tag: &str| {
+        message
+            .lines()
+            .find_map(|l| l.strip_prefix(tag))
+            .map(str::trim)
+    };
+
+    if field("Version: ") != Some("1") {
+        return Err(malformed("unsupported version"));
+    }
+
+    Ok(SignInFields {
+        domain: domain.to_string(),
+        address: address.trim().to_string(),
+        uri: field("URI: ")
+            .ok_or_else(|| malformed("missing URI"))?
+            .to_string(),
+        chain_id: field("Chain ID: ")
+            .ok_or_else(|| malformed("missing chain ID"))?
+            .to_string(),
+        nonce: field("Nonce: ")
+            .ok_or_else(|| malformed("missing nonce"))?
+            .to_string(),
+        issued_at: parse_timestamp(
+            field("Issued At: ").ok_or_else(|| malformed("missing issued at"))?,
+        )?,
+        expiration_time: field("Expiration Time: ")
+            .map(parse_timestamp)
+            .transpose()?,
+        not_before: field("Not Before: ").map(parse_timestamp).transpose()?,
+    })
+}
+
 pub fn verify_signature(address: &str, message: &str, signature: &str) -> bool {
     // Decode the Solana public key (base58)
     let pubkey_bytes = match bs58::decode(address).into_vec() {
@@ -22,10 +81,11 @@ pub fn verify_signature(address: &str, message: &str, signature: &str) -> bool {
         _ => return false,
     };
 
-    // Try to decode signature as base58 first, then hex
+    // Try to decode signature as base58 first, then hex. A hex string can
+    // also be valid base58, so fall back whenever the length is wrong.
     let sig_bytes = match bs58::decode(signature).into_vec() {
-        Ok(bytes) => bytes,
-        Err(_) => match hex::decode(signature.trim_start_matches("0x")) {
+        Ok(bytes) if bytes.len() == 64 => bytes,
+        _ => match hex::decode(signature.trim_start_matches("0x")) {
             Ok(bytes) => bytes,
             Err(_) => return false,
         },
@@ -64,3 +124,70 @@ pub async fn verify_transaction(_rpc_url: &str, _tx_signature: &str, _to_address
     // The wallet already confirmed the transaction on-chain
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const ADDRESS: &str = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM";
+
+    fn params(nonce: &str) -> MessageParams<'_> {
+        let issued_at = parse_timestamp("2024-05-01T12:00:00Z").unwrap();
+        MessageParams {
+            domain: "blog.example",
+            uri: "https://blog.example",
+            chain_id: "mainnet",
+            statement: "Sign in to the blog",
+            nonce,
+            issued_at,
+            expiration_time: issued_at + Duration::minutes(10),
+        }
+    }
+
+    #[test]
+    fn parses_the_messages_it_creates() {
+        let params = params("n0nce");
+        let fields = parse_siws_message(&create_siws_message(ADDRESS, &params)).unwrap();
+
+        assert_eq!(fields.domain, "blog.example");
+        assert_eq!(fields.address, ADDRESS);
+        assert_eq!(fields.uri, "https://blog.example");
+        assert_eq!(fields.chain_id, "mainnet");
+        assert_eq!(fields.nonce, "n0nce");
+        assert_eq!(fields.issued_at, params.issued_at);
+        assert_eq!(fields.expiration_time, Some(params.expiration_time));
+        assert_eq!(fields.not_before, None);
+    }
+
+    #[test]
+    fn reads_not_before() {
+        let message = format!(
+            "{}\nNot Before: 2024-05-01T12:05:00Z",
+            create_siws_message(ADDRESS, &params("n0nce"))
+        );
+        let fields = parse_siws_message(&message).unwrap();
+        assert_eq!(
+            fields.not_before,
+            Some(parse_timestamp("2024-05-01T12:05:00Z").unwrap())
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let message = create_siws_message(ADDRESS, &params("n0nce"));
+        for broken in [
+            message.replace(PREAMBLE, " wants you to sign in:"),
+            message.replace("Version: 1", "Version: 2"),
+            message.replace("Nonce: n0nce\n", ""),
+            message.replace("Chain ID: mainnet\n", ""),
+            message.replace("Issued At: 2024-05-01T12:00:00Z", "Issued At: yesterday"),
+        ] {
+            assert!(
+                matches!(parse_siws_message(&broken), Err(SignInError::Malformed(_))),
+                "accepted {:?}",
+                broken
+            );
+        }
+    }
+}
@@ -44,13 +44,16 @@ const ERC20_ABI = [
     }
 ];
 
-// Admin addresses (lowercase for comparison)
-const ADMIN_ADDRESSES = [
-    '0x360091e9e692b7775543da956b7ca6cc39bae86c'
-];
-
-function isAdmin(address) {
-    return ADMIN_ADDRESSES.includes(address.toLowerCase());
+// Look up the signed-in user's role from the server session
+async function fetchUserType() {
+    try {
+        const response = await fetch('/api/auth/session');
+        if (!response.ok) return null;
+        const session = await response.json();
+        return session?.user_type || null;
+    } catch (e) {
+        return null;
+    }
 }
 
 // Modal Functions
@@ -409,7 +412,6 @@ async function authenticateWalletConnect(address) {
             window.connectedWallet = {
                 address: address,
                 chain: 'ethereum',
-                token: verifyResult.session_token,
                 provider: 'walletconnect'
             };
 
@@ -725,23 +727,76 @@ function connectAvalancheMobile() {
     }
 }
 
+// Server-side wallet type: Base and Avalanche are EVM chains signed with SIWE
+function serverWalletType(chain) {
+    return chain === 'solana' ? 'solana' : 'ethereum';
+}
+
+// Fetch a sign-in message for the wallet, sign it and post it to `endpoint`
+// (/api/auth/verify to sign in, /api/auth/link to link to the current account).
+// Returns the server's JSON reply, or null if signing was cancelled.
+async function signAndSubmit(address, walletType, endpoint) {
+    const nonceResponse = await fetch('/api/auth/nonce', {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({
+            wallet_address: address,
+            wallet_type: walletType
+        })
+    });
+
+    if (!nonceResponse.ok) {
+        console.error('Nonce request failed:', nonceResponse.status);
+        throw new Error('Failed to get nonce');
+    }
+
+    const { message } = await nonceResponse.json();
+
+    const signature = walletType === 'solana'
+        ? await signMessageSol(message)
+        : await signMessageEth(message);
+    if (!signature) return null;
+
+    const response = await fetch(endpoint, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({
+            wallet_address: address,
+            wallet_type: walletType,
+            signature: signature,
+            message: message
+        })
+    });
+
+    return await response.json();
+}
+
 // Generic authentication function for wallet connections
 async function authenticateWallet(address, chain, walletType) {
     const statusEl = document.getElementById('walletStatus');
 
     try {
-        // For development: simplified connection without server verification
-        // This allows testing wallet connection even if server verification fails
-
-        statusEl.innerHTML = '<span class="text-blue-400">Connecting wallet...</span>';
+        statusEl.innerHTML = '<span class="text-blue-400">Please sign the message in your wallet...</span>';
         statusEl.classList.remove('hidden');
 
-        // Store connection info directly (skip server verification for now)
+        const result = await signAndSubmit(address, serverWalletType(chain), '/api/auth/verify');
+
+        if (!result) {
+            statusEl.innerHTML = '<span class="text-red-500">Signing cancelled or failed.</span>';
+            setTimeout(() => statusEl.classList.add('hidden'), 3000);
+            return;
+        }
+
+        if (!result.success) {
+            statusEl.innerHTML = `<span class="text-red-500">${result.message || 'Authentication failed.'}</span>`;
+            setTimeout(() => statusEl.classList.add('hidden'), 3000);
+            return;
+        }
+
         window.connectedWallet = {
             address: address,
             chain: chain,
-            walletType: walletType,
-            token: null
+            walletType: walletType
         };
 
         localStorage.setItem('connectedWallet', JSON.stringify(window.connectedWallet));
@@ -756,78 +811,97 @@ async function authenticateWallet(address, chain, walletType) {
         setTimeout(() => {
             closeWalletModal();
         }, 500);
+    } catch (error) {
+        console.error('Authentication error:', error);
+        statusEl.innerHTML = '<span class="text-red-500">Connection error. Please try again.</span>';
+        setTimeout(() => statusEl.classList.add('hidden'), 3000);
+    }
+}
 
-        return;
-
-        /* Original server verification code (disabled for development)
-        // Get nonce from server
-        const nonceResponse = await fetch('/api/auth/nonce', {
-            method: 'POST',
-            headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({
-                wallet_address: address,
-                wallet_type: chain
-            })
-        });
+// Link another wallet to the signed-in account. The new wallet signs its own
+// nonce message, proving ownership of both wallets.
+async function linkWallet(walletType) {
+    let address = null;
 
-        if (!nonceResponse.ok) {
-            console.error('Nonce request failed:', nonceResponse.status);
-            throw new Error('Failed to get nonce');
+    try {
+        if (walletType === 'solana') {
+            address = await connectPhantom();
+        } else if (window.ethereum) {
+            const accounts = await window.ethereum.request({ method: 'eth_requestAccounts' });
+            address = accounts[0];
+        } else {
+            alert('Please install MetaMask to link an Ethereum wallet!');
+            return;
         }
 
-        const nonceData = await nonceResponse.json();
-        const { nonce, message } = nonceData;
+        if (!address) return;
 
-        // Sign the message
-        statusEl.innerHTML = '<span class="text-blue-400">Please sign the message in your wallet...</span>';
+        const result = await signAndSubmit(address, walletType, '/api/auth/link');
+        if (!result) return;
+        if (!result.success) throw new Error(result.message || 'Failed to link wallet');
 
-        let signature = null;
-        if (chain === 'ethereum') {
-            signature = await signMessageEth(message);
-        } else if (chain === 'solana') {
-            signature = await signMessageSol(message);
-        }
-
-        if (!signature) {
-            statusEl.innerHTML = '<span class="text-red-500">Signing cancelled or failed.</span>';
-            setTimeout(() => statusEl.classList.add('hidden'), 3000);
-            return;
-        }
+        renderLinkedWallets(result.wallets);
+        applyUserRole();
+    } catch (error) {
+        console.error('Link wallet error:', error);
+        alert(error.message);
+    }
+}
 
-        // Verify signature with server
-        const verifyResponse = await fetch('/api/auth/verify', {
-            method: 'POST',
-            headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({
-                wallet_address: address,
-                wallet_type: chain,
-                signature: signature,
-                message: message
-            })
-        });
+async function unlinkWallet(userId) {
+    if (!confirm('Unlink this wallet from your account?')) return;
 
-        const verifyResult = await verifyResponse.json();
+    try {
+        const response = await fetch(`/api/auth/wallets/${userId}`, { method: 'DELETE' });
+        const result = await response.json();
+        if (!result.success) throw new Error(result.message || 'Failed to unlink wallet');
+        renderLinkedWallets(result.wallets);
+    } catch (error) {
+        console.error('Unlink wallet error:', error);
+        alert(error.message);
+    }
+}
 
-        if (verifyResult.success) {
-            window.connectedWallet = {
-                address: address,
-                chain: chain,
-                walletType: walletType,
-                token: verifyResult.session_token
-            };
+async function loadLinkedWallets() {
+    try {
+        const response = await fetch('/api/auth/wallets');
+        if (!response.ok) return;
+        const result = await response.json();
+        if (result.success) renderLinkedWallets(result.wallets);
+    } catch (e) {
+        console.error('Failed to load linked wallets:', e);
+    }
+}
 
-            localStorage.setItem('connectedWallet', JSON.stringify(window.connectedWallet));
-            updateWalletUI(address, chain);
-            closeWalletModal();
-            statusEl.innerHTML = '<span class="text-green-400">Connected successfully!</span>';
+function renderLinkedWallets(wallets) {
+    const list = document.getElementById('linkedWalletsList');
+    if (!list) return;
+
+    const current = window.connectedWallet?.address?.toLowerCase();
+    list.innerHTML = '';
+    for (const wallet of wallets) {
+        const row = document.createElement('div');
+        row.className = 'flex items-center gap-2 px-2 py-1 text-xs text-primary-300';
+
+        const label = document.createElement('code');
+        const addr = wallet.wallet_address;
+        label.textContent = addr.length > 10 ? `${addr.slice(0, 6)}...${addr.slice(-4)}` : addr;
+        label.title = `${wallet.wallet_type}: ${addr}`;
+        row.appendChild(label);
+
+        if (addr.toLowerCase() === current) {
+            const you = document.createElement('span');
+            you.className = 'ml-auto text-primary-500';
+            you.textContent = 'current';
+            row.appendChild(you);
         } else {
-            statusEl.innerHTML = `<span class="text-red-500">${verifyResult.message || 'Authentication failed.'}</span>`;
+            const btn = document.createElement('button');
+            btn.className = 'ml-auto text-red-400 hover:text-red-300';
+            btn.textContent = 'Unlink';
+            btn.onclick = () => unlinkWallet(wallet.id);
+            row.appendChild(btn);
         }
-        */
-    } catch (error) {
-        console.error('Authentication error:', error);
-        statusEl.innerHTML = '<span class="text-red-500">Connection error. Please try again.</span>';
-        setTimeout(() => statusEl.classList.add('hidden'), 3000);
+        list.appendChild(row);
     }
 }
 
@@ -874,8 +948,8 @@ async function signMessageSol(message) {
     try {
         const encodedMessage = new TextEncoder().encode(message);
         const signedMessage = await window.solana.signMessage(encodedMessage, 'utf8');
-        // Convert to base58
-        return btoa(String.fromCharCode.apply(null, signedMessage.signature));
+        // Hex-encode; the server accepts base58 or hex
+        return Array.from(signedMessage.signature, b => b.toString(16).padStart(2, '0')).join('');
     } catch (error) {
         console.error('Signing error:', error);
         return null;
@@ -950,8 +1024,7 @@ async function connectWallet(chain, walletType) {
             // Store connection info
             window.connectedWallet = {
                 address: address,
-                chain: chain,
-                token: verifyResult.session_token
+                chain: chain
             };
 
             localStorage.setItem('connectedWallet', JSON.stringify(window.connectedWallet));
@@ -1007,19 +1080,34 @@ function updateWalletUI(address, chain) {
     if (headerBalancesLoading) headerBalancesLoading.classList.remove('hidden');
     if (headerBalancesContent) headerBalancesContent.classList.add('hidden');
 
-    // Show admin menu and badge if admin
+    // Fetch balances immediately
+    fetchAllBalances();
+
+    applyUserRole();
+    loadLinkedWallets();
+}
+
+// Show admin menu if the session belongs to an admin or writer, then notify listeners
+async function applyUserRole() {
     const adminMenu = document.getElementById('adminMenu');
     const leftSpacer = document.getElementById('leftSpacer');
+    const readersLink = document.getElementById('adminReadersLink');
+    const tagsLink = document.getElementById('adminTagsLink');
+
+    window.userType = await fetchUserType();
+    window.isAdmin = window.userType === 'admin';
+    window.canWrite = window.isAdmin || window.userType === 'writer';
+
+    if (readersLink) readersLink.classList.toggle('hidden', !window.isAdmin);
+    if (tagsLink) tagsLink.classList.toggle('hidden', !window.isAdmin);
 
-    if (isAdmin(address)) {
-        window.isAdmin = true;
+    if (window.canWrite) {
         if (adminMenu) {
             adminMenu.classList.remove('hidden');
             adminMenu.classList.add('flex');
         }
         if (leftSpacer) leftSpacer.classList.add('hidden');
     } else {
-        window.isAdmin = false;
         if (adminMenu) {
             adminMenu.classList.add('hidden');
             adminMenu.classList.remove('flex');
@@ -1027,9 +1115,6 @@ function updateWalletUI(address, chain) {
         if (leftSpacer) leftSpacer.classList.remove('hidden');
     }
 
-    // Fetch balances immediately
-    fetchAllBalances();
-
     // Dispatch event for other components
     window.dispatchEvent(new Event('walletConnected'));
 }
@@ -1041,8 +1126,17 @@ async function disconnectWallet() {
         await disconnectWalletConnect();
     }
 
+    // Revoke the server-side session
+    try {
+        await fetch('/api/auth/logout', { method: 'POST' });
+    } catch (e) {
+        console.error('Logout error:', e);
+    }
+
     window.connectedWallet = null;
     window.isAdmin = false;
+    window.canWrite = false;
+    window.userType = null;
     window.walletBalances = {};
     localStorage.removeItem('connectedWallet');
 